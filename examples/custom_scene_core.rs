@@ -82,7 +82,7 @@ mod my_scene {
                     shader::defs::V_WORLD_POS,
                     &format!("instance_transform * vec4({}, 1.0)", position),
                 )
-                .with_out(shader::defs::V_TEX_COORD, "position.xy + 0.5")
+                .with_out(shader::defs::V_TEX_COORD, "tex_coord")
                 .with_out(
                     shader::defs::V_POS,
                     "context_camera_projection * context_camera_view * v_world_pos",
@@ -108,11 +108,11 @@ struct Scene {
 struct Pipeline {
     rendology: rendology::Pipeline,
 
-    shadow_pass: Option<ShadowPass<basic_obj::Core>>,
+    shadow_pass: ShadowPass<basic_obj::Core>,
     scene_pass: ShadedScenePass<basic_obj::Core>,
     glowing_scene_pass: ShadedScenePass<basic_obj::Core>,
 
-    my_shadow_pass: Option<ShadowPass<my_scene::Core>>,
+    my_shadow_pass: ShadowPass<my_scene::Core>,
     my_scene_pass: ShadedScenePass<my_scene::Core>,
 
     cube: Mesh<basic_obj::Vertex>,
@@ -205,7 +205,13 @@ impl Pipeline {
         };
 
         self.rendology
-            .start_frame(facade, (0.0, 0.0, 0.0), context.clone(), target)?
+            .start_frame(
+                facade,
+                (0.0, 0.0, 0.0),
+                context.clone(),
+                &scene.lights,
+                target,
+            )?
             .shadow_pass()
             .draw(
                 &self.shadow_pass,
@@ -238,7 +244,7 @@ impl Pipeline {
                 &my_params,
                 &Default::default(),
             )?
-            .compose(&scene.lights)?
+            .compose()?
             .postprocess()?
             .present()
     }
@@ -260,6 +266,7 @@ fn main() {
     // Initialize rendology pipeline
     let deferred_config = rendology::pipeline::deferred::Config {
         light_min_threshold: 0.0001,
+        ..Default::default()
     };
     let mut pipeline_config = rendology::Config {
         hdr: Some(1.0),
@@ -341,6 +348,7 @@ fn scene(time: f32) -> Scene {
     scene.cubes.add(basic_obj::Instance {
         transform: na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(10.0, 10.0, 0.1)),
         color: na::Vector4::new(0.9, 0.9, 0.9, 1.0),
+        ..Default::default()
     });
 
     let n = 10;
@@ -360,6 +368,7 @@ fn scene(time: f32) -> Scene {
         scene.glowing_cubes.add(basic_obj::Instance {
             transform: orbit_transform,
             color: color / 2.0,
+            ..Default::default()
         });
 
         scene.lights.push(Light {
@@ -409,5 +418,6 @@ fn render_context(target_size: (u32, u32)) -> rendology::Context {
         main_light_pos: na::Point3::new(1.0, 1.0, 10.0),
         main_light_center: na::Point3::new(0.0, 0.0, 0.0),
         ambient_light: na::Vector3::new(0.01, 0.01, 0.01),
+        previous_camera: None,
     }
 }
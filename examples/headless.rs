@@ -0,0 +1,117 @@
+use nalgebra as na;
+
+use rendology::headless::Headless;
+use rendology::{basic_obj, BasicObj, InstancingMode, Light, ShadedScenePassSetup};
+
+const IMAGE_SIZE: (u32, u32) = (1280, 720);
+
+fn main() {
+    simple_logger::init_with_level(log::Level::Info).unwrap();
+
+    // Initialize a context without a window
+    let headless = Headless::create().unwrap();
+
+    // Initialize rendology pipeline
+    let mut pipeline =
+        rendology::Pipeline::create(&headless, &Default::default(), IMAGE_SIZE).unwrap();
+    let shadow_pass = pipeline
+        .create_shadow_pass(&headless, basic_obj::Core, InstancingMode::Uniforms)
+        .unwrap();
+    let scene_pass = pipeline
+        .create_shaded_scene_pass(
+            &headless,
+            basic_obj::Core,
+            InstancingMode::Uniforms,
+            ShadedScenePassSetup {
+                draw_shadowed: true,
+                draw_glowing: false,
+            },
+        )
+        .unwrap();
+    let resources = basic_obj::Resources::create(&headless).unwrap();
+
+    let mut objects = basic_obj::RenderList::<basic_obj::Instance>::default();
+    objects[BasicObj::Cube].add(basic_obj::Instance {
+        transform: na::Matrix4::new_translation(&na::Vector3::new(0.0, 0.0, 3.0))
+            * na::Matrix4::from_euler_angles(0.5, 0.5, 0.5),
+        color: na::Vector4::new(0.9, 0.9, 0.9, 1.0),
+        ..Default::default()
+    });
+    objects[BasicObj::Cube].add(basic_obj::Instance {
+        transform: na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(10.0, 10.0, 0.1)),
+        color: na::Vector4::new(0.0, 1.0, 0.0, 1.0),
+        ..Default::default()
+    });
+
+    let lights = vec![Light {
+        position: na::Point3::new(10.0, 10.0, 10.0),
+        attenuation: na::Vector4::new(1.0, 0.0, 0.0, 0.0),
+        color: na::Vector3::new(1.0, 1.0, 1.0),
+        is_main: true,
+        ..Default::default()
+    }];
+
+    let draw_params = glium::DrawParameters {
+        backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
+        ..Default::default()
+    };
+
+    // Draw a single frame and read it back
+    let image = headless
+        .render(IMAGE_SIZE, |target| {
+            pipeline
+                .start_frame(
+                    &headless,
+                    (0.0, 0.0, 0.0),
+                    render_context(),
+                    &lights,
+                    target,
+                )?
+                .shadow_pass()
+                .draw(
+                    &shadow_pass,
+                    &objects.as_drawable(&resources),
+                    &(),
+                    &draw_params,
+                )?
+                .shaded_scene_pass()
+                .draw(
+                    &scene_pass,
+                    &objects.as_drawable(&resources),
+                    &(),
+                    &draw_params,
+                )?
+                .compose()?
+                .postprocess()?
+                .present()
+        })
+        .unwrap();
+
+    image.to_rgba8().write_png("headless.png").unwrap();
+}
+
+fn render_context() -> rendology::Context {
+    let camera = rendology::Camera {
+        view: na::Matrix4::look_at_rh(
+            &na::Point3::new(9.0, -5.0, 7.0),
+            &na::Point3::new(0.0, 0.0, 0.0),
+            &na::Vector3::new(0.0, 0.0, 1.0),
+        ),
+        projection: na::Perspective3::new(
+            IMAGE_SIZE.0 as f32 / IMAGE_SIZE.1 as f32,
+            60.0f32.to_radians(),
+            0.1,
+            1000.0,
+        )
+        .to_homogeneous(),
+        viewport_size: na::Vector2::new(IMAGE_SIZE.0 as f32, IMAGE_SIZE.1 as f32),
+    };
+
+    rendology::Context {
+        camera,
+        main_light_pos: na::Point3::new(10.0, 10.0, 10.0),
+        main_light_center: na::Point3::new(0.0, 0.0, 0.0),
+        ambient_light: na::Vector3::new(0.3, 0.3, 0.3),
+        previous_camera: None,
+    }
+}
@@ -30,7 +30,7 @@ struct Scene {
 struct Pipeline {
     rendology: rendology::Pipeline,
 
-    shadow_pass: Option<ShadowPass<basic_obj::Core>>,
+    shadow_pass: ShadowPass<basic_obj::Core>,
     scene_pass: ShadedScenePass<basic_obj::Core>,
     line_pass: PlainScenePass<line::Core>,
 
@@ -93,7 +93,13 @@ impl Pipeline {
         let line_params = line::Params { feather: 0.1 };
 
         self.rendology
-            .start_frame(facade, (1.0, 1.0, 1.0), context.clone(), target)?
+            .start_frame(
+                facade,
+                (1.0, 1.0, 1.0),
+                context.clone(),
+                &scene.lights,
+                target,
+            )?
             .shadow_pass()
             .draw(
                 &self.shadow_pass,
@@ -108,7 +114,7 @@ impl Pipeline {
                 &(),
                 &draw_params,
             )?
-            .compose(&scene.lights)?
+            .compose()?
             .postprocess()?
             .plain_scene_pass()
             .draw(
@@ -173,11 +179,13 @@ fn scene(time: f32) -> Scene {
         transform: na::Matrix4::new_translation(&na::Vector3::new(0.0, 0.0, 3.0))
             * na::Matrix4::from_euler_angles(time, time, time),
         color: na::Vector4::new(0.9, 0.9, 0.9, 1.0),
+        ..Default::default()
     });
 
     scene.cubes.add(basic_obj::Instance {
         transform: na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(10.0, 10.0, 0.1)),
         color: na::Vector4::new(0.0, 1.0, 0.0, 1.0),
+        ..Default::default()
     });
 
     scene.lights.push(Light {
@@ -220,5 +228,6 @@ fn render_context(target_size: (u32, u32)) -> rendology::Context {
         main_light_pos: na::Point3::new(10.0, 10.0, 10.0),
         main_light_center: na::Point3::new(0.0, 0.0, 0.0),
         ambient_light: na::Vector3::new(0.3, 0.3, 0.3),
+        previous_camera: None,
     }
 }
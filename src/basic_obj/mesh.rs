@@ -7,20 +7,37 @@ pub fn mesh_from_slices<F: glium::backend::Facade>(
     primitive_type: glium::index::PrimitiveType,
     positions: &[[f32; 3]],
     normals: &[[f32; 3]],
+    tex_coords: &[[f32; 2]],
     indices: &[u32],
 ) -> Result<Mesh<Vertex>, CreationError> {
     let vertices = positions
         .iter()
         .zip(normals.iter())
-        .map(|(&p, &n)| Vertex {
+        .zip(tex_coords.iter())
+        .map(|((&p, &n), &t)| Vertex {
             position: p,
             normal: n,
+            tex_coord: t,
         })
         .collect::<Vec<_>>();
 
     Mesh::create_with_indices(facade, primitive_type, &vertices, indices)
 }
 
+/// Texture coordinates for a point on the surface of the unit cube, projected
+/// along the axis of the given face normal.
+pub fn cube_tex_coord(position: [f32; 3], normal: [f32; 3]) -> [f32; 2] {
+    let [x, y, z] = position;
+
+    if normal[0] != 0.0 {
+        [y + 0.5, z + 0.5]
+    } else if normal[1] != 0.0 {
+        [x + 0.5, z + 0.5]
+    } else {
+        [x + 0.5, y + 0.5]
+    }
+}
+
 #[rustfmt::skip]
 pub fn create_mesh<F: glium::backend::Facade>(
     object: BasicObj,
@@ -40,6 +57,12 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 [0.0, 0.0, -1.0],
             ];
 
+            let tex_coords = vec![
+                [0.0, 0.0],
+                [0.0, 1.0],
+                [1.0, 0.5],
+            ];
+
             let indices = vec![0, 1, 2];
 
             mesh_from_slices(
@@ -47,6 +70,7 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 glium::index::PrimitiveType::TrianglesList,
                 &positions,
                 &normals,
+                &tex_coords,
                 &indices,
             )
         }
@@ -65,6 +89,13 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 [0.0, 0.0, 1.0],
             ];
 
+            let tex_coords = vec![
+                [0.0, 0.0],
+                [1.0, 0.0],
+                [1.0, 1.0],
+                [0.0, 1.0],
+            ];
+
             let indices = vec![
                 0, 1, 2,
                 2, 3, 0,
@@ -75,15 +106,23 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 glium::index::PrimitiveType::TrianglesList,
                 &positions,
                 &normals,
+                &tex_coords,
                 &indices,
             )
         }
         BasicObj::Cube => {
+            let tex_coords = CUBE_POSITIONS
+                .iter()
+                .zip(CUBE_NORMALS.iter())
+                .map(|(&position, &normal)| cube_tex_coord(position, normal))
+                .collect::<Vec<_>>();
+
             mesh_from_slices(
                 facade,
                 glium::index::PrimitiveType::TrianglesList,
                 CUBE_POSITIONS,
                 CUBE_NORMALS,
+                &tex_coords,
                 CUBE_INDICES,
             )
         }
@@ -92,6 +131,7 @@ pub fn create_mesh<F: glium::backend::Facade>(
 
             let mut positions = Vec::new();
             let mut normals = Vec::new();
+            let mut tex_coords = Vec::new();
             let mut indices = Vec::new();
 
             let radius = 0.5;
@@ -115,6 +155,10 @@ pub fn create_mesh<F: glium::backend::Facade>(
 
                     positions.push([x * radius, y * radius, z * radius]);
                     normals.push([x, y, z]);
+                    tex_coords.push([
+                        j as f32 / num_sectors as f32,
+                        1.0 - i as f32 / num_stacks as f32,
+                    ]);
                 }
             }
 
@@ -145,6 +189,7 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 glium::index::PrimitiveType::TrianglesList,
                 &positions,
                 &normals,
+                &tex_coords,
                 &indices,
             )
         }
@@ -154,6 +199,7 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 glium::index::PrimitiveType::LinesList,
                 &[[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]],
                 &[[0.0, 0.0, 1.0], [0.0, 0.0, 1.0]],
+                &[[0.0, 0.0], [1.0, 0.0]],
                 &[0, 1],
             )
         }
@@ -163,6 +209,7 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 glium::index::PrimitiveType::LinesList,
                 &[[0.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
                 &[[0.0, 0.0, 1.0], [0.0, 0.0, 1.0]],
+                &[[0.0, 0.0], [1.0, 0.0]],
                 &[0, 1],
             )
         }
@@ -172,12 +219,14 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 glium::index::PrimitiveType::LinesList,
                 &[[0.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
                 &[[1.0, 0.0, 0.0], [1.0, 0.0, 0.0]],
+                &[[0.0, 0.0], [1.0, 0.0]],
                 &[0, 1],
             )
         }
         BasicObj::TessellatedCube => {
             let mut positions = Vec::new();
             let mut normals = Vec::new();
+            let mut tex_coords = Vec::new();
             let mut indices = Vec::new();
 
             // Number of subdivisions along the x axis
@@ -188,8 +237,11 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 let x_offset = i as f32 / n as f32 - 0.5;
 
                 for (&position, &normal) in CUBE_POSITIONS.iter().zip(CUBE_NORMALS.iter()) {
-                    positions.push([x_offset + position[0] / n as f32, position[1], position[2]]);
+                    let position = [x_offset + position[0] / n as f32, position[1], position[2]];
+
+                    positions.push(position);
                     normals.push(normal);
+                    tex_coords.push(cube_tex_coord(position, normal));
                 }
 
                 for &index in CUBE_INDICES {
@@ -202,12 +254,14 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 glium::index::PrimitiveType::TrianglesList,
                 &positions,
                 &normals,
+                &tex_coords,
                 &indices,
             )
         }
         BasicObj::TessellatedCylinder => {
             let mut positions = Vec::new();
             let mut normals = Vec::new();
+            let mut tex_coords = Vec::new();
             let mut indices = Vec::new();
 
             // Number of subdivisions along the x axis
@@ -216,12 +270,14 @@ pub fn create_mesh<F: glium::backend::Facade>(
             // Number of subdivisions along the angle
             let m = 8;
 
-            // Add positions and normals
+            // Add positions, normals and texture coordinates. Each slice gets
+            // one more vertex than there are stripes, so that the texture
+            // coordinates do not wrap around at the seam.
             for i in 0..=n {
                 let x = i as f32 / n as f32 - 0.5;
 
                 // Add one x-slice of the cylinder
-                for j in 0..m {
+                for j in 0..=m {
                     // Add one stripe of the cylinder
                     let theta = j as f32 / m as f32 * 2.0 * std::f32::consts::PI;
 
@@ -229,19 +285,21 @@ pub fn create_mesh<F: glium::backend::Facade>(
                     let z = theta.cos();
                     positions.push([x, y, z]);
                     normals.push([0.0, y, z]);
+                    tex_coords.push([x + 0.5, j as f32 / m as f32]);
                 }
             }
 
             // Add triangles
+            let k = m + 1;
             for i in 0..n {
                 for j in 0..m {
-                    indices.push((i + 0) * m + ((j + 0) % m));
-                    indices.push((i + 1) * m + ((j + 0) % m));
-                    indices.push((i + 0) * m + ((j + 1) % m));
+                    indices.push(i * k + j);
+                    indices.push((i + 1) * k + j);
+                    indices.push(i * k + j + 1);
 
-                    indices.push((i + 0) * m + ((j + 1) % m));
-                    indices.push((i + 1) * m + ((j + 0) % m));
-                    indices.push((i + 1) * m + ((j + 1) % m));
+                    indices.push(i * k + j + 1);
+                    indices.push((i + 1) * k + j);
+                    indices.push((i + 1) * k + j + 1);
                 }
             }
 
@@ -250,6 +308,7 @@ pub fn create_mesh<F: glium::backend::Facade>(
                 glium::index::PrimitiveType::TrianglesList,
                 &positions,
                 &normals,
+                &tex_coords,
                 &indices,
             )
         }
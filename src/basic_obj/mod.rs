@@ -9,8 +9,8 @@ use num_traits::{FromPrimitive, ToPrimitive};
 use crate::shader::{InstanceInput, InstancingMode, ToUniforms};
 use crate::{CreationError, DrawError, Drawable, Mesh};
 
-pub use mesh::{mesh_from_slices, CUBE_INDICES, CUBE_NORMALS, CUBE_POSITIONS};
-pub use scene::{Core, Instance};
+pub use mesh::{cube_tex_coord, mesh_from_slices, CUBE_INDICES, CUBE_NORMALS, CUBE_POSITIONS};
+pub use scene::{Core, Instance, TexturedCore, TexturedInstance, TexturedParams};
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug, num_derive::FromPrimitive, num_derive::ToPrimitive)]
 pub enum BasicObj {
@@ -33,9 +33,10 @@ pub const NUM_TYPES: usize = 9;
 pub struct Vertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
+    pub tex_coord: [f32; 2],
 }
 
-implement_vertex!(Vertex, position, normal);
+implement_vertex!(Vertex, position, normal, tex_coord);
 
 pub struct Resources {
     pub meshes: Vec<Mesh<Vertex>>,
@@ -7,6 +7,14 @@ use crate::{basic_obj, shader, Context};
 pub struct Instance {
     pub transform: na::Matrix4<f32>,
     pub color: na::Vector4<f32>,
+
+    /// Transform of the instance in the previous frame, used for motion
+    /// vectors. If `None`, the instance is assumed not to have moved.
+    pub previous_transform: Option<na::Matrix4<f32>>,
+
+    /// ID that is written into the object ID buffer, for picking. IDs start
+    /// at 1, since 0 means that there is no object.
+    pub object_id: u32,
 }
 
 impl Default for Instance {
@@ -14,6 +22,8 @@ impl Default for Instance {
         Self {
             transform: na::Matrix4::identity(),
             color: na::Vector4::new(1.0, 1.0, 1.0, 1.0),
+            previous_transform: None,
+            object_id: 0,
         }
     }
 }
@@ -23,6 +33,9 @@ impl_instance_input!(
     self => {
         instance_transform: [[f32; 4]; 4] = self.transform,
         instance_color: [f32; 4] = self.color,
+        instance_previous_transform: [[f32; 4]; 4] =
+            self.previous_transform.unwrap_or(self.transform),
+        instance_object_id: u32 = self.object_id,
     },
 );
 
@@ -46,7 +59,13 @@ impl SceneCore for Core {
                 shader::defs::V_WORLD_POS,
                 "instance_transform * vec4(position, 1.0)",
             )
+            .with_out(
+                shader::defs::V_PREVIOUS_WORLD_POS,
+                "instance_previous_transform * vec4(position, 1.0)",
+            )
+            .with_out(shader::defs::V_OBJECT_ID, "instance_object_id")
             .with_out(shader::defs::V_COLOR, "instance_color")
+            .with_out(shader::defs::V_TEX_COORD, "tex_coord")
             .with_out(
                 shader::defs::V_POS,
                 "context_camera_projection * context_camera_view * v_world_pos",
@@ -59,3 +78,94 @@ impl SceneCore for Core {
         shader::Core { vertex, fragment }
     }
 }
+
+/// Instance data for `TexturedCore`.
+#[derive(Clone, Debug)]
+pub struct TexturedInstance {
+    pub transform: na::Matrix4<f32>,
+
+    /// Color that the texture is multiplied with.
+    pub color: na::Vector4<f32>,
+
+    /// Layer of the texture array to sample from.
+    pub texture_layer: f32,
+}
+
+impl Default for TexturedInstance {
+    fn default() -> Self {
+        Self {
+            transform: na::Matrix4::identity(),
+            color: na::Vector4::new(1.0, 1.0, 1.0, 1.0),
+            texture_layer: 0.0,
+        }
+    }
+}
+
+impl_instance_input!(
+    TexturedInstance,
+    self => {
+        instance_transform: [[f32; 4]; 4] = self.transform,
+        instance_color: [f32; 4] = self.color,
+        instance_texture_layer: f32 = self.texture_layer,
+    },
+);
+
+#[derive(Clone)]
+pub struct TexturedParams<'a> {
+    /// Textures to choose from with `TexturedInstance::texture_layer`. A
+    /// single texture can be used as an array with one layer.
+    pub textures: &'a glium::texture::SrgbTexture2dArray,
+}
+
+impl_uniform_input!(
+    TexturedParams<'a>,
+    self => {
+        textures: &'a glium::texture::SrgbTexture2dArray = self.textures,
+    },
+);
+
+const V_TEXTURE_LAYER: (&str, shader::VertexOutDef) = (
+    "v_texture_layer",
+    shader::VertexOutDef(shader::Type::Float, shader::VertexOutQualifier::Flat),
+);
+
+/// Scene core for rendering `BasicObj` meshes with a texture per instance.
+pub struct TexturedCore;
+
+impl CoreInput for TexturedCore {
+    type Params = TexturedParams<'static>;
+    type Instance = TexturedInstance;
+    type Vertex = basic_obj::Vertex;
+}
+
+impl SceneCore for TexturedCore {
+    fn scene_core(&self) -> shader::Core<(Context, Self::Params), Self::Instance, Self::Vertex> {
+        let vertex = shader::VertexCore::empty()
+            .with_out(
+                shader::defs::V_WORLD_NORMAL,
+                "normalize(transpose(inverse(mat3(instance_transform))) * normal)",
+            )
+            .with_out(
+                shader::defs::V_WORLD_POS,
+                "instance_transform * vec4(position, 1.0)",
+            )
+            .with_out(shader::defs::V_COLOR, "instance_color")
+            .with_out(shader::defs::V_TEX_COORD, "tex_coord")
+            .with_out(V_TEXTURE_LAYER, "instance_texture_layer")
+            .with_out(
+                shader::defs::V_POS,
+                "context_camera_projection * context_camera_view * v_world_pos",
+            );
+
+        let fragment = shader::FragmentCore::empty()
+            .with_in_def(shader::defs::V_COLOR)
+            .with_in_def(shader::defs::V_TEX_COORD)
+            .with_in_def(V_TEXTURE_LAYER)
+            .with_out(
+                shader::defs::F_COLOR,
+                "v_color * texture(textures, vec3(v_tex_coord, v_texture_layer))",
+            );
+
+        shader::Core { vertex, fragment }
+    }
+}
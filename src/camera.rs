@@ -25,6 +25,21 @@ impl Camera {
         }
     }
 
+    /// Returns the distances of the near and far plane, as encoded in the
+    /// projection matrix. Supports perspective and orthographic projections.
+    pub fn depth_range(&self) -> (f32, f32) {
+        let m22 = self.projection[(2, 2)];
+        let m23 = self.projection[(2, 3)];
+
+        if self.projection[(3, 3)] == 1.0 {
+            // Orthographic projection
+            ((m23 + 1.0) / m22, (m23 - 1.0) / m22)
+        } else {
+            // Perspective projection
+            (m23 / (m22 - 1.0), m23 / (m22 + 1.0))
+        }
+    }
+
     pub fn project_to_viewport(&self, p: &na::Point3<f32>) -> na::Point3<f32> {
         let q = self.projection * self.view * na::Vector4::new(p.x, p.y, p.z, 1.0);
         let h = q.fixed_rows::<na::U3>(0) / q.w;
@@ -8,6 +8,10 @@ pub enum CreationError {
     VertexBuffer(glium::vertex::BufferCreationError),
     IndexBuffer(glium::index::BufferCreationError),
     IO(std::io::Error),
+
+    /// The configuration is invalid, e.g. because it was deserialized from a
+    /// file that has been edited by hand.
+    InvalidConfig(String),
 }
 
 impl From<shader::BuildError> for CreationError {
@@ -10,6 +10,7 @@ use crate::{shader, DrawError, ScreenQuad};
 pub use crate::CreationError;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Quality {
     Low,
     Medium,
@@ -26,7 +27,12 @@ impl Quality {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Config {
     pub quality: Quality,
 }
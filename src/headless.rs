@@ -0,0 +1,217 @@
+//! Rendering without a window, e.g. for generating images on build servers.
+//!
+//! This creates an OpenGL context with EGL on the surfaceless platform of
+//! Mesa, which does not need a display server or a GPU. Without a GPU, Mesa
+//! falls back to its `llvmpipe` software rasterizer. Setting the environment
+//! variable `LIBGL_ALWAYS_SOFTWARE=1` forces the software rasterizer.
+//!
+//! The context has no default framebuffer, so frames are drawn into an owned
+//! texture with `Headless::render`.
+
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::rc::Rc;
+
+use glutin_egl_sys::egl;
+use log::info;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{MipmapsOption, Texture2d, UncompressedFloatFormat};
+
+use crate::pipeline::capture::{self, Image};
+use crate::DrawError;
+
+/// `EGL_PLATFORM_SURFACELESS_MESA` from `EGL_MESA_platform_surfaceless`.
+const PLATFORM_SURFACELESS_MESA: egl::types::EGLenum = 0x31DD;
+
+#[derive(Debug)]
+pub enum CreationError {
+    LoadLibrary(libloading::Error),
+
+    /// An EGL call failed. Contains the name of the call and the EGL error
+    /// code.
+    Egl(&'static str, egl::types::EGLint),
+
+    IncompatibleOpenGl(glium::IncompatibleOpenGl),
+}
+
+impl From<libloading::Error> for CreationError {
+    fn from(err: libloading::Error) -> CreationError {
+        CreationError::LoadLibrary(err)
+    }
+}
+
+impl From<glium::IncompatibleOpenGl> for CreationError {
+    fn from(err: glium::IncompatibleOpenGl) -> CreationError {
+        CreationError::IncompatibleOpenGl(err)
+    }
+}
+
+struct Backend {
+    egl: egl::Egl,
+    display: egl::types::EGLDisplay,
+    context: egl::types::EGLContext,
+
+    // Needs to be kept alive for the function pointers in `egl`.
+    _library: libloading::Library,
+}
+
+unsafe impl glium::backend::Backend for Backend {
+    fn swap_buffers(&self) -> Result<(), glium::SwapBuffersError> {
+        Ok(())
+    }
+
+    unsafe fn get_proc_address(&self, symbol: &str) -> *const c_void {
+        let symbol = CString::new(symbol).unwrap();
+        self.egl.GetProcAddress(symbol.as_ptr()) as *const c_void
+    }
+
+    fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+        // There is no default framebuffer.
+        (1, 1)
+    }
+
+    fn is_current(&self) -> bool {
+        unsafe { self.egl.GetCurrentContext() == self.context }
+    }
+
+    unsafe fn make_current(&self) {
+        self.egl
+            .MakeCurrent(self.display, egl::NO_SURFACE, egl::NO_SURFACE, self.context);
+    }
+}
+
+impl Drop for Backend {
+    fn drop(&mut self) {
+        // The display is shared by all contexts of the process, so we must
+        // not terminate it here.
+        unsafe {
+            self.egl.MakeCurrent(
+                self.display,
+                egl::NO_SURFACE,
+                egl::NO_SURFACE,
+                egl::NO_CONTEXT,
+            );
+            self.egl.DestroyContext(self.display, self.context);
+        }
+    }
+}
+
+/// A headless OpenGL 3.3 core context, which can be used as a `Facade`.
+pub struct Headless {
+    context: Rc<glium::backend::Context>,
+}
+
+impl Headless {
+    /// Creates a headless context. This needs `libEGL.so.1` with support for
+    /// EGL 1.5 and `EGL_MESA_platform_surfaceless`.
+    pub fn create() -> Result<Self, CreationError> {
+        let library = unsafe { libloading::Library::new("libEGL.so.1")? };
+        let egl = egl::Egl::load_with(|symbol| {
+            let symbol = CString::new(symbol).unwrap();
+            unsafe {
+                library
+                    .get::<*const c_void>(symbol.as_bytes_with_nul())
+                    .map_or(std::ptr::null(), |pointer| *pointer)
+            }
+        });
+
+        let error = |call| CreationError::Egl(call, unsafe { egl.GetError() });
+        // If creation fails after initializing the display, we terminate it
+        // again.
+        let terminate = |display, error| {
+            unsafe {
+                egl.Terminate(display);
+            }
+            error
+        };
+
+        let display = unsafe {
+            egl.GetPlatformDisplay(
+                PLATFORM_SURFACELESS_MESA,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+            )
+        };
+        if display == egl::NO_DISPLAY {
+            return Err(error("eglGetPlatformDisplay"));
+        }
+
+        let (mut major, mut minor) = (0, 0);
+        if unsafe { egl.Initialize(display, &mut major, &mut minor) } == 0 {
+            return Err(error("eglInitialize"));
+        }
+        info!("Initialized EGL {}.{}", major, minor);
+
+        if unsafe { egl.BindAPI(egl::OPENGL_API) } == 0 {
+            return Err(terminate(display, error("eglBindAPI")));
+        }
+
+        let attributes = [
+            egl::CONTEXT_MAJOR_VERSION as egl::types::EGLint,
+            3,
+            egl::CONTEXT_MINOR_VERSION as egl::types::EGLint,
+            3,
+            egl::CONTEXT_OPENGL_PROFILE_MASK as egl::types::EGLint,
+            egl::CONTEXT_OPENGL_CORE_PROFILE_BIT as egl::types::EGLint,
+            egl::NONE as egl::types::EGLint,
+        ];
+        let context = unsafe {
+            egl.CreateContext(
+                display,
+                std::ptr::null(),
+                egl::NO_CONTEXT,
+                attributes.as_ptr(),
+            )
+        };
+        if context == egl::NO_CONTEXT {
+            return Err(terminate(display, error("eglCreateContext")));
+        }
+
+        let backend = Backend {
+            egl,
+            display,
+            context,
+            _library: library,
+        };
+        let context = unsafe { glium::backend::Context::new(backend, true, Default::default())? };
+
+        info!(
+            "Created headless context: {} {}",
+            context.get_opengl_vendor_string(),
+            context.get_opengl_renderer_string()
+        );
+
+        Ok(Headless { context })
+    }
+
+    /// Renders into a new texture of the given size and reads back the
+    /// result. The `draw` function is given the framebuffer to draw into,
+    /// e.g. as the target of `Pipeline::start_frame`.
+    pub fn render<G>(&self, size: (u32, u32), draw: G) -> Result<Image<[f32; 4]>, DrawError>
+    where
+        G: FnOnce(&mut SimpleFrameBuffer<'_>) -> Result<(), DrawError>,
+    {
+        let texture = Texture2d::empty_with_format(
+            self,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )
+        .map_err(crate::CreationError::from)?;
+
+        {
+            let mut framebuffer = SimpleFrameBuffer::new(self, &texture)?;
+            draw(&mut framebuffer)?;
+        }
+
+        Ok(capture::read_texture(&texture))
+    }
+}
+
+impl glium::backend::Facade for Headless {
+    fn get_context(&self) -> &Rc<glium::backend::Context> {
+        &self.context
+    }
+}
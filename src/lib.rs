@@ -13,10 +13,15 @@ mod stage;
 pub mod basic_obj;
 pub mod dither;
 pub mod fxaa;
+#[cfg(feature = "headless")]
+pub mod headless;
 pub mod line;
+pub mod material;
+pub mod normal_map;
 pub mod particle;
 pub mod pipeline;
 pub mod screen_quad;
+pub mod smaa;
 
 pub use basic_obj::BasicObj;
 pub use camera::Camera;
@@ -31,4 +36,4 @@ pub use render_list::RenderList;
 pub use scene::{CoreInput, SceneCore};
 pub use screen_quad::ScreenQuad;
 pub use shader::InstancingMode;
-pub use stage::{Context, Light};
+pub use stage::{Context, Light, LightKind};
@@ -0,0 +1,372 @@
+//! Materials that describe the surface of objects independently of scene
+//! cores.
+//!
+//! A `Material` is turned into uniforms with `Params`. Any scene core can be
+//! wrapped in `material::Core`, which takes the color, emission, specular
+//! and physically based parameters of fragments from these uniforms. Since
+//! the parameters are uniforms, objects with different materials can share
+//! the same shader program. The material of each draw call is set by
+//! drawing through a `material::RenderList`, which groups instances by
+//! material.
+
+use std::rc::Rc;
+
+use glium::texture::SrgbTexture2d;
+use glium::uniforms::{Sampler, SamplerWrapFunction};
+use nalgebra as na;
+
+use crate::scene::{CoreInput, SceneCore};
+use crate::shader::{InstanceInput, InstancingMode, ToUniforms, UniformInput};
+use crate::{shader, Context, CreationError, DrawError, Drawable, Mesh};
+
+/// Determines how the alpha channel of a material's base color is
+/// interpreted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored and the object is fully opaque.
+    Opaque,
+
+    /// Fragments with alpha below the cutoff are discarded, while all other
+    /// fragments are fully opaque.
+    Mask { cutoff: f32 },
+
+    /// Alpha blending is enabled. Note that blending does not work with
+    /// deferred shading. Draw such objects in the transparent scene pass, or
+    /// use `AlphaMode::Mask` or `dither::Core` instead.
+    Blend,
+}
+
+#[derive(Debug, Clone)]
+pub struct Material {
+    /// Base color, multiplied with the color given by the wrapped scene core.
+    pub base_color: na::Vector4<f32>,
+
+    /// Texture that is multiplied with the base color.
+    pub base_color_texture: Option<Rc<SrgbTexture2d>>,
+
+    /// Light emitted by the surface.
+    pub emissive: na::Vector3<f32>,
+
+    /// Texture that is multiplied with the emitted light.
+    pub emissive_texture: Option<Rc<SrgbTexture2d>>,
+
+    /// Specular intensity for Blinn-Phong shading.
+    pub specular: f32,
+
+    /// Blinn-Phong exponent.
+    pub shininess: f32,
+
+    /// Metalness for physically based shading.
+    pub metallic: f32,
+
+    /// Roughness for physically based shading.
+    pub roughness: f32,
+
+    pub alpha_mode: AlphaMode,
+
+    /// If true, backface culling is disabled for this material, and normals
+    /// of back faces are flipped.
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: na::Vector4::new(1.0, 1.0, 1.0, 1.0),
+            base_color_texture: None,
+            emissive: na::Vector3::zeros(),
+            emissive_texture: None,
+            specular: 0.0,
+            shininess: 1.0,
+            metallic: 0.0,
+            roughness: 0.8,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+impl PartialEq for Material {
+    fn eq(&self, other: &Self) -> bool {
+        fn same_texture(a: &Option<Rc<SrgbTexture2d>>, b: &Option<Rc<SrgbTexture2d>>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+
+        self.base_color == other.base_color
+            && same_texture(&self.base_color_texture, &other.base_color_texture)
+            && self.emissive == other.emissive
+            && same_texture(&self.emissive_texture, &other.emissive_texture)
+            && self.specular == other.specular
+            && self.shininess == other.shininess
+            && self.metallic == other.metallic
+            && self.roughness == other.roughness
+            && self.alpha_mode == other.alpha_mode
+            && self.double_sided == other.double_sided
+    }
+}
+
+impl Material {
+    /// Adjusts the given draw parameters for drawing with this material.
+    ///
+    /// Alpha blending is only enabled if `draw_params` does not already
+    /// specify blending, such as in the transparent scene pass.
+    pub fn draw_parameters<'a>(
+        &self,
+        draw_params: &glium::DrawParameters<'a>,
+    ) -> glium::DrawParameters<'a> {
+        let mut draw_params = draw_params.clone();
+
+        if self.double_sided {
+            draw_params.backface_culling =
+                glium::draw_parameters::BackfaceCullingMode::CullingDisabled;
+        }
+
+        if self.alpha_mode == AlphaMode::Blend && draw_params.blend == Default::default() {
+            draw_params.blend = glium::Blend::alpha_blending();
+        }
+
+        draw_params
+    }
+
+    pub fn params<'a>(&'a self, resources: &'a Resources) -> Params<'a> {
+        Params {
+            material: self,
+            resources,
+        }
+    }
+}
+
+/// Shared resources for drawing materials.
+pub struct Resources {
+    /// Used in place of textures that are not given by a material.
+    pub white_texture: SrgbTexture2d,
+}
+
+impl Resources {
+    pub fn create<F: glium::backend::Facade>(facade: &F) -> Result<Self, CreationError> {
+        let white_texture = SrgbTexture2d::new(facade, vec![vec![(255u8, 255u8, 255u8, 255u8)]])?;
+
+        Ok(Self { white_texture })
+    }
+}
+
+#[derive(Clone)]
+pub struct Params<'a> {
+    pub material: &'a Material,
+    pub resources: &'a Resources,
+}
+
+impl<'a> Params<'a> {
+    fn texture(&self, texture: &'a Option<Rc<SrgbTexture2d>>) -> Sampler<'a, SrgbTexture2d> {
+        texture
+            .as_ref()
+            .map_or(&self.resources.white_texture, |texture| texture)
+            .sampled()
+            .wrap_function(SamplerWrapFunction::Repeat)
+    }
+
+    fn alpha_cutoff(&self) -> f32 {
+        match self.material.alpha_mode {
+            AlphaMode::Mask { cutoff } => cutoff,
+            _ => 0.0,
+        }
+    }
+}
+
+impl_uniform_input!(
+    Params<'a>,
+    self => {
+        material_base_color: [f32; 4] = self.material.base_color.into(),
+        material_base_color_texture: Sampler<'a, SrgbTexture2d> =
+            self.texture(&self.material.base_color_texture),
+        material_emissive: [f32; 3] = self.material.emissive.into(),
+        material_emissive_texture: Sampler<'a, SrgbTexture2d> =
+            self.texture(&self.material.emissive_texture),
+        material_specular: [f32; 2] = [self.material.specular, self.material.shininess],
+        material_metallic_roughness: [f32; 2] =
+            [self.material.metallic, self.material.roughness],
+        material_alpha_cutoff: f32 = self.alpha_cutoff(),
+        material_opaque: bool = self.material.alpha_mode != AlphaMode::Blend,
+        material_double_sided: bool = self.material.double_sided,
+    },
+);
+
+/// Shader core transform for taking the surface parameters of fragments from
+/// a `Material`. The uniforms of `Params` are added to the fragment core.
+///
+/// The base color is multiplied with the `F_COLOR` output of the given core,
+/// if there is one. Outputs for `F_EMISSIVE`, `F_SPECULAR` and `F_MATERIAL`
+/// are added, unless the core already defines them.
+pub fn material_core_transform<P, I, V>(core: shader::Core<P, I, V>) -> shader::Core<P, I, V> {
+    assert!(
+        core.vertex.has_out_def(shader::defs::V_TEX_COORD),
+        "VertexCore needs V_TEX_COORD output for materials"
+    );
+
+    let mut fragment = core.fragment.with_in_def(shader::defs::V_TEX_COORD);
+
+    for (name, t) in Params::uniform_input_defs() {
+        fragment = fragment.with_extra_uniform(&name, t);
+    }
+
+    let base_color = "material_base_color * texture(material_base_color_texture, v_tex_coord)";
+
+    fragment = if fragment.has_out_def(shader::defs::F_COLOR) {
+        fragment.with_out_expr("f_color", &format!("f_color * {}", base_color))
+    } else {
+        fragment.with_out(shader::defs::F_COLOR, base_color)
+    };
+
+    fragment = fragment
+        .with_body("if (f_color.a < material_alpha_cutoff) { discard; }")
+        .with_out_expr(
+            "f_color",
+            "material_opaque ? vec4(f_color.rgb, 1.0) : f_color",
+        );
+
+    if !fragment.has_out("f_emissive") {
+        fragment = fragment.with_out(
+            shader::defs::F_EMISSIVE,
+            "material_emissive * texture(material_emissive_texture, v_tex_coord).rgb",
+        );
+    }
+    if !fragment.has_out("f_specular") {
+        fragment = fragment.with_out(shader::defs::F_SPECULAR, "material_specular");
+    }
+    if !fragment.has_out("f_material") {
+        fragment = fragment.with_out(
+            shader::defs::F_MATERIAL,
+            "vec3(material_metallic_roughness, 1.0)",
+        );
+    }
+
+    // Flip the normals of back faces for double-sided materials.
+    let flip_normal = "material_double_sided && !gl_FrontFacing";
+
+    if fragment.has_out("f_normal") {
+        fragment = fragment.with_out_expr(
+            "f_normal",
+            &format!("{} ? -f_normal : f_normal", flip_normal),
+        );
+    } else if core.vertex.has_out_def(shader::defs::V_WORLD_NORMAL) {
+        fragment = fragment.with_in_def(shader::defs::V_WORLD_NORMAL).with_out(
+            shader::defs::F_NORMAL,
+            &format!("{} ? -v_world_normal : v_world_normal", flip_normal),
+        );
+    }
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
+
+/// Wraps a scene core so that its fragments take their surface parameters
+/// from a `Material`.
+///
+/// The material uniforms are not part of the core's parameters. Instead,
+/// they are set by drawing with a `material::RenderList`.
+pub struct Core<C: SceneCore>(pub C);
+
+impl<C: SceneCore> CoreInput for Core<C> {
+    type Params = C::Params;
+    type Instance = C::Instance;
+    type Vertex = C::Vertex;
+}
+
+impl<C: SceneCore> SceneCore for Core<C> {
+    fn scene_core(&self) -> shader::Core<(Context, C::Params), C::Instance, C::Vertex> {
+        material_core_transform(self.0.scene_core())
+    }
+}
+
+/// A list of instances, grouped by their material.
+#[derive(Clone)]
+pub struct RenderList<I: InstanceInput> {
+    groups: Vec<(Material, crate::RenderList<I>)>,
+}
+
+impl<I: InstanceInput> Default for RenderList<I> {
+    fn default() -> Self {
+        Self { groups: Vec::new() }
+    }
+}
+
+impl<I: InstanceInput> RenderList<I> {
+    /// Removes all instances. The materials are kept, so that they need not
+    /// be compared again in the next frame.
+    pub fn clear(&mut self) {
+        for (_, list) in self.groups.iter_mut() {
+            list.clear();
+        }
+    }
+
+    pub fn add(&mut self, material: &Material, instance: I) {
+        let index = match self.groups.iter().position(|(m, _)| m == material) {
+            Some(index) => index,
+            None => {
+                self.groups.push((material.clone(), Default::default()));
+                self.groups.len() - 1
+            }
+        };
+
+        self.groups[index].1.add(instance);
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&Material, &crate::RenderList<I>)> {
+        self.groups.iter().map(|(material, list)| (material, list))
+    }
+
+    pub fn as_drawable<'a, V: glium::vertex::Vertex>(
+        &'a self,
+        mesh: &'a Mesh<V>,
+        resources: &'a Resources,
+    ) -> impl Drawable<I, V> + 'a {
+        RenderListDrawableImpl(self, mesh, resources)
+    }
+}
+
+struct RenderListDrawableImpl<'a, I: InstanceInput, V: Copy>(
+    &'a RenderList<I>,
+    &'a Mesh<V>,
+    &'a Resources,
+);
+
+impl<'a, I, V> Drawable<I, V> for RenderListDrawableImpl<'a, I, V>
+where
+    I: InstanceInput,
+    V: glium::vertex::Vertex,
+{
+    const INSTANCING_MODE: InstancingMode = InstancingMode::Uniforms;
+
+    fn draw<U, S>(
+        &self,
+        program: &glium::Program,
+        uniforms: &U,
+        draw_params: &glium::DrawParameters,
+        target: &mut S,
+    ) -> Result<(), DrawError>
+    where
+        U: ToUniforms,
+        S: glium::Surface,
+    {
+        for (material, list) in self.0.groups.iter() {
+            if list.as_slice().is_empty() {
+                continue;
+            }
+
+            list.as_drawable(self.1).draw(
+                program,
+                &(uniforms, material.params(self.2)),
+                &material.draw_parameters(draw_params),
+                target,
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+//! Normal mapping with tangent-space vertex data.
+//!
+//! Meshes need tangents in order to transform normals from a normal map into
+//! world space. These can be generated with `compute_tangents`, similar to
+//! MikkTSpace: per-triangle tangents are accumulated with angle weights and
+//! then orthogonalized against the vertex normal.
+
+use glium::implement_vertex;
+use nalgebra as na;
+
+use crate::scene::{CoreInput, SceneCore};
+use crate::{basic_obj, shader, Context, CreationError, Mesh};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coord: [f32; 2],
+
+    /// Tangent in the direction of increasing U. The W component is the
+    /// handedness of the tangent space, i.e. the sign of the bitangent.
+    pub tangent: [f32; 4],
+}
+
+implement_vertex!(Vertex, position, normal, tex_coord, tangent);
+
+/// Computes tangents for an indexed triangle list.
+pub fn compute_tangents(
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    tex_coords: &[[f32; 2]],
+    indices: &[u32],
+) -> Vec<[f32; 4]> {
+    assert!(positions.len() == normals.len() && positions.len() == tex_coords.len());
+    assert_eq!(indices.len() % 3, 0, "Expected a triangle list");
+
+    let mut tangents = vec![na::Vector3::<f32>::zeros(); positions.len()];
+    let mut bitangents = vec![na::Vector3::<f32>::zeros(); positions.len()];
+
+    for triangle in indices.chunks(3) {
+        let i = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        let p = [
+            na::Vector3::from(positions[i[0]]),
+            na::Vector3::from(positions[i[1]]),
+            na::Vector3::from(positions[i[2]]),
+        ];
+        let uv = [
+            na::Vector2::from(tex_coords[i[0]]),
+            na::Vector2::from(tex_coords[i[1]]),
+            na::Vector2::from(tex_coords[i[2]]),
+        ];
+
+        let edge1 = p[1] - p[0];
+        let edge2 = p[2] - p[0];
+        let delta_uv1 = uv[1] - uv[0];
+        let delta_uv2 = uv[2] - uv[0];
+
+        let det = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+        if det.abs() < f32::EPSILON {
+            // Degenerate texture coordinates, no tangent information
+            continue;
+        }
+
+        let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) / det;
+        let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) / det;
+
+        // Weight by the angle of the triangle at each corner, so that the
+        // result does not depend on how faces are tessellated.
+        for corner in 0..3 {
+            let a = p[(corner + 1) % 3] - p[corner];
+            let b = p[(corner + 2) % 3] - p[corner];
+            let angle = a.angle(&b);
+
+            if angle.is_finite() {
+                tangents[i[corner]] += tangent * angle;
+                bitangents[i[corner]] += bitangent * angle;
+            }
+        }
+    }
+
+    tangents
+        .iter()
+        .zip(bitangents.iter())
+        .zip(normals.iter())
+        .map(|((tangent, bitangent), normal)| {
+            let normal = na::Vector3::from(*normal);
+
+            // Gram-Schmidt orthogonalization
+            let tangent = tangent - normal * normal.dot(tangent);
+            let tangent = tangent.try_normalize(f32::EPSILON).unwrap_or_else(|| {
+                // Pick any vector orthogonal to the normal
+                let axis = if normal.x.abs() < 0.9 {
+                    na::Vector3::x()
+                } else {
+                    na::Vector3::y()
+                };
+                normal.cross(&axis).normalize()
+            });
+
+            let handedness = if normal.cross(&tangent).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+
+            [tangent.x, tangent.y, tangent.z, handedness]
+        })
+        .collect()
+}
+
+/// Creates a triangle list mesh, generating tangents for the vertices.
+pub fn create_mesh<F: glium::backend::Facade>(
+    facade: &F,
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    tex_coords: &[[f32; 2]],
+    indices: &[u32],
+) -> Result<Mesh<Vertex>, CreationError> {
+    let tangents = compute_tangents(positions, normals, tex_coords, indices);
+
+    let vertices = positions
+        .iter()
+        .zip(normals.iter())
+        .zip(tex_coords.iter())
+        .zip(tangents.iter())
+        .map(|(((&position, &normal), &tex_coord), &tangent)| Vertex {
+            position,
+            normal,
+            tex_coord,
+            tangent,
+        })
+        .collect::<Vec<_>>();
+
+    Mesh::create_with_indices(
+        facade,
+        glium::index::PrimitiveType::TrianglesList,
+        &vertices,
+        indices,
+    )
+}
+
+/// Shader core transform for perturbing normals with a tangent-space normal
+/// map. The core's parameters need to provide the `normal_map` sampler, e.g.
+/// by including `Params`.
+///
+/// The resulting normal is written to `f_normal`, which is then picked up by
+/// the lighting instead of `v_world_normal`.
+pub fn normal_map_core_transform<P, I, V>(core: shader::Core<P, I, V>) -> shader::Core<P, I, V> {
+    assert!(
+        core.vertex.has_out_def(shader::defs::V_WORLD_NORMAL),
+        "VertexCore needs V_WORLD_NORMAL output for normal mapping"
+    );
+    assert!(
+        core.vertex.has_out_def(shader::defs::V_WORLD_TANGENT),
+        "VertexCore needs V_WORLD_TANGENT output for normal mapping"
+    );
+    assert!(
+        core.vertex.has_out_def(shader::defs::V_TEX_COORD),
+        "VertexCore needs V_TEX_COORD output for normal mapping"
+    );
+
+    let fragment = core
+        .fragment
+        .with_in_def(shader::defs::V_WORLD_NORMAL)
+        .with_in_def(shader::defs::V_WORLD_TANGENT)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_defs(
+            "
+            vec3 normal_map_normal(vec3 normal, vec4 tangent, vec2 tex_coord) {
+                vec3 n = normalize(normal);
+                vec3 t = normalize(tangent.xyz - n * dot(n, tangent.xyz));
+                vec3 b = cross(n, t) * tangent.w;
+
+                vec3 tangent_normal = texture(normal_map, tex_coord).xyz * 2.0 - 1.0;
+
+                return normalize(mat3(t, b, n) * tangent_normal);
+            }
+            ",
+        )
+        .with_out(
+            shader::defs::F_NORMAL,
+            "normal_map_normal(v_world_normal, v_world_tangent, v_tex_coord)",
+        );
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
+
+#[derive(Clone)]
+pub struct Params<'a> {
+    pub normal_map: &'a glium::texture::Texture2d,
+}
+
+impl_uniform_input!(
+    Params<'a>,
+    self => {
+        normal_map: &'a glium::texture::Texture2d = self.normal_map,
+    },
+);
+
+/// Scene core for rendering normal mapped meshes with a uniform color per
+/// instance.
+pub struct Core;
+
+impl CoreInput for Core {
+    type Params = Params<'static>;
+    type Instance = basic_obj::Instance;
+    type Vertex = Vertex;
+}
+
+impl SceneCore for Core {
+    fn scene_core(&self) -> shader::Core<(Context, Self::Params), Self::Instance, Self::Vertex> {
+        let vertex = shader::VertexCore::empty()
+            .with_out(
+                shader::defs::V_WORLD_NORMAL,
+                "normalize(transpose(inverse(mat3(instance_transform))) * normal)",
+            )
+            .with_out(
+                shader::defs::V_WORLD_TANGENT,
+                "vec4(normalize(mat3(instance_transform) * tangent.xyz), tangent.w)",
+            )
+            .with_out(
+                shader::defs::V_WORLD_POS,
+                "instance_transform * vec4(position, 1.0)",
+            )
+            .with_out(shader::defs::V_TEX_COORD, "tex_coord")
+            .with_out(shader::defs::V_COLOR, "instance_color")
+            .with_out(
+                shader::defs::V_POS,
+                "context_camera_projection * context_camera_view * v_world_pos",
+            );
+
+        let fragment = shader::FragmentCore::empty()
+            .with_in_def(shader::defs::V_COLOR)
+            .with_out(shader::defs::F_COLOR, "v_color");
+
+        normal_map_core_transform(shader::Core { vertex, fragment })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+    const NORMALS: [[f32; 3]; 4] = [[0.0, 0.0, 1.0]; 4];
+    const INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+    fn assert_tangent_eq(actual: [f32; 4], expected: [f32; 4]) {
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn quad_tangents_follow_u() {
+        let tex_coords = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let tangents = compute_tangents(&POSITIONS, &NORMALS, &tex_coords, &INDICES);
+
+        for &tangent in &tangents {
+            assert_tangent_eq(tangent, [1.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn mirrored_quad_tangents_are_left_handed() {
+        // U decreases along X, while V still increases along Y.
+        let tex_coords = [[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let tangents = compute_tangents(&POSITIONS, &NORMALS, &tex_coords, &INDICES);
+
+        for &tangent in &tangents {
+            assert_tangent_eq(tangent, [-1.0, 0.0, 0.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn rotated_quad_tangents_follow_u() {
+        // U increases along Y and V decreases along X.
+        let tex_coords = [[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]];
+        let tangents = compute_tangents(&POSITIONS, &NORMALS, &tex_coords, &INDICES);
+
+        for &tangent in &tangents {
+            assert_tangent_eq(tangent, [0.0, 1.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn degenerate_tex_coords_give_orthogonal_tangents() {
+        let tex_coords = [[0.5, 0.5]; 4];
+        let tangents = compute_tangents(&POSITIONS, &NORMALS, &tex_coords, &INDICES);
+
+        for &[x, y, z, w] in &tangents {
+            let tangent = na::Vector3::new(x, y, z);
+
+            assert!((tangent.norm() - 1.0).abs() < 1e-5);
+            assert!(tangent.dot(&na::Vector3::z()).abs() < 1e-5);
+            assert_eq!(w.abs(), 1.0);
+        }
+    }
+}
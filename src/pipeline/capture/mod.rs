@@ -0,0 +1,212 @@
+//! Reading back images from the GPU, e.g. for screenshots or bug reports.
+//!
+//! Reading a texture waits for the GPU to finish drawing into it, so this
+//! should not be done every frame.
+
+pub mod shaders;
+
+#[cfg(feature = "png")]
+use std::io::Write;
+#[cfg(feature = "png")]
+use std::path::Path;
+
+use log::info;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{DepthTexture2dArray, MipmapsOption, Texture2d, UncompressedFloatFormat};
+use glium::{uniform, Program, Rect, Surface};
+
+use crate::{shader, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+/// An image that has been read back from the GPU. The rows are stored from
+/// top to bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image<P> {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<P>,
+}
+
+impl<P> Image<P> {
+    /// Returns the pixel at the given position, with the origin at the top
+    /// left.
+    pub fn pixel(&self, x: u32, y: u32) -> &P {
+        assert!(x < self.width && y < self.height);
+
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    /// Creates an image from rows that are stored from bottom to top, as
+    /// they are returned by OpenGL.
+    fn from_bottom_up_rows(width: u32, height: u32, rows: Vec<Vec<P>>) -> Self {
+        Self {
+            width,
+            height,
+            pixels: rows.into_iter().rev().flatten().collect(),
+        }
+    }
+}
+
+impl Image<[f32; 4]> {
+    /// Converts to 8 bits per channel, clamping the values to `[0, 1]`. No
+    /// gamma correction is applied here.
+    pub fn to_rgba8(&self) -> Image<[u8; 4]> {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|pixel| {
+                    [
+                        to_u8(pixel[0]),
+                        to_u8(pixel[1]),
+                        to_u8(pixel[2]),
+                        to_u8(pixel[3]),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Image<f32> {
+    /// Converts to opaque grayscale with 8 bits per channel, clamping the
+    /// values to `[0, 1]`.
+    pub fn to_rgba8(&self) -> Image<[u8; 4]> {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self
+                .pixels
+                .iter()
+                .map(|&value| {
+                    let value = to_u8(value);
+                    [value, value, value, 255]
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "png")]
+impl Image<[u8; 4]> {
+    /// Encodes the image as PNG.
+    pub fn encode_png<W: Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self.pixels.iter().flatten().copied().collect();
+        encoder.write_header()?.write_image_data(&data)
+    }
+
+    /// Writes the image to a PNG file.
+    pub fn write_png<Q: AsRef<Path>>(&self, path: Q) -> Result<(), png::EncodingError> {
+        let file = std::fs::File::create(path)?;
+
+        self.encode_png(std::io::BufWriter::new(file))
+    }
+}
+
+fn to_u8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Reads the contents of a color texture.
+pub fn read_texture(texture: &Texture2d) -> Image<[f32; 4]> {
+    let (width, height) = texture.dimensions();
+
+    let rows: Vec<Vec<(f32, f32, f32, f32)>> = texture
+        .main_level()
+        .first_layer()
+        .into_image(None)
+        .unwrap()
+        .raw_read(&Rect {
+            left: 0,
+            bottom: 0,
+            width,
+            height,
+        });
+
+    let rows = rows
+        .into_iter()
+        .map(|row| row.into_iter().map(|(r, g, b, a)| [r, g, b, a]).collect())
+        .collect();
+
+    Image::from_bottom_up_rows(width, height, rows)
+}
+
+/// Reads the contents of depth textures. Depth textures can not be read
+/// directly, so they are first copied into a color texture.
+pub struct DepthCapture {
+    copy_depth_layer_program: Program,
+    screen_quad: ScreenQuad,
+}
+
+impl DepthCapture {
+    pub fn create<F: glium::backend::Facade>(facade: &F) -> Result<Self, CreationError> {
+        info!("Creating depth capture program");
+        let copy_depth_layer_program = shaders::copy_depth_layer_core()
+            .build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(DepthCapture {
+            copy_depth_layer_program,
+            screen_quad,
+        })
+    }
+
+    /// Reads one layer of a depth texture array, e.g. one cascade of the
+    /// shadow map.
+    pub fn read_layer<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        texture: &DepthTexture2dArray,
+        layer: u32,
+    ) -> Result<Image<f32>, DrawError> {
+        assert!(layer < texture.array_size());
+
+        let (width, height) = (texture.width(), texture.height());
+        let color_texture = Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32,
+            MipmapsOption::NoMipmap,
+            width,
+            height,
+        )
+        .map_err(crate::CreationError::from)?;
+
+        {
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &color_texture)?;
+
+            framebuffer.draw(
+                &self.screen_quad.vertex_buffer,
+                &self.screen_quad.index_buffer,
+                &self.copy_depth_layer_program,
+                &uniform! {
+                    depth_texture: texture,
+                    depth_texture_layer: layer as i32,
+                },
+                &Default::default(),
+            )?;
+        }
+
+        let rows: Vec<Vec<f32>> = color_texture
+            .main_level()
+            .first_layer()
+            .into_image(None)
+            .unwrap()
+            .raw_read(&Rect {
+                left: 0,
+                bottom: 0,
+                width,
+                height,
+            });
+
+        Ok(Image::from_bottom_up_rows(width, height, rows))
+    }
+}
@@ -0,0 +1,23 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader};
+
+/// Shader core for copying one layer of a depth texture array into the red
+/// channel of a color texture, which can then be read back.
+pub fn copy_depth_layer_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty().with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("depth_texture", UniformType::Sampler2dArray)
+        .with_extra_uniform("depth_texture_layer", UniformType::Int)
+        .with_out(
+            shader::defs::F_COLOR,
+            "vec4(texelFetch(
+                depth_texture,
+                ivec3(ivec2(gl_FragCoord.xy), depth_texture_layer),
+                0
+            ).r)",
+        );
+
+    shader::Core { vertex, fragment }
+}
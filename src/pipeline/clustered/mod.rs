@@ -0,0 +1,437 @@
+//! Clustered forward lighting.
+//!
+//! For reference:
+//! http://www.aortiz.me/2018/12/21/CG.html
+//!
+//! The view frustum is divided into a grid of clusters: tiles in screen
+//! space, which are further split into slices along the view direction.
+//! Slices are spaced exponentially, so that clusters far away from the camera
+//! are not excessively long. Each frame, we assign lights to the clusters
+//! that their bounding spheres overlap on the CPU. The resulting light lists
+//! are uploaded into textures, so that fragments of the scene pass only need
+//! to evaluate the lights of their own cluster.
+//!
+//! In contrast to deferred shading, this works with blending and does not
+//! need a G-buffer. It is used only if deferred shading is disabled.
+
+pub mod shaders;
+
+use log::info;
+
+use nalgebra as na;
+
+use glium::texture::{MipmapsOption, UncompressedFloatFormat};
+use glium::{Rect, Texture2d};
+
+use crate::pipeline::deferred;
+use crate::pipeline::render_pass::{HasScenePassParams, RenderPassComponent, ScenePassComponent};
+use crate::{shader, Camera, Context, DrawError, Light};
+
+pub use crate::CreationError;
+
+/// Number of texels that are used for storing one light in the light texture.
+/// The first texel holds the position and the radius.
+const LIGHT_TEXELS: u32 = 5;
+
+/// Width of the texture holding the light indices of all clusters.
+const LIGHT_INDEX_TEXTURE_WIDTH: u32 = 1024;
+
+/// Minimal near plane distance used for slicing the view frustum.
+const MIN_NEAR: f32 = 0.01;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    /// Number of clusters along the screen's X and Y axes and along the view
+    /// direction.
+    pub grid_size: na::Vector3<u32>,
+
+    /// Maximal number of lights per frame. Lights beyond that are ignored.
+    pub max_lights: usize,
+
+    /// Maximal number of light indices, summed over all clusters. Once this
+    /// is exceeded, lights are missing from some clusters.
+    pub max_light_indices: usize,
+
+    /// Light intensity at which the influence of a light is cut off.
+    pub light_min_threshold: f32,
+
+    /// How the intensity of lights decreases with distance.
+    ///
+    /// With deferred shading, this and `light_min_threshold` are taken from
+    /// `deferred::Config`, so that transparent objects are lit in the same way
+    /// as the rest of the scene.
+    pub light_falloff: deferred::LightFalloff,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            grid_size: na::Vector3::new(16, 9, 24),
+            max_lights: 1024,
+            max_light_indices: 128 * 1024,
+            light_min_threshold: 0.02,
+            light_falloff: deferred::LightFalloff::Attenuation,
+        }
+    }
+}
+
+pub struct ClusteredLighting {
+    config: Config,
+
+    /// Parameters of each light, stored in `LIGHT_TEXELS` rows.
+    light_texture: Texture2d,
+
+    /// Offset into the light index texture and number of lights for each
+    /// cluster. Each row holds one depth slice.
+    cluster_texture: Texture2d,
+
+    light_index_texture: Texture2d,
+
+    num_directional_lights: usize,
+    depth_range: (f32, f32),
+}
+
+impl RenderPassComponent for ClusteredLighting {
+    fn clear_buffers<F: glium::backend::Facade>(&self, _: &F) -> Result<(), DrawError> {
+        Ok(())
+    }
+}
+
+pub struct ScenePassParams<'a> {
+    clustered_light_texture: &'a Texture2d,
+    clustered_cluster_texture: &'a Texture2d,
+    clustered_light_index_texture: &'a Texture2d,
+    clustered_grid_size: [f32; 3],
+    clustered_depth_range: [f32; 2],
+    clustered_num_directional_lights: f32,
+}
+
+impl_uniform_input!(
+    ScenePassParams<'a>,
+    self => {
+        clustered_light_texture: &'a Texture2d = self.clustered_light_texture,
+        clustered_cluster_texture: &'a Texture2d = self.clustered_cluster_texture,
+        clustered_light_index_texture: &'a Texture2d = self.clustered_light_index_texture,
+        clustered_grid_size: [f32; 3] = self.clustered_grid_size,
+        clustered_depth_range: [f32; 2] = self.clustered_depth_range,
+        clustered_num_directional_lights: f32 = self.clustered_num_directional_lights,
+    },
+);
+
+impl<'u> HasScenePassParams<'u> for ClusteredLighting {
+    type Params = ScenePassParams<'u>;
+}
+
+impl ScenePassComponent for ClusteredLighting {
+    fn core_transform<P, I, V>(
+        &self,
+        core: shader::Core<(Context, P), I, V>,
+    ) -> shader::Core<(Context, P), I, V> {
+        shaders::clustered_lighting_core_transform(self.config.light_falloff, core)
+    }
+
+    fn params(&self, _: &Context) -> ScenePassParams<'_> {
+        ScenePassParams {
+            clustered_light_texture: &self.light_texture,
+            clustered_cluster_texture: &self.cluster_texture,
+            clustered_light_index_texture: &self.light_index_texture,
+            clustered_grid_size: [
+                self.config.grid_size.x as f32,
+                self.config.grid_size.y as f32,
+                self.config.grid_size.z as f32,
+            ],
+            clustered_depth_range: [self.depth_range.0, self.depth_range.1],
+            clustered_num_directional_lights: self.num_directional_lights as f32,
+        }
+    }
+}
+
+impl ClusteredLighting {
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+    ) -> Result<Self, CreationError> {
+        assert!(config.grid_size.x > 0 && config.grid_size.y > 0 && config.grid_size.z > 0);
+        assert!(config.max_lights > 0);
+        assert!(config.max_light_indices > 0);
+
+        info!("Creating clustered lighting textures");
+
+        let light_texture = Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            config.max_lights as u32,
+            LIGHT_TEXELS,
+        )?;
+
+        let cluster_texture = Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32F32,
+            MipmapsOption::NoMipmap,
+            config.grid_size.x * config.grid_size.y,
+            config.grid_size.z,
+        )?;
+
+        let light_index_texture = Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32,
+            MipmapsOption::NoMipmap,
+            LIGHT_INDEX_TEXTURE_WIDTH,
+            (config.max_light_indices as u32).div_ceil(LIGHT_INDEX_TEXTURE_WIDTH),
+        )?;
+
+        let mut result = Self {
+            config: config.clone(),
+            light_texture,
+            cluster_texture,
+            light_index_texture,
+            num_directional_lights: 0,
+            depth_range: (MIN_NEAR, 1.0),
+        };
+
+        // Make sure that the textures do not contain garbage before the first
+        // call to `set_lights`.
+        result.clear_lights();
+
+        Ok(result)
+    }
+
+    /// Assigns the given lights to clusters of the camera's view frustum.
+    ///
+    /// The main light is skipped, since it is always evaluated separately.
+    pub fn set_lights(&mut self, camera: &Camera, lights: &[Light]) {
+        let (near, far) = camera.depth_range();
+        let near = near.max(MIN_NEAR);
+        self.depth_range = (near, far.max(near * 2.0));
+
+        // Directional lights affect every cluster, so they are stored first
+        // and evaluated for all fragments.
+        let mut sorted_lights: Vec<&Light> = lights
+            .iter()
+            .filter(|light| !light.is_main && light.kind.is_directional())
+            .collect();
+        self.num_directional_lights = sorted_lights.len().min(self.config.max_lights);
+
+        sorted_lights.extend(
+            lights
+                .iter()
+                .filter(|light| !light.is_main && !light.kind.is_directional()),
+        );
+        sorted_lights.truncate(self.config.max_lights);
+
+        if sorted_lights.is_empty() {
+            self.clear_lights();
+            return;
+        }
+
+        // Upload light parameters
+        let mut rows = vec![Vec::with_capacity(sorted_lights.len()); LIGHT_TEXELS as usize];
+        for light in sorted_lights.iter() {
+            let direction = light.kind.direction();
+            let cone = light.kind.cone();
+            let radius = self.light_radius(light);
+
+            rows[0].push((light.position.x, light.position.y, light.position.z, radius));
+            rows[1].push((light.color.x, light.color.y, light.color.z, 0.0));
+            rows[2].push((
+                light.attenuation.x,
+                light.attenuation.y,
+                light.attenuation.z,
+                light.attenuation.w,
+            ));
+            rows[3].push((direction.x, direction.y, direction.z, 0.0));
+            rows[4].push((cone.x, cone.y, cone.z, 0.0));
+        }
+
+        self.light_texture.write(
+            Rect {
+                left: 0,
+                bottom: 0,
+                width: sorted_lights.len() as u32,
+                height: LIGHT_TEXELS,
+            },
+            rows,
+        );
+
+        // Assign lights to clusters
+        let grid_size = self.config.grid_size;
+        let num_clusters = (grid_size.x * grid_size.y * grid_size.z) as usize;
+        let mut cluster_lights = vec![Vec::new(); num_clusters];
+
+        for (index, light) in sorted_lights
+            .iter()
+            .enumerate()
+            .skip(self.num_directional_lights)
+        {
+            let radius = self.light_radius(light);
+
+            if let Some((min, max)) = self.cluster_bounds(camera, light.position, radius) {
+                for z in min.z..=max.z {
+                    for y in min.y..=max.y {
+                        for x in min.x..=max.x {
+                            let cluster = x + grid_size.x * (y + grid_size.y * z);
+                            cluster_lights[cluster as usize].push(index as f32);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Flatten light indices and upload
+        let mut clusters = vec![Vec::new(); grid_size.z as usize];
+        let mut light_indices = Vec::new();
+
+        for (cluster, lights) in cluster_lights.iter().enumerate() {
+            let offset = light_indices.len();
+            let count = lights.len().min(self.config.max_light_indices - offset);
+
+            light_indices.extend_from_slice(&lights[..count]);
+
+            let slice = cluster / (grid_size.x * grid_size.y) as usize;
+            clusters[slice].push((offset as f32, count as f32));
+        }
+
+        self.cluster_texture.write(
+            Rect {
+                left: 0,
+                bottom: 0,
+                width: grid_size.x * grid_size.y,
+                height: grid_size.z,
+            },
+            clusters,
+        );
+
+        if !light_indices.is_empty() {
+            let width = LIGHT_INDEX_TEXTURE_WIDTH as usize;
+            let height = light_indices.len().div_ceil(width);
+            light_indices.resize(width * height, 0.0);
+
+            self.light_index_texture.write(
+                Rect {
+                    left: 0,
+                    bottom: 0,
+                    width: width as u32,
+                    height: height as u32,
+                },
+                light_indices
+                    .chunks(width)
+                    .map(|row| row.to_vec())
+                    .collect::<Vec<_>>(),
+            );
+        }
+    }
+
+    fn light_radius(&self, light: &Light) -> f32 {
+        deferred::falloff_radius(
+            self.config.light_falloff,
+            self.config.light_min_threshold,
+            light,
+        )
+    }
+
+    /// Removes all lights, so that only the main light is evaluated.
+    pub fn clear_lights(&mut self) {
+        self.num_directional_lights = 0;
+
+        let grid_size = self.config.grid_size;
+        let clusters = vec![
+            vec![(0.0f32, 0.0f32); (grid_size.x * grid_size.y) as usize];
+            grid_size.z as usize
+        ];
+
+        self.cluster_texture.write(
+            Rect {
+                left: 0,
+                bottom: 0,
+                width: grid_size.x * grid_size.y,
+                height: grid_size.z,
+            },
+            clusters,
+        );
+    }
+
+    /// Returns the range of clusters that are overlapped by a sphere, or
+    /// `None` if the sphere is outside of the view frustum.
+    fn cluster_bounds(
+        &self,
+        camera: &Camera,
+        center: na::Point3<f32>,
+        radius: f32,
+    ) -> Option<(na::Vector3<u32>, na::Vector3<u32>)> {
+        let grid_size = self.config.grid_size;
+        let (near, far) = self.depth_range;
+
+        let view_center = camera.view.transform_point(&center);
+        let min_depth = -view_center.z - radius;
+        let max_depth = -view_center.z + radius;
+
+        if max_depth < near || min_depth > far {
+            return None;
+        }
+
+        let slice = |depth: f32| {
+            let depth = depth.max(near).min(far);
+            let slice = (depth / near).ln() / (far / near).ln() * grid_size.z as f32;
+
+            (slice.max(0.0) as u32).min(grid_size.z - 1)
+        };
+
+        // Project the corners of the sphere's bounding box to find the
+        // covered screen tiles. If any corner is behind the camera, we
+        // conservatively cover all tiles.
+        let mut ndc_min = na::Vector2::new(1.0f32, 1.0);
+        let mut ndc_max = na::Vector2::new(-1.0f32, -1.0);
+        let mut behind_camera = false;
+
+        for &dx in &[-radius, radius] {
+            for &dy in &[-radius, radius] {
+                for &dz in &[-radius, radius] {
+                    let corner = view_center + na::Vector3::new(dx, dy, dz);
+                    let clip = camera.projection * corner.to_homogeneous();
+
+                    if clip.w <= f32::EPSILON {
+                        behind_camera = true;
+                        continue;
+                    }
+
+                    let ndc = na::Vector2::new(clip.x / clip.w, clip.y / clip.w);
+                    ndc_min = na::Vector2::new(ndc_min.x.min(ndc.x), ndc_min.y.min(ndc.y));
+                    ndc_max = na::Vector2::new(ndc_max.x.max(ndc.x), ndc_max.y.max(ndc.y));
+                }
+            }
+        }
+
+        let (tile_min, tile_max) = if behind_camera {
+            (
+                na::Vector2::new(0, 0),
+                na::Vector2::new(grid_size.x - 1, grid_size.y - 1),
+            )
+        } else {
+            if ndc_max.x < -1.0 || ndc_max.y < -1.0 || ndc_min.x > 1.0 || ndc_min.y > 1.0 {
+                return None;
+            }
+
+            let tile = |ndc: f32, n: u32| {
+                let tile = (ndc * 0.5 + 0.5) * n as f32;
+
+                (tile.max(0.0) as u32).min(n - 1)
+            };
+
+            (
+                na::Vector2::new(tile(ndc_min.x, grid_size.x), tile(ndc_min.y, grid_size.y)),
+                na::Vector2::new(tile(ndc_max.x, grid_size.x), tile(ndc_max.y, grid_size.y)),
+            )
+        };
+
+        Some((
+            na::Vector3::new(tile_min.x, tile_min.y, slice(min_depth)),
+            na::Vector3::new(tile_max.x, tile_max.y, slice(max_depth)),
+        ))
+    }
+}
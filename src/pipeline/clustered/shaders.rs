@@ -0,0 +1,153 @@
+use glium::uniforms::UniformType;
+
+use crate::pipeline::deferred::{self, LightFalloff};
+use crate::shader;
+
+/// Shader core transform that defines `clustered_lighting`, which sums up the
+/// diffuse and specular light at a fragment from the lights of its cluster.
+///
+/// The function is called by `diffuse_scene_core_transform`.
+pub fn clustered_lighting_core_transform<P, I, V>(
+    light_falloff: LightFalloff,
+    core: shader::Core<P, I, V>,
+) -> shader::Core<P, I, V> {
+    let falloff_defs = format!(
+        "
+        float clustered_light_falloff(
+            float light_distance,
+            float light_distance_sq,
+            vec4 light_attenuation,
+            float light_radius
+        ) {{
+            return {falloff};
+        }}
+        ",
+        falloff = deferred::shaders::light_falloff_expr(
+            light_falloff,
+            "light_attenuation",
+            "light_radius"
+        ),
+    );
+
+    let defs = "
+        vec4 clustered_light_texel(int light, int row) {
+            return texelFetch(clustered_light_texture, ivec2(light, row), 0);
+        }
+
+        void clustered_light(
+            int light,
+            vec3 world_pos,
+            vec3 normal,
+            vec3 view_dir,
+            vec2 specular_params,
+            inout vec3 diffuse,
+            inout vec3 specular
+        ) {
+            vec4 light_pos_radius = clustered_light_texel(light, 0);
+            vec3 light_pos = light_pos_radius.xyz;
+            vec3 light_color = clustered_light_texel(light, 1).rgb;
+            vec4 light_attenuation = clustered_light_texel(light, 2);
+            vec3 light_direction = clustered_light_texel(light, 3).xyz;
+            vec3 light_cone = clustered_light_texel(light, 4).xyz;
+
+            vec3 light_dir;
+            float intensity;
+
+            if (light_cone.z > 0.5) {
+                // Directional light
+                light_dir = -light_direction;
+                intensity = 1.0;
+            } else {
+                vec3 light_vector = light_pos - world_pos;
+                float light_distance_sq = dot(light_vector, light_vector);
+                float light_distance = sqrt(light_distance_sq);
+
+                light_dir = light_vector / light_distance;
+                intensity = clustered_light_falloff(
+                    light_distance,
+                    light_distance_sq,
+                    light_attenuation,
+                    light_pos_radius.w
+                );
+            }
+
+            // Spot light falloff. For other kinds of lights, the cone
+            // parameters are chosen such that this evaluates to 1.
+            intensity *= smoothstep(light_cone.y, light_cone.x, dot(-light_dir, light_direction));
+
+            float n_dot_l = max(dot(normal, light_dir), 0.0);
+            vec3 half_dir = normalize(light_dir + view_dir);
+            float light_specular = n_dot_l > 0.0
+                ? specular_params.x * pow(max(dot(normal, half_dir), 0.0), specular_params.y)
+                : 0.0;
+
+            diffuse += light_color * n_dot_l * intensity;
+            specular += light_color * light_specular * intensity;
+        }
+
+        void clustered_lighting(
+            vec3 world_pos,
+            vec3 normal,
+            vec3 eye_pos,
+            vec2 specular_params,
+            out vec3 diffuse,
+            out vec3 specular
+        ) {
+            diffuse = vec3(0.0);
+            specular = vec3(0.0);
+
+            vec3 view_dir = normalize(eye_pos - world_pos);
+
+            for (int i = 0; i < int(clustered_num_directional_lights); ++i) {
+                clustered_light(i, world_pos, normal, view_dir, specular_params, diffuse, specular);
+            }
+
+            // Find the cluster of this fragment
+            float near = clustered_depth_range.x;
+            float far = clustered_depth_range.y;
+            float depth = -(context_camera_view * vec4(world_pos, 1.0)).z;
+
+            ivec3 grid_size = ivec3(clustered_grid_size);
+            ivec3 cluster = ivec3(
+                floor(gl_FragCoord.xy / context_camera_viewport_size * clustered_grid_size.xy),
+                floor(log(max(depth, near) / near) / log(far / near) * clustered_grid_size.z)
+            );
+            cluster = clamp(cluster, ivec3(0), grid_size - 1);
+
+            vec2 offset_count = texelFetch(
+                clustered_cluster_texture,
+                ivec2(cluster.x + cluster.y * grid_size.x, cluster.z),
+                0
+            ).xy;
+            int offset = int(offset_count.x);
+            int count = int(offset_count.y);
+            int index_width = textureSize(clustered_light_index_texture, 0).x;
+
+            for (int i = offset; i < offset + count; ++i) {
+                int light = int(texelFetch(
+                    clustered_light_index_texture,
+                    ivec2(i % index_width, i / index_width),
+                    0
+                ).r);
+
+                clustered_light(light, world_pos, normal, view_dir, specular_params, diffuse, specular);
+            }
+        }
+    ";
+
+    let fragment = core
+        .fragment
+        .with_extra_uniform("clustered_light_texture", UniformType::Sampler2d)
+        .with_extra_uniform("clustered_cluster_texture", UniformType::Sampler2d)
+        .with_extra_uniform("clustered_light_index_texture", UniformType::Sampler2d)
+        .with_extra_uniform("clustered_grid_size", UniformType::FloatVec3)
+        .with_extra_uniform("clustered_depth_range", UniformType::FloatVec2)
+        .with_extra_uniform("clustered_num_directional_lights", UniformType::Float)
+        .with_defs(&falloff_defs)
+        .with_defs(defs);
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
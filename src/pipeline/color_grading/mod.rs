@@ -0,0 +1,573 @@
+//! Color grading with 3D lookup tables.
+//!
+//! Color grading is applied in the composition pass, after tone mapping and
+//! before gamma correction, by looking up each color in a 3D texture. Two
+//! lookup tables can be blended, which allows for smooth transitions, e.g.
+//! when entering a different area.
+//!
+//! Lookup tables can be loaded from the `.cube` format, which is supported
+//! by most color grading tools. For reference:
+//! https://wwwimages2.adobe.com/content/dam/acom/en/products/speedgrade/cc/pdfs/cube-lut-specification-1.0.pdf
+
+pub mod shaders;
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use log::info;
+use nalgebra as na;
+
+use glium::texture::{ClientFormat, MipmapsOption, RawImage3d, Texture3d, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+
+pub use crate::CreationError;
+
+/// The largest lookup table size that we accept.
+pub const MAX_LUT_SIZE: u32 = 256;
+
+#[derive(Debug)]
+pub enum LutError {
+    IO(std::io::Error),
+
+    /// The `.cube` file is malformed. `line` starts at 1.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl From<std::io::Error> for LutError {
+    fn from(err: std::io::Error) -> LutError {
+        LutError::IO(err)
+    }
+}
+
+/// A 3D lookup table that maps colors to colors.
+///
+/// With the `serde` feature, deserialized lookup tables are checked in the
+/// same way as in `from_cube_str`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Lut {
+    size: u32,
+    domain_min: na::Vector3<f32>,
+    domain_max: na::Vector3<f32>,
+
+    /// Output colors, with the red input varying fastest and the blue input
+    /// varying slowest.
+    data: Vec<(f32, f32, f32)>,
+}
+
+impl Lut {
+    /// Creates a lookup table with `size` entries per dimension by
+    /// evaluating `f` at each entry. The input colors are in `[0, 1]`.
+    pub fn from_fn<G>(size: u32, f: G) -> Self
+    where
+        G: Fn(na::Vector3<f32>) -> na::Vector3<f32>,
+    {
+        assert!(
+            (2..=MAX_LUT_SIZE).contains(&size),
+            "Lookup table size must be in [2, {}]",
+            MAX_LUT_SIZE
+        );
+
+        let scale = 1.0 / (size - 1) as f32;
+        let mut data = Vec::with_capacity((size * size * size) as usize);
+
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let color = f(na::Vector3::new(r as f32, g as f32, b as f32) * scale);
+                    data.push((color.x, color.y, color.z));
+                }
+            }
+        }
+
+        Self {
+            size,
+            domain_min: na::Vector3::zeros(),
+            domain_max: na::Vector3::new(1.0, 1.0, 1.0),
+            data,
+        }
+    }
+
+    /// Creates a lookup table that leaves colors unchanged.
+    pub fn identity() -> Self {
+        // Trilinear filtering reproduces the identity exactly, so we do not
+        // need more than two entries per dimension.
+        Self::from_fn(2, |color| color)
+    }
+
+    /// Parses a 3D lookup table in the `.cube` format. Unknown keywords are
+    /// ignored before the first entry of the table.
+    pub fn from_cube_str(source: &str) -> Result<Self, LutError> {
+        let mut size = None;
+        let mut domain_min = na::Vector3::zeros();
+        let mut domain_max = na::Vector3::new(1.0, 1.0, 1.0);
+        let mut data = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: &str| LutError::Parse {
+                line: line_number,
+                message: message.to_string(),
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let keyword = tokens.next().unwrap();
+
+            if keyword.parse::<f32>().is_ok() {
+                let size = size.ok_or_else(|| error("Data before LUT_3D_SIZE"))?;
+                if data.len() == (size * size * size) as usize {
+                    return Err(error("Too many entries"));
+                }
+
+                let color = parse_vector(line.split_whitespace())
+                    .ok_or_else(|| error("Expected three numbers"))?;
+                data.push((color.x, color.y, color.z));
+                continue;
+            }
+
+            match keyword {
+                "TITLE" => (),
+                "LUT_3D_SIZE" => {
+                    if size.is_some() {
+                        return Err(error("Duplicate LUT_3D_SIZE"));
+                    }
+
+                    let value = parse_single(tokens)
+                        .filter(|value| (2..=MAX_LUT_SIZE).contains(value))
+                        .ok_or_else(|| error("Invalid LUT_3D_SIZE"))?;
+                    size = Some(value);
+                }
+                "LUT_1D_SIZE" => {
+                    return Err(error("1D lookup tables are not supported"));
+                }
+                "DOMAIN_MIN" => {
+                    domain_min = parse_vector(tokens).ok_or_else(|| error("Invalid DOMAIN_MIN"))?;
+                }
+                "DOMAIN_MAX" => {
+                    domain_max = parse_vector(tokens).ok_or_else(|| error("Invalid DOMAIN_MAX"))?;
+                }
+                "LUT_3D_INPUT_RANGE" => {
+                    let (min, max) =
+                        parse_range(tokens).ok_or_else(|| error("Invalid LUT_3D_INPUT_RANGE"))?;
+                    domain_min = na::Vector3::new(min, min, min);
+                    domain_max = na::Vector3::new(max, max, max);
+                }
+                // Some tools write their own keywords into the header, which
+                // we do not need to understand.
+                _ if data.is_empty() => (),
+                _ => {
+                    return Err(error(&format!("Unknown keyword `{}`", keyword)));
+                }
+            }
+        }
+
+        let size = size.ok_or(LutError::Parse {
+            line: source.lines().count(),
+            message: "Missing LUT_3D_SIZE".to_string(),
+        })?;
+
+        let lut = Self {
+            size,
+            domain_min,
+            domain_max,
+            data,
+        };
+        lut.check().map_err(|message| LutError::Parse {
+            line: source.lines().count(),
+            message,
+        })?;
+
+        Ok(lut)
+    }
+
+    /// Loads a 3D lookup table from a `.cube` file.
+    pub fn from_cube_file<P: AsRef<Path>>(path: P) -> Result<Self, LutError> {
+        let source = std::fs::read_to_string(path)?;
+
+        Self::from_cube_str(&source)
+    }
+
+    /// Number of entries per dimension.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Checks that the lookup table can be turned into a texture.
+    fn check(&self) -> Result<(), String> {
+        if !(2..=MAX_LUT_SIZE).contains(&self.size) {
+            return Err(format!(
+                "Lookup table size must be in [2, {}], got {}",
+                MAX_LUT_SIZE, self.size
+            ));
+        }
+
+        let num_entries = (self.size * self.size * self.size) as usize;
+        if self.data.len() != num_entries {
+            return Err(format!(
+                "Expected {} entries, found {}",
+                num_entries,
+                self.data.len()
+            ));
+        }
+
+        if (0..3).any(|i| self.domain_min[i] >= self.domain_max[i]) {
+            return Err("Empty domain".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Lut {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "Lut")]
+        struct Fields {
+            size: u32,
+            domain_min: na::Vector3<f32>,
+            domain_max: na::Vector3<f32>,
+            data: Vec<(f32, f32, f32)>,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let lut = Lut {
+            size: fields.size,
+            domain_min: fields.domain_min,
+            domain_max: fields.domain_max,
+            data: fields.data,
+        };
+        lut.check().map_err(serde::de::Error::custom)?;
+
+        Ok(lut)
+    }
+}
+
+fn parse_single<'a, T: std::str::FromStr>(mut tokens: impl Iterator<Item = &'a str>) -> Option<T> {
+    let value = tokens.next()?.parse().ok()?;
+
+    if tokens.next().is_none() {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+fn parse_range<'a>(tokens: impl Iterator<Item = &'a str>) -> Option<(f32, f32)> {
+    let values = tokens
+        .map(|token| token.parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+
+    match values.as_slice() {
+        [min, max] => Some((*min, *max)),
+        _ => None,
+    }
+}
+
+fn parse_vector<'a>(tokens: impl Iterator<Item = &'a str>) -> Option<na::Vector3<f32>> {
+    let values = tokens
+        .map(|token| token.parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+
+    match values.as_slice() {
+        [x, y, z] => Some(na::Vector3::new(*x, *y, *z)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    pub lut: Lut,
+
+    /// Optional second lookup table, which is blended with `lut` according
+    /// to `blend_factor`.
+    pub next_lut: Option<Lut>,
+
+    /// Blend factor between `lut` and `next_lut`, where 0 means that only
+    /// `lut` is used.
+    pub blend_factor: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            lut: Lut::identity(),
+            next_lut: None,
+            blend_factor: 0.0,
+        }
+    }
+}
+
+struct LutTexture {
+    texture: Texture3d,
+    domain_min: na::Vector3<f32>,
+    domain_max: na::Vector3<f32>,
+}
+
+impl LutTexture {
+    fn create<F: glium::backend::Facade>(facade: &F, lut: &Lut) -> Result<Self, CreationError> {
+        info!("Creating color grading texture of size {}", lut.size);
+
+        let image = RawImage3d {
+            data: Cow::Borrowed(&lut.data),
+            width: lut.size,
+            height: lut.size,
+            depth: lut.size,
+            format: ClientFormat::F32F32F32,
+        };
+        let texture = Texture3d::with_format(
+            facade,
+            image,
+            UncompressedFloatFormat::F32F32F32,
+            MipmapsOption::NoMipmap,
+        )?;
+
+        Ok(Self {
+            texture,
+            domain_min: lut.domain_min,
+            domain_max: lut.domain_max,
+        })
+    }
+
+    fn sampler(&self) -> Sampler<'_, Texture3d> {
+        Sampler::new(&self.texture)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .wrap_function(SamplerWrapFunction::Clamp)
+    }
+}
+
+pub struct ColorGrading {
+    lut: LutTexture,
+    next_lut: Option<LutTexture>,
+    blend_factor: f32,
+}
+
+pub struct Params<'a> {
+    lut: &'a LutTexture,
+    next_lut: &'a LutTexture,
+    blend_factor: f32,
+}
+
+impl_uniform_input!(
+    Params<'a>,
+    self => {
+        color_grading_lut: Sampler<'a, Texture3d> = self.lut.sampler(),
+        color_grading_lut_domain_min: [f32; 3] = self.lut.domain_min.into(),
+        color_grading_lut_domain_max: [f32; 3] = self.lut.domain_max.into(),
+        color_grading_next_lut: Sampler<'a, Texture3d> = self.next_lut.sampler(),
+        color_grading_next_lut_domain_min: [f32; 3] = self.next_lut.domain_min.into(),
+        color_grading_next_lut_domain_max: [f32; 3] = self.next_lut.domain_max.into(),
+        color_grading_blend_factor: f32 = self.blend_factor,
+    },
+);
+
+impl ColorGrading {
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+    ) -> Result<Self, CreationError> {
+        let mut color_grading = Self {
+            lut: LutTexture::create(facade, &config.lut)?,
+            next_lut: None,
+            blend_factor: 0.0,
+        };
+
+        if let Some(next_lut) = config.next_lut.as_ref() {
+            color_grading.next_lut = Some(LutTexture::create(facade, next_lut)?);
+        }
+        color_grading.set_blend_factor(config.blend_factor);
+
+        Ok(color_grading)
+    }
+
+    /// Replaces the lookup tables. The blend factor is kept.
+    pub fn set_luts<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        lut: &Lut,
+        next_lut: Option<&Lut>,
+    ) -> Result<(), CreationError> {
+        let next_lut = next_lut
+            .map(|next_lut| LutTexture::create(facade, next_lut))
+            .transpose()?;
+
+        self.lut = LutTexture::create(facade, lut)?;
+        self.next_lut = next_lut;
+
+        Ok(())
+    }
+
+    /// Sets the blend factor between the two lookup tables. This is clamped
+    /// to `[0, 1]`.
+    pub fn set_blend_factor(&mut self, blend_factor: f32) {
+        self.blend_factor = blend_factor.clamp(0.0, 1.0);
+    }
+
+    pub fn params(&self) -> Params<'_> {
+        // Without a second lookup table, we just blend the first one with
+        // itself.
+        Params {
+            lut: &self.lut,
+            next_lut: self.next_lut.as_ref().unwrap_or(&self.lut),
+            blend_factor: self.blend_factor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: &str = "
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1
+";
+
+    fn assert_parse_error(source: &str, expected_line: usize, expected_message: &str) {
+        match Lut::from_cube_str(source) {
+            Err(LutError::Parse { line, message }) => {
+                assert_eq!(line, expected_line, "{}", message);
+                assert_eq!(message, expected_message);
+            }
+            result => panic!("Expected parse error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn parses_small_lut() {
+        let source = format!(
+            "# Comment\nTITLE \"Test\"\nLUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 1 2 1\n{}",
+            DATA
+        );
+        let lut = Lut::from_cube_str(&source).unwrap();
+
+        assert_eq!(lut.size(), 2);
+        assert_eq!(lut.domain_min, na::Vector3::zeros());
+        assert_eq!(lut.domain_max, na::Vector3::new(1.0, 2.0, 1.0));
+        assert_eq!(lut.data.len(), 8);
+        assert_eq!(lut.data[1], (1.0, 0.0, 0.0));
+        assert_eq!(lut.data[6], (0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn parses_input_range() {
+        let source = format!("LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE -1 2\n{}", DATA);
+        let lut = Lut::from_cube_str(&source).unwrap();
+
+        assert_eq!(lut.domain_min, na::Vector3::new(-1.0, -1.0, -1.0));
+        assert_eq!(lut.domain_max, na::Vector3::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn missing_size() {
+        assert_parse_error(DATA, 2, "Data before LUT_3D_SIZE");
+        assert_parse_error("TITLE \"Test\"", 1, "Missing LUT_3D_SIZE");
+    }
+
+    #[test]
+    fn duplicate_size() {
+        assert_parse_error("LUT_3D_SIZE 2\nLUT_3D_SIZE 2", 2, "Duplicate LUT_3D_SIZE");
+    }
+
+    #[test]
+    fn invalid_size() {
+        assert_parse_error("LUT_3D_SIZE 1", 1, "Invalid LUT_3D_SIZE");
+        assert_parse_error("LUT_3D_SIZE 257", 1, "Invalid LUT_3D_SIZE");
+        assert_parse_error("LUT_3D_SIZE 2 2", 1, "Invalid LUT_3D_SIZE");
+        assert_parse_error("LUT_1D_SIZE 2", 1, "1D lookup tables are not supported");
+    }
+
+    #[test]
+    fn too_many_entries() {
+        let source = format!("LUT_3D_SIZE 2{}1 1 1\n", DATA);
+
+        assert_parse_error(&source, 10, "Too many entries");
+    }
+
+    #[test]
+    fn too_few_entries() {
+        assert_parse_error(
+            "LUT_3D_SIZE 2\n0 0 0\n1 0 0",
+            3,
+            "Expected 8 entries, found 2",
+        );
+    }
+
+    #[test]
+    fn invalid_entry() {
+        assert_parse_error("LUT_3D_SIZE 2\n0 0", 2, "Expected three numbers");
+        assert_parse_error("LUT_3D_SIZE 2\n0 0 x", 2, "Expected three numbers");
+    }
+
+    #[test]
+    fn invalid_domain() {
+        assert_parse_error("DOMAIN_MIN 0 0", 1, "Invalid DOMAIN_MIN");
+        assert_parse_error("DOMAIN_MAX 1 1 x", 1, "Invalid DOMAIN_MAX");
+        assert_parse_error("LUT_3D_INPUT_RANGE 0", 1, "Invalid LUT_3D_INPUT_RANGE");
+        assert_parse_error("LUT_3D_INPUT_RANGE 0 1 2", 1, "Invalid LUT_3D_INPUT_RANGE");
+    }
+
+    #[test]
+    fn empty_domain() {
+        let source = format!("LUT_3D_SIZE 2\nDOMAIN_MIN 0 1 0\nDOMAIN_MAX 1 1 1{}", DATA);
+        assert_parse_error(&source, 11, "Empty domain");
+
+        let source = format!("LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE 1 0{}", DATA);
+        assert_parse_error(&source, 10, "Empty domain");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_checks_lut() {
+        let lut = Lut::from_fn(2, |color| color);
+        let json = serde_json::to_string(&lut).unwrap();
+        assert_eq!(serde_json::from_str::<Lut>(&json).unwrap(), lut);
+
+        let mut invalid = lut.clone();
+        invalid.data.pop();
+        let json = serde_json::to_string(&invalid).unwrap();
+        let error = serde_json::from_str::<Lut>(&json).unwrap_err();
+        assert!(error.to_string().contains("Expected 8 entries, found 7"));
+
+        let mut invalid = lut;
+        invalid.size = 1000;
+        let json = serde_json::to_string(&invalid).unwrap();
+        assert!(serde_json::from_str::<Lut>(&json).is_err());
+    }
+
+    #[test]
+    fn ignores_unknown_keywords_in_header() {
+        let source = format!("LUT_IN_VIDEO_RANGE\nLUT_3D_SIZE 2\nFOO 1{}", DATA);
+        let lut = Lut::from_cube_str(&source).unwrap();
+
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.data.len(), 8);
+    }
+
+    #[test]
+    fn unknown_keyword() {
+        let source = format!("LUT_3D_SIZE 2{}FOO 1", DATA);
+
+        assert_parse_error(&source, 10, "Unknown keyword `FOO`");
+    }
+}
@@ -0,0 +1,69 @@
+use glium::uniforms::UniformType;
+
+use crate::shader;
+
+/// Shader core transform for mapping `f_color` through the color grading
+/// lookup tables.
+///
+/// The colors are expected to be in the domain of the tables, so this needs
+/// to be applied after tone mapping. Both tables are sampled with trilinear
+/// filtering and blended with `color_grading_blend_factor`.
+pub fn core_transform<P, I, V>(core: shader::Core<P, I, V>) -> shader::Core<P, I, V> {
+    assert!(
+        core.fragment.has_out_def(shader::defs::F_COLOR),
+        "FragmentCore needs F_COLOR output for color grading"
+    );
+
+    let defs = "
+        vec3 color_grading_lookup(
+            sampler3D lut,
+            vec3 domain_min,
+            vec3 domain_max,
+            vec3 color
+        ) {
+            // Map the color to the centers of the outermost texels, so that
+            // the corners of the domain hit the corners of the table exactly.
+            float size = float(textureSize(lut, 0).x);
+            vec3 coord = clamp((color - domain_min) / (domain_max - domain_min), 0.0, 1.0);
+
+            return texture(lut, coord * ((size - 1.0) / size) + 0.5 / size).rgb;
+        }
+    ";
+
+    let fragment = core
+        .fragment
+        .with_extra_uniform("color_grading_lut", UniformType::Sampler3d)
+        .with_extra_uniform("color_grading_lut_domain_min", UniformType::FloatVec3)
+        .with_extra_uniform("color_grading_lut_domain_max", UniformType::FloatVec3)
+        .with_extra_uniform("color_grading_next_lut", UniformType::Sampler3d)
+        .with_extra_uniform("color_grading_next_lut_domain_min", UniformType::FloatVec3)
+        .with_extra_uniform("color_grading_next_lut_domain_max", UniformType::FloatVec3)
+        .with_extra_uniform("color_grading_blend_factor", UniformType::Float)
+        .with_defs(defs)
+        .with_out_expr(
+            "f_color",
+            "vec4(
+                mix(
+                    color_grading_lookup(
+                        color_grading_lut,
+                        color_grading_lut_domain_min,
+                        color_grading_lut_domain_max,
+                        f_color.rgb
+                    ),
+                    color_grading_lookup(
+                        color_grading_next_lut,
+                        color_grading_next_lut_domain_min,
+                        color_grading_next_lut_domain_max,
+                        f_color.rgb
+                    ),
+                    color_grading_blend_factor
+                ),
+                f_color.a
+            )",
+        );
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
@@ -1,23 +1,31 @@
-use log::info;
-
 use crate::scene::SceneCore;
-use crate::shader::InstancingMode;
 use crate::{fxaa, screen_quad, shader, Context, DrawError, Drawable};
 
+use crate::pipeline::clustered::{self, ClusteredLighting};
+use crate::pipeline::color_grading::{self, ColorGrading};
 use crate::pipeline::config::Config;
 use crate::pipeline::deferred::{self, DeferredShading};
+use crate::pipeline::fog::Fog;
 use crate::pipeline::glow::{self, Glow};
+use crate::pipeline::object_id::{self, ObjectId};
+use crate::pipeline::oit::{self, OIT};
 use crate::pipeline::render_pass::{
-    CompositionPassComponent, RenderPassComponent, ScenePassComponent, ShadedScenePass,
-    ShadedScenePassSetup, ShadowPass,
+    CompositionPassComponent, RenderPassComponent, ScenePassComponent, ShadedScenePassSetup,
 };
 use crate::pipeline::shaders;
 use crate::pipeline::shadow::{self, ShadowMapping};
+use crate::pipeline::velocity::{self, Velocity};
 
 pub struct Components {
     pub shadow_mapping: Option<ShadowMapping>,
     pub deferred_shading: Option<DeferredShading>,
+    pub clustered_lighting: Option<ClusteredLighting>,
     pub glow: Option<Glow>,
+    pub fog: Option<Fog>,
+    pub color_grading: Option<ColorGrading>,
+    pub oit: Option<OIT>,
+    pub velocity: Option<Velocity>,
+    pub object_id: Option<ObjectId>,
 }
 
 impl Components {
@@ -26,81 +34,268 @@ impl Components {
         config: &Config,
         target_size: (u32, u32),
     ) -> Result<Self, CreationError> {
-        let shadow_mapping = config
+        Ok(Self {
+            shadow_mapping: Self::create_shadow_mapping(facade, config)?,
+            deferred_shading: Self::create_deferred_shading(facade, config, target_size)?,
+            clustered_lighting: Self::create_clustered_lighting(facade, config)?,
+            glow: Self::create_glow(facade, config, target_size)?,
+            fog: config.fog.as_ref().map(Fog::create),
+            color_grading: Self::create_color_grading(facade, config)?,
+            oit: Self::create_oit(facade, config, target_size)?,
+            velocity: Self::create_velocity(facade, config, target_size)?,
+            object_id: Self::create_object_id(facade, config, target_size)?,
+        })
+    }
+
+    /// Recreates the components whose configuration differs between
+    /// `old_config` and `config`. Returns true if any component has been
+    /// recreated, in which case the programs of passes need to be rebuilt.
+    pub fn reconfigure<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        old_config: &Config,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<bool, CreationError> {
+        let mut changed = false;
+
+        if config.shadow_mapping != old_config.shadow_mapping
+            || config.deferred_shading.is_some() != old_config.deferred_shading.is_some()
+        {
+            self.shadow_mapping = Self::create_shadow_mapping(facade, config)?;
+            changed = true;
+        }
+
+        if config.deferred_shading != old_config.deferred_shading
+            || config.shadow_mapping != old_config.shadow_mapping
+            || config.glow.is_some() != old_config.glow.is_some()
+            || config.msaa != old_config.msaa
+        {
+            self.deferred_shading = Self::create_deferred_shading(facade, config, target_size)?;
+            changed = true;
+        }
+
+        if config.clustered_lighting != old_config.clustered_lighting
+            || config.deferred_shading != old_config.deferred_shading
+            || config.oit.is_some() != old_config.oit.is_some()
+        {
+            self.clustered_lighting = Self::create_clustered_lighting(facade, config)?;
+            changed = true;
+        }
+
+        if config.glow != old_config.glow {
+            self.glow = Self::create_glow(facade, config, target_size)?;
+            changed = true;
+        }
+
+        if config.fog != old_config.fog {
+            self.fog = config.fog.as_ref().map(Fog::create);
+            changed = true;
+        }
+
+        if config.color_grading != old_config.color_grading {
+            self.color_grading = Self::create_color_grading(facade, config)?;
+            changed = true;
+        }
+
+        if config.oit != old_config.oit
+            || config.hdr != old_config.hdr
+            || config.color_grading.is_some() != old_config.color_grading.is_some()
+            || config.gamma_correction != old_config.gamma_correction
+        {
+            self.oit = Self::create_oit(facade, config, target_size)?;
+            changed = true;
+        }
+
+        if Self::needs_velocity(config) != Self::needs_velocity(old_config) {
+            self.velocity = Self::create_velocity(facade, config, target_size)?;
+            changed = true;
+        }
+
+        if config.object_id != old_config.object_id || config.msaa != old_config.msaa {
+            self.object_id = Self::create_object_id(facade, config, target_size)?;
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    fn create_shadow_mapping<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+    ) -> Result<Option<ShadowMapping>, CreationError> {
+        config
             .shadow_mapping
             .as_ref()
-            .map(|config| ShadowMapping::create(facade, config))
+            .map(|shadow_config| {
+                // Point light shadows are only rendered with deferred
+                // shading, so there is no need for their cube maps otherwise.
+                let max_point_light_shadows = if config.deferred_shading.is_some() {
+                    shadow_config.max_point_light_shadows
+                } else {
+                    0
+                };
+
+                ShadowMapping::create(
+                    facade,
+                    &shadow::Config {
+                        max_point_light_shadows,
+                        ..shadow_config.clone()
+                    },
+                )
+            })
             .transpose()
-            .map_err(CreationError::ShadowMapping)?;
+            .map_err(CreationError::ShadowMapping)
+    }
 
-        let deferred_shading = config
+    fn create_deferred_shading<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Option<DeferredShading>, CreationError> {
+        config
             .deferred_shading
             .as_ref()
-            .map(|config| {
-                DeferredShading::create(facade, &config, shadow_mapping.is_some(), target_size)
+            .map(|deferred_config| {
+                DeferredShading::create(
+                    facade,
+                    deferred_config,
+                    config.shadow_mapping.as_ref(),
+                    config.glow.as_ref(),
+                    config.msaa.as_ref(),
+                    target_size,
+                )
+            })
+            .transpose()
+            .map_err(CreationError::DeferredShading)
+    }
+
+    fn create_clustered_lighting<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+    ) -> Result<Option<ClusteredLighting>, CreationError> {
+        // Clustered lighting is an alternative to deferred shading, so it is
+        // only used when the latter is disabled. Transparent objects can not
+        // be deferred, so they need it as well.
+        config
+            .clustered_lighting
+            .as_ref()
+            .filter(|_| config.deferred_shading.is_none() || config.oit.is_some())
+            .map(|clustered_config| {
+                // Light the same way as deferred shading does.
+                let clustered_config = match config.deferred_shading.as_ref() {
+                    Some(deferred_config) => clustered::Config {
+                        light_min_threshold: deferred_config.light_min_threshold,
+                        light_falloff: deferred_config.light_falloff,
+                        ..clustered_config.clone()
+                    },
+                    None => clustered_config.clone(),
+                };
+
+                ClusteredLighting::create(facade, &clustered_config)
             })
             .transpose()
-            .map_err(CreationError::DeferredShading)?;
+            .map_err(CreationError::ClusteredLighting)
+    }
 
-        let glow = config
+    fn create_glow<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Option<Glow>, CreationError> {
+        config
             .glow
             .as_ref()
             .map(|config| Glow::create(facade, config, target_size))
             .transpose()
-            .map_err(CreationError::Glow)?;
+            .map_err(CreationError::Glow)
+    }
 
-        Ok(Self {
-            shadow_mapping,
-            deferred_shading,
-            glow,
-        })
+    fn create_color_grading<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+    ) -> Result<Option<ColorGrading>, CreationError> {
+        config
+            .color_grading
+            .as_ref()
+            .map(|config| ColorGrading::create(facade, config))
+            .transpose()
+            .map_err(CreationError::ColorGrading)
     }
 
-    pub fn create_shadow_pass<F, C>(
-        &self,
+    fn create_oit<F: glium::backend::Facade>(
         facade: &F,
-        scene_core: C,
-        instancing_mode: InstancingMode,
-    ) -> Result<Option<ShadowPass<C>>, crate::CreationError>
-    where
-        F: glium::backend::Facade,
-        C: SceneCore,
-    {
-        self.shadow_mapping
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Option<OIT>, CreationError> {
+        config
+            .oit
             .as_ref()
-            .map(|shadow_mapping| {
-                info!(
-                    "Creating shadow pass for `C={}`",
-                    std::any::type_name::<C>()
-                );
-
-                let shader_core =
-                    shadow_mapping.shadow_pass_core_transform(scene_core.scene_core());
-                let program = shader_core.build_program(facade, instancing_mode)?;
-
-                Ok(ShadowPass {
-                    instancing_mode,
-                    program,
-                    shader_core,
-                })
+            .map(|oit_config| {
+                OIT::create(
+                    facade,
+                    oit_config,
+                    config.hdr,
+                    config.color_grading.is_some(),
+                    config.gamma_correction,
+                    target_size,
+                )
             })
             .transpose()
+            .map_err(CreationError::OIT)
     }
 
-    pub fn create_shaded_scene_pass<F, C>(
-        &self,
+    /// Motion vectors are only needed for postprocessing effects.
+    fn needs_velocity(config: &Config) -> bool {
+        config.motion_blur.is_some() || config.taa.is_some()
+    }
+
+    fn create_velocity<F: glium::backend::Facade>(
         facade: &F,
-        scene_core: C,
-        instancing_mode: InstancingMode,
-        setup: ShadedScenePassSetup,
-    ) -> Result<ShadedScenePass<C>, crate::CreationError>
-    where
-        F: glium::backend::Facade,
-        C: SceneCore,
-    {
-        info!("Creating scene pass for `C={}`", std::any::type_name::<C>());
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Option<Velocity>, CreationError> {
+        if Self::needs_velocity(config) {
+            Ok(Some(
+                Velocity::create(facade, target_size).map_err(CreationError::Velocity)?,
+            ))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn create_object_id<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Option<ObjectId>, CreationError> {
+        config
+            .object_id
+            .as_ref()
+            .map(|object_id_config| {
+                ObjectId::create(facade, object_id_config, config.msaa.as_ref(), target_size)
+            })
+            .transpose()
+            .map_err(CreationError::ObjectId)
+    }
+
+    /// Transforms the shader core of a shadow pass. Returns `None` if shadow
+    /// mapping is disabled.
+    pub fn shadow_pass_core<P, I, V>(
+        &self,
+        scene_core: shader::Core<(Context, P), I, V>,
+    ) -> Option<shader::Core<(Context, P), I, V>> {
+        self.shadow_mapping
+            .as_ref()
+            .map(|shadow_mapping| shadow_mapping.shadow_pass_core_transform(scene_core))
+    }
 
-        let mut shader_core = scene_core.scene_core();
+    pub fn shaded_scene_pass_core<P, I, V>(
+        &self,
+        scene_core: shader::Core<(Context, P), I, V>,
+        setup: &ShadedScenePassSetup,
+    ) -> shader::Core<(Context, P), I, V> {
+        let mut shader_core = scene_core;
 
         if let Some(glow) = self.glow.as_ref() {
             if setup.draw_glowing {
@@ -109,12 +304,18 @@ impl Components {
                 // Whoopsie there goes the "abstraction", heh. All good though.
                 shader_core = glow::shaders::no_glow_map_core_transform(shader_core);
             }
-        } else {
-            if setup.draw_glowing {
-                // Even with glow effect disabled, we still want to get the
-                // brighter colors.
-                shader_core = glow::shaders::brighten_color_core_transform(shader_core);
-            }
+        } else if setup.draw_glowing {
+            // Even with glow effect disabled, we still want to get the
+            // brighter colors.
+            shader_core = glow::shaders::brighten_color_core_transform(shader_core);
+        }
+
+        if let Some(velocity) = self.velocity.as_ref() {
+            shader_core = ScenePassComponent::core_transform(velocity, shader_core);
+        }
+
+        if let Some(object_id) = self.object_id.as_ref() {
+            shader_core = ScenePassComponent::core_transform(object_id, shader_core);
         }
 
         if let Some(shadow_mapping) = self.shadow_mapping.as_ref() {
@@ -126,17 +327,77 @@ impl Components {
         if let Some(deferred_shading) = self.deferred_shading.as_ref() {
             shader_core = ScenePassComponent::core_transform(deferred_shading, shader_core);
         } else {
-            shader_core = shaders::diffuse_scene_core_transform(shader_core);
+            if let Some(clustered_lighting) = self.clustered_lighting.as_ref() {
+                shader_core = ScenePassComponent::core_transform(clustered_lighting, shader_core);
+            }
+
+            shader_core = shaders::diffuse_scene_core_transform(
+                self.clustered_lighting.is_some(),
+                shader_core,
+            );
+
+            // Emissive light is not affected by lighting. With deferred
+            // shading, it is added in the composition pass instead.
+            if self.glow.is_none() {
+                shader_core = glow::shaders::emissive_color_core_transform(shader_core);
+            }
         }
 
-        let program = shader_core.build_program(facade, instancing_mode)?;
+        shader_core
+    }
 
-        Ok(ShadedScenePass {
-            instancing_mode,
-            setup,
-            program,
-            shader_core,
-        })
+    pub fn transparent_scene_pass_core<P, I, V>(
+        &self,
+        config: &Config,
+        scene_core: shader::Core<(Context, P), I, V>,
+    ) -> shader::Core<(Context, P), I, V> {
+        let mut shader_core = scene_core;
+
+        if let Some(shadow_mapping) = self.shadow_mapping.as_ref() {
+            shader_core = ScenePassComponent::core_transform(shadow_mapping, shader_core);
+        }
+
+        if let Some(clustered_lighting) = self.clustered_lighting.as_ref() {
+            shader_core = ScenePassComponent::core_transform(clustered_lighting, shader_core);
+        }
+
+        shader_core =
+            shaders::diffuse_scene_core_transform(self.clustered_lighting.is_some(), shader_core);
+        shader_core = glow::shaders::emissive_color_core_transform(shader_core);
+
+        if let Some(fog) = self.fog.as_ref() {
+            shader_core = ScenePassComponent::core_transform(fog, shader_core);
+        }
+
+        if let Some(oit) = self.oit.as_ref() {
+            shader_core = ScenePassComponent::core_transform(oit, shader_core);
+        } else {
+            // Without OIT, we blend directly onto the composed scene, which
+            // has already been color corrected.
+            shader_core = shaders::color_correction_core_transform(
+                config.hdr,
+                config.color_grading.is_some(),
+                config.gamma_correction,
+                shader_core,
+            );
+        }
+
+        shader_core
+    }
+
+    /// Transforms the shader core of a plain scene pass whose objects can be
+    /// picked. Returns the core together with whether it writes object IDs.
+    pub fn pickable_plain_scene_pass_core<P, I, V>(
+        &self,
+        scene_core: shader::Core<(Context, P), I, V>,
+    ) -> (shader::Core<(Context, P), I, V>, bool) {
+        match self.object_id.as_ref() {
+            Some(object_id) => (
+                ScenePassComponent::core_transform(object_id, scene_core),
+                true,
+            ),
+            None => (scene_core, false),
+        }
     }
 
     pub fn composition_core(
@@ -153,16 +414,19 @@ impl Components {
             shader_core = CompositionPassComponent::core_transform(glow, shader_core);
         }
 
-        if let Some(_) = config.hdr {
-            // TODO: Use factor
-            shader_core = shaders::hdr_composition_core_transform(shader_core);
-        }
-
-        if let Some(gamma) = config.gamma_correction {
-            shader_core = shaders::gamma_correction_composition_core_transform(shader_core, gamma);
+        // Fog is applied before tone mapping, so that it works in HDR.
+        if let Some(fog) = self.fog.as_ref() {
+            shader_core = CompositionPassComponent::core_transform(fog, shader_core);
         }
 
-        shader_core
+        // Color grading is applied after tone mapping, so that the lookup
+        // tables work with colors in [0, 1].
+        shaders::color_correction_core_transform(
+            config.hdr,
+            config.color_grading.is_some(),
+            config.gamma_correction,
+            shader_core,
+        )
     }
 
     pub fn clear_buffers<F: glium::backend::Facade>(&self, facade: &F) -> Result<(), DrawError> {
@@ -178,6 +442,18 @@ impl Components {
             .as_ref()
             .map(|c| c.clear_buffers(facade))
             .transpose()?;
+        self.oit
+            .as_ref()
+            .map(|c| c.clear_buffers(facade))
+            .transpose()?;
+        self.velocity
+            .as_ref()
+            .map(|c| c.clear_buffers(facade))
+            .transpose()?;
+        self.object_id
+            .as_ref()
+            .map(|c| c.clear_buffers(facade))
+            .transpose()?;
 
         Ok(())
     }
@@ -201,15 +477,61 @@ impl Components {
             self.shadow_mapping
                 .as_ref()
                 .map(|c| ScenePassComponent::params(c, params.0)),
-            self.deferred_shading
+            (
+                self.deferred_shading
+                    .as_ref()
+                    .map(|c| ScenePassComponent::params(c, params.0)),
+                self.clustered_lighting
+                    .as_ref()
+                    .map(|c| ScenePassComponent::params(c, params.0)),
+            ),
+            (
+                self.glow
+                    .as_ref()
+                    .map(|c| ScenePassComponent::params(c, params.0)),
+                self.velocity
+                    .as_ref()
+                    .map(|c| ScenePassComponent::params(c, params.0)),
+            ),
+        );
+
+        drawable.draw(program, &uniforms, &draw_params, target)
+    }
+
+    pub fn transparent_scene_pass<C, D, P, S>(
+        &self,
+        drawable: &D,
+        program: &glium::Program,
+        params: (&Context, &P),
+        draw_params: &glium::DrawParameters,
+        target: &mut S,
+    ) -> Result<(), DrawError>
+    where
+        C: SceneCore,
+        D: Drawable<C::Instance, C::Vertex>,
+        P: shader::input::CompatibleWith<C::Params>,
+        S: glium::Surface,
+    {
+        let uniforms = (
+            params,
+            self.shadow_mapping
                 .as_ref()
                 .map(|c| ScenePassComponent::params(c, params.0)),
-            self.glow
+            self.clustered_lighting
                 .as_ref()
                 .map(|c| ScenePassComponent::params(c, params.0)),
+            (
+                self.fog
+                    .as_ref()
+                    .map(|c| ScenePassComponent::params(c, params.0)),
+                self.oit
+                    .as_ref()
+                    .map(|c| ScenePassComponent::params(c, params.0)),
+                self.color_grading.as_ref().map(ColorGrading::params),
+            ),
         );
 
-        drawable.draw(program, &uniforms, &draw_params, target)
+        drawable.draw(program, &uniforms, draw_params, target)
     }
 
     pub fn on_target_resize<F: glium::backend::Facade>(
@@ -225,6 +547,18 @@ impl Components {
             glow.on_target_resize(facade, target_size)?;
         }
 
+        if let Some(oit) = self.oit.as_mut() {
+            oit.on_target_resize(facade, target_size)?;
+        }
+
+        if let Some(velocity) = self.velocity.as_mut() {
+            velocity.on_target_resize(facade, target_size)?;
+        }
+
+        if let Some(object_id) = self.object_id.as_mut() {
+            object_id.on_target_resize(facade, target_size)?;
+        }
+
         Ok(())
     }
 
@@ -246,6 +580,12 @@ impl Components {
                 .map_or(Vec::new(), |c| c.output_textures()),
         );
 
+        textures.extend(
+            self.velocity
+                .as_ref()
+                .map_or(Vec::new(), |c| c.output_textures()),
+        );
+
         textures
     }
 }
@@ -254,7 +594,12 @@ impl Components {
 pub enum CreationError {
     ShadowMapping(shadow::CreationError),
     DeferredShading(deferred::CreationError),
+    ClusteredLighting(clustered::CreationError),
     Glow(glow::CreationError),
+    ColorGrading(color_grading::CreationError),
+    OIT(oit::CreationError),
+    Velocity(velocity::CreationError),
+    ObjectId(object_id::CreationError),
     FXAA(fxaa::CreationError),
     CreationError(crate::CreationError),
 }
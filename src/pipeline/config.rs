@@ -1,14 +1,80 @@
-use crate::fxaa;
-use crate::pipeline::{deferred, glow, shadow};
+use nalgebra as na;
 
-#[derive(Debug, Clone)]
+use crate::pipeline::{
+    clustered, color_grading, deferred, dof, fog, glow, motion_blur, msaa, object_id, oit, outline,
+    shadow, taa, upscale,
+};
+use crate::{fxaa, smaa};
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Config {
     pub shadow_mapping: Option<shadow::Config>,
     pub deferred_shading: Option<deferred::Config>,
+
+    /// Clustered forward lighting, which evaluates all lights passed to
+    /// `start_frame` in the scene pass. This is only used if
+    /// `deferred_shading` is `None`, or for lighting the transparent scene
+    /// pass if `oit` is given.
+    pub clustered_lighting: Option<clustered::Config>,
+
+    /// Weighted blended order-independent transparency for the transparent
+    /// scene pass. Without it, transparent objects are alpha blended in the
+    /// order that they are drawn. With deferred shading, this also enables
+    /// `clustered_lighting` for lighting the transparent scene pass, so it
+    /// should only be set if transparent objects are drawn.
+    pub oit: Option<oit::Config>,
+
     pub glow: Option<glow::Config>,
+
+    /// Object ID buffer, which allows picking objects with `Pipeline::pick`.
+    pub object_id: Option<object_id::Config>,
+
+    pub fog: Option<fog::Config>,
     pub hdr: Option<f32>,
+
+    /// Color grading with 3D lookup tables, applied after `hdr` tone mapping
+    /// and before `gamma_correction`.
+    pub color_grading: Option<color_grading::Config>,
+
     pub gamma_correction: Option<f32>,
+
+    /// Multisample anti-aliasing for the shaded scene pass and plain scene
+    /// passes. See the `msaa` module for how this works with deferred
+    /// shading.
+    pub msaa: Option<msaa::Config>,
+
+    /// Temporal anti-aliasing, applied at the start of postprocessing. This
+    /// jitters the camera projection of the frame and makes the shaded scene
+    /// pass output motion vectors. It is usually used instead of `fxaa`, but
+    /// both can be combined.
+    pub taa: Option<taa::Config>,
+
+    /// Depth of field, applied after temporal anti-aliasing.
+    pub depth_of_field: Option<dof::Config>,
+
+    /// Motion blur, applied after depth of field. This makes the shaded
+    /// scene pass output motion vectors.
+    pub motion_blur: Option<motion_blur::Config>,
+
     pub fxaa: Option<fxaa::Config>,
+
+    /// Higher quality alternative to `fxaa`. If both are given, SMAA is
+    /// used.
+    pub smaa: Option<smaa::Config>,
+
+    /// Outlines around objects drawn in the outline pass, which follows
+    /// postprocessing.
+    pub outline: Option<outline::Config>,
+
+    /// Renders the scene at a lower resolution, which is upscaled to the
+    /// target at the end of postprocessing. Outlines and plain scene passes
+    /// after postprocessing are drawn at the full resolution.
+    pub upscale: Option<upscale::Config>,
 }
 
 impl Default for Config {
@@ -16,10 +82,66 @@ impl Default for Config {
         Self {
             shadow_mapping: Some(Default::default()),
             deferred_shading: Some(Default::default()),
+            clustered_lighting: Some(Default::default()),
+            oit: None,
             glow: Some(Default::default()),
+            object_id: None,
+            fog: None,
             hdr: None,
+            color_grading: None,
             gamma_correction: Some(2.2),
+            msaa: None,
+            taa: None,
+            depth_of_field: None,
+            motion_blur: None,
             fxaa: Some(Default::default()),
+            smaa: None,
+            outline: None,
+            upscale: None,
+        }
+    }
+}
+
+impl Config {
+    /// Preset for slow hardware, with small shadow maps, no percentage-closer
+    /// filtering and cheap glow.
+    pub fn low() -> Self {
+        Self::preset(1024, 0, 1, fxaa::Quality::Low)
+    }
+
+    /// Preset that is equal to `Config::default()`.
+    pub fn medium() -> Self {
+        Self::preset(2048, 1, 2, fxaa::Quality::Low)
+    }
+
+    /// Preset with large shadow maps and better anti-aliasing.
+    pub fn high() -> Self {
+        Self::preset(4096, 1, 3, fxaa::Quality::Medium)
+    }
+
+    /// Preset for fast hardware, with large shadow maps, wide
+    /// percentage-closer filtering and smooth glow.
+    pub fn ultra() -> Self {
+        Self::preset(4096, 2, 4, fxaa::Quality::High)
+    }
+
+    fn preset(
+        shadow_map_size: u32,
+        pcf_distance: usize,
+        num_blur_passes: usize,
+        fxaa_quality: fxaa::Quality,
+    ) -> Self {
+        Self {
+            shadow_mapping: Some(shadow::Config {
+                shadow_map_size: na::Vector2::new(shadow_map_size, shadow_map_size),
+                pcf_distance,
+                ..Default::default()
+            }),
+            glow: Some(glow::Config { num_blur_passes }),
+            fxaa: Some(fxaa::Config {
+                quality: fxaa_quality,
+            }),
+            ..Default::default()
         }
     }
 }
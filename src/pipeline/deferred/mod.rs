@@ -7,54 +7,99 @@ pub mod shaders;
 
 use log::info;
 
-use nalgebra as na;
-
 use glium::{Surface, Texture2d};
 
 use crate::shader::{self, InstanceInput, ToUniforms};
 use crate::{
     basic_obj, screen_quad, BasicObj, Camera, Context, DrawError, Drawable, Instancing, Light,
-    Mesh, ScreenQuad,
+    LightKind, Mesh, ScreenQuad,
 };
 
+use crate::pipeline::glow;
+use crate::pipeline::msaa::{self, MSAA};
 use crate::pipeline::render_pass::{
     CompositionPassComponent, HasCompositionPassParams, HasScenePassParams, RenderPassComponent,
     ScenePassComponent,
 };
+use crate::pipeline::shadow::{self, ShadowMapping};
 
 pub use crate::CreationError;
 
-#[derive(Debug, Clone)]
+/// Lighting model used for evaluating lights in the light pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShadingModel {
+    /// Lambertian diffuse lighting with Blinn-Phong specular highlights.
+    /// Scene cores can provide `f_specular`.
+    BlinnPhong,
+
+    /// Cook-Torrance BRDF, using the scene color as albedo. Scene cores can
+    /// provide metallic, roughness and ambient occlusion in `f_material`.
+    PhysicallyBased,
+}
+
+/// Determines how the intensity of lights decreases with distance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LightFalloff {
+    /// Use the polynomial/exponential `Light::attenuation`.
+    Attenuation,
+
+    /// Physically based inverse-square falloff, windowed to reach zero at
+    /// the light's radius. `Light::attenuation` is ignored.
+    InverseSquare,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Config {
     pub light_min_threshold: f32,
+    pub shading_model: ShadingModel,
+    pub light_falloff: LightFalloff,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             light_min_threshold: 0.02,
+            shading_model: ShadingModel::BlinnPhong,
+            light_falloff: LightFalloff::Attenuation,
         }
     }
 }
 
-const NUM_TEXTURES: usize = 2;
+const NUM_TEXTURES: usize = 3;
 
 pub struct DeferredShading {
     config: Config,
 
     scene_textures: [Texture2d; NUM_TEXTURES],
     shadow_texture: Option<Texture2d>,
+    emissive_texture: Option<Texture2d>,
 
     light_texture: Texture2d,
 
     main_light_screen_quad_program: glium::Program,
     light_object_program: glium::Program,
+    spot_light_object_program: glium::Program,
+    directional_light_program: glium::Program,
+    shadowed_light_object_program: Option<glium::Program>,
 
     screen_quad: ScreenQuad,
+    screen_quad_mesh: Mesh<screen_quad::Vertex>,
     sphere: Mesh<basic_obj::Vertex>,
+    cone: Mesh<basic_obj::Vertex>,
 
     light_instances: Vec<<Light as InstanceInput>::Vertex>,
     light_instancing: Instancing<Light>,
+    spot_light_instances: Vec<<Light as InstanceInput>::Vertex>,
+    spot_light_instancing: Instancing<Light>,
+    directional_light_instances: Vec<<Light as InstanceInput>::Vertex>,
+    directional_light_instancing: Instancing<Light>,
 }
 
 impl RenderPassComponent for DeferredShading {
@@ -79,19 +124,32 @@ impl ScenePassComponent for DeferredShading {
         core: shader::Core<(Context, P), I, V>,
     ) -> shader::Core<(Context, P), I, V> {
         // Write scene to separate buffers
-        shaders::scene_buffers_core_transform(self.shadow_texture.is_some(), core)
+        shaders::scene_buffers_core_transform(
+            self.config.shading_model,
+            self.shadow_texture.is_some(),
+            self.emissive_texture.is_some(),
+            core,
+        )
     }
 
     fn output_textures(&self) -> Vec<(&'static str, &Texture2d)> {
         let mut result = vec![
             ("f_world_pos", &self.scene_textures[0]),
             ("f_world_normal", &self.scene_textures[1]),
+            (
+                shaders::material_out_def(self.config.shading_model).0 .0,
+                &self.scene_textures[2],
+            ),
         ];
 
         if let Some(shadow_texture) = self.shadow_texture.as_ref() {
             result.push(("f_shadow", shadow_texture));
         }
 
+        if let Some(emissive_texture) = self.emissive_texture.as_ref() {
+            result.push((shaders::F_EMISSIVE_COLOR.0, emissive_texture));
+        }
+
         result
     }
 
@@ -101,6 +159,7 @@ impl ScenePassComponent for DeferredShading {
 pub struct CompositionPassParams<'a> {
     light_texture: &'a Texture2d,
     normal_texture: &'a Texture2d,
+    material_texture: &'a Texture2d,
 }
 
 impl_uniform_input!(
@@ -108,11 +167,23 @@ impl_uniform_input!(
     self => {
         light_texture: &'a Texture2d = self.light_texture,
         normal_texture: &'a Texture2d = self.normal_texture,
+        material_texture: &'a Texture2d = self.material_texture,
+    },
+);
+
+pub struct EmissiveParams<'a> {
+    emissive_texture: &'a Texture2d,
+}
+
+impl_uniform_input!(
+    EmissiveParams<'a>,
+    self => {
+        emissive_texture: &'a Texture2d = self.emissive_texture,
     },
 );
 
 impl<'u> HasCompositionPassParams<'u> for DeferredShading {
-    type Params = CompositionPassParams<'u>;
+    type Params = (CompositionPassParams<'u>, Option<EmissiveParams<'u>>);
 }
 
 impl CompositionPassComponent for DeferredShading {
@@ -120,14 +191,27 @@ impl CompositionPassComponent for DeferredShading {
         &self,
         core: shader::Core<Context, (), screen_quad::Vertex>,
     ) -> shader::Core<Context, (), screen_quad::Vertex> {
-        shaders::composition_core_transform(core)
+        shaders::composition_core_transform(
+            self.config.shading_model,
+            self.emissive_texture.is_some(),
+            core,
+        )
     }
 
-    fn params(&self) -> CompositionPassParams {
-        CompositionPassParams {
-            light_texture: &self.light_texture,
-            normal_texture: &self.scene_textures[1],
-        }
+    fn params(&self, _: &Context) -> <Self as HasCompositionPassParams<'_>>::Params {
+        let emissive_params = self
+            .emissive_texture
+            .as_ref()
+            .map(|emissive_texture| EmissiveParams { emissive_texture });
+
+        (
+            CompositionPassParams {
+                light_texture: &self.light_texture,
+                normal_texture: &self.scene_textures[1],
+                material_texture: &self.scene_textures[2],
+            },
+            emissive_params,
+        )
     }
 }
 
@@ -135,37 +219,82 @@ impl DeferredShading {
     pub fn create<F: glium::backend::Facade>(
         facade: &F,
         config: &Config,
-        have_shadows: bool,
+        shadow_config: Option<&shadow::Config>,
+        glow_config: Option<&glow::Config>,
+        msaa_config: Option<&msaa::Config>,
         target_size: (u32, u32),
     ) -> Result<DeferredShading, CreationError> {
+        let have_shadows = shadow_config.is_some();
+
+        // With glow, emissive light is added to the scene through the glow
+        // buffer. Otherwise, we need a separate buffer for it.
+        let have_emissive = glow_config.is_none();
+        let num_samples = msaa_config.map(|config| config.num_samples);
+
         info!("Creating deferred buffer textures");
         let scene_textures = [
             Self::create_texture(facade, target_size)?,
             Self::create_texture(facade, target_size)?,
+            Self::create_texture(facade, target_size)?,
         ];
         let shadow_texture = if have_shadows {
             Some(Self::create_shadow_texture(facade, target_size)?)
         } else {
             None
         };
+        let emissive_texture = if have_emissive {
+            Some(Self::create_texture(facade, target_size)?)
+        } else {
+            None
+        };
         let light_texture = Self::create_texture(facade, target_size)?;
 
         info!("Creating deferred light programs");
-        let main_light_screen_quad_core = shaders::main_light_screen_quad_core(have_shadows);
+        let main_light_screen_quad_core =
+            shaders::main_light_screen_quad_core(config, num_samples, have_shadows);
         let main_light_screen_quad_program =
             main_light_screen_quad_core.build_program(facade, shader::InstancingMode::Uniforms)?;
-        let light_object_core = shaders::light_object_core();
+        let light_object_core = shaders::light_object_core(config, num_samples);
         let light_object_program =
             light_object_core.build_program(facade, shader::InstancingMode::Vertex)?;
+        let spot_light_object_program = shaders::spot_light_object_core(config, num_samples)
+            .build_program(facade, shader::InstancingMode::Vertex)?;
+        let directional_light_program =
+            shaders::directional_light_screen_quad_core(config, num_samples)
+                .build_program(facade, shader::InstancingMode::Vertex)?;
+        let shadowed_light_object_program = shadow_config
+            .filter(|shadow_config| shadow_config.max_point_light_shadows > 0)
+            .map(|shadow_config| {
+                shaders::shadowed_light_object_core(
+                    config,
+                    num_samples,
+                    shadow::POINT_LIGHT_SHADOW_NEAR,
+                    shadow_config.point_light_shadow_map_size,
+                )
+                .build_program(facade, shader::InstancingMode::Uniforms)
+            })
+            .transpose()?;
 
         info!("Creating screen quad");
         let screen_quad = ScreenQuad::create(facade)?;
+        let screen_quad_mesh = Mesh::create_with_indices(
+            facade,
+            glium::index::PrimitiveType::TrianglesList,
+            screen_quad::VERTICES,
+            &screen_quad::INDICES
+                .iter()
+                .map(|&i| u32::from(i))
+                .collect::<Vec<_>>(),
+        )?;
 
-        info!("Creating sphere");
+        info!("Creating sphere and cone");
         let sphere = BasicObj::Sphere.create_mesh(facade)?;
+        let cone = Self::create_cone_mesh(facade)?;
 
         info!("Creating light buffers");
         let light_instancing = Instancing::create(facade)?;
+        let spot_light_instancing = Instancing::create(facade)?;
+        let directional_light_instancing = Instancing::create(facade)?;
 
         info!("Deferred shading initialized");
 
@@ -173,13 +302,23 @@ impl DeferredShading {
             config: config.clone(),
             scene_textures,
             shadow_texture,
+            emissive_texture,
             light_texture,
             main_light_screen_quad_program,
             light_object_program,
+            spot_light_object_program,
+            directional_light_program,
+            shadowed_light_object_program,
             screen_quad,
+            screen_quad_mesh,
             sphere,
+            cone,
             light_instances: Vec::new(),
             light_instancing,
+            spot_light_instances: Vec::new(),
+            spot_light_instancing,
+            directional_light_instances: Vec::new(),
+            directional_light_instancing,
         })
     }
 
@@ -196,23 +335,43 @@ impl DeferredShading {
         self.scene_textures = [
             Self::create_texture(facade, target_size)?,
             Self::create_texture(facade, target_size)?,
+            Self::create_texture(facade, target_size)?,
         ];
 
         if let Some(shadow_texture) = self.shadow_texture.as_mut() {
             *shadow_texture = Self::create_shadow_texture(facade, target_size)?;
         }
 
+        if let Some(emissive_texture) = self.emissive_texture.as_mut() {
+            *emissive_texture = Self::create_texture(facade, target_size)?;
+        }
+
         self.light_texture = Self::create_texture(facade, target_size)?;
 
         Ok(())
     }
 
+    /// Returns the distance from a light at which its contribution falls
+    /// below `Config::light_min_threshold`.
+    pub fn light_radius(&self, light: &Light) -> f32 {
+        light_radius(&self.config, light)
+    }
+
+    /// Renders the contribution of `lights` into the light texture.
+    ///
+    /// With `msaa`, the multisampled versions of the scene buffers are read,
+    /// so that edge pixels can be lit per sample. In that case,
+    /// `DeferredShading` must have been created with the same MSAA config.
+    #[allow(clippy::too_many_arguments)]
     pub fn light_pass<F: glium::backend::Facade>(
         &mut self,
         facade: &F,
         scene_depth_texture: &glium::texture::DepthTexture2d,
+        scene_color_texture: &Texture2d,
         camera: &Camera,
         lights: &[Light],
+        shadow_mapping: Option<&ShadowMapping>,
+        msaa: Option<&MSAA>,
     ) -> Result<(), DrawError> {
         let draw_params = glium::DrawParameters {
             backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
@@ -238,47 +397,103 @@ impl DeferredShading {
 
         light_buffer.clear_color(0.0, 0.0, 0.0, 1.0);
 
-        let textures = &plain_uniforms! {
+        let textures = plain_uniforms! {
             position_texture: &self.scene_textures[0],
             normal_texture: &self.scene_textures[1],
+            material_texture: &self.scene_textures[2],
+            albedo_texture: scene_color_texture,
+        };
+        let multisample_textures = msaa.map(|msaa| {
+            let output_texture = |name| {
+                msaa.output_texture(name)
+                    .expect("MSAA needs to be created with the deferred shading outputs")
+            };
+
+            plain_uniforms! {
+                position_texture: output_texture("f_world_pos"),
+                normal_texture: output_texture("f_world_normal"),
+                material_texture:
+                    output_texture(shaders::material_out_def(self.config.shading_model).0 .0),
+                albedo_texture: msaa.color_texture(),
+            }
+        });
+
+        let shadow_texture = self.shadow_texture.as_ref().map(|shadow_texture| {
+            plain_uniforms! {
+                shadow_texture: shadow_texture,
+            }
+        });
+        let multisample_shadow_texture =
+            msaa.and_then(|msaa| msaa.output_texture("f_shadow"))
+                .map(|shadow_texture| {
+                    plain_uniforms! {
+                        shadow_texture: shadow_texture,
+                    }
+                });
+
+        // Only one of the two is used, depending on whether the light
+        // programs were built for multisampled textures.
+        let (textures, shadow_texture) = if msaa.is_some() {
+            (
+                (None, multisample_textures),
+                (None, multisample_shadow_texture),
+            )
+        } else {
+            ((Some(textures), None), (shadow_texture, None))
         };
 
+        // Lights with a shadow cube map need to be drawn separately
+        let shadowed_lights: Vec<(&Light, &glium::texture::DepthCubemap)> =
+            match (shadow_mapping, self.shadowed_light_object_program.as_ref()) {
+                (Some(shadow_mapping), Some(_)) => shadow_mapping
+                    .shadow_casting_point_lights(lights)
+                    .zip(shadow_mapping.point_light_shadow_maps().iter())
+                    .collect(),
+                _ => Vec::new(),
+            };
+
         self.light_instances.clear();
+        self.spot_light_instances.clear();
+        self.directional_light_instances.clear();
+
         for light in lights {
-            if light.is_main {
+            if light.is_main
+                || shadowed_lights
+                    .iter()
+                    .any(|(shadowed_light, _)| std::ptr::eq(light, *shadowed_light))
+            {
                 continue;
             }
 
             let light = Light {
-                radius: light_radius(self.config.light_min_threshold, light),
+                radius: light_radius(&self.config, light),
                 ..light.clone()
             };
 
-            self.light_instances.push(light.to_vertex());
+            let instances = match light.kind {
+                LightKind::Point => &mut self.light_instances,
+                LightKind::Spot { .. } => &mut self.spot_light_instances,
+                LightKind::Directional { .. } => &mut self.directional_light_instances,
+            };
+
+            instances.push(light.to_vertex());
         }
 
         self.light_instancing
             .update(facade, &self.light_instances)?;
+        self.spot_light_instancing
+            .update(facade, &self.spot_light_instances)?;
+        self.directional_light_instancing
+            .update(facade, &self.directional_light_instances)?;
 
         // Draw main light
         for light in lights.iter() {
             if light.is_main {
-                // Fragment shader uses viewport size, but we don't need view/projection
-                let no_camera = Camera {
-                    view: na::Matrix4::identity(),
-                    projection: na::Matrix4::identity(),
-                    viewport_size: camera.viewport_size,
+                let light = Light {
+                    radius: light_radius(&self.config, light),
+                    ..light.clone()
                 };
-
-                let textures_with_shadow = (
-                    &textures,
-                    &self.shadow_texture.as_ref().map(|shadow_texture| {
-                        plain_uniforms! {
-                            shadow_texture: shadow_texture,
-                        }
-                    }),
-                );
-                let uniforms = (textures_with_shadow, (no_camera, &light));
+                let uniforms = ((&textures, &shadow_texture), (camera, &light));
 
                 light_buffer.draw(
                     &self.screen_quad.vertex_buffer,
@@ -293,6 +508,17 @@ impl DeferredShading {
         // Draw additional light using instancing
         let uniforms = (&textures, &camera);
 
+        // Directional lights cover the whole screen, so they are not depth
+        // tested against a light volume
+        self.directional_light_instancing
+            .as_drawable(&self.screen_quad_mesh)
+            .draw(
+                &self.directional_light_program,
+                &uniforms,
+                &draw_params,
+                &mut light_buffer,
+            )?;
+
         // With backface culling, there is a problem in that lights are
         // not rendered when the camera moves within the sphere. With
         // frontface culling this problem does not happen.
@@ -315,9 +541,79 @@ impl DeferredShading {
             &mut light_buffer,
         )?;
 
+        self.spot_light_instancing.as_drawable(&self.cone).draw(
+            &self.spot_light_object_program,
+            &uniforms,
+            &draw_params,
+            &mut light_buffer,
+        )?;
+
+        // Draw lights that cast shadows one by one
+        if let Some(program) = self.shadowed_light_object_program.as_ref() {
+            for (light, shadow_map) in shadowed_lights {
+                let light = Light {
+                    radius: light_radius(&self.config, light),
+                    ..light.clone()
+                };
+                let shadow_map = plain_uniforms! {
+                    light_shadow_map: glium::uniforms::Sampler::new(shadow_map)
+                        .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest)
+                        .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest),
+                };
+                let uniforms = (&textures, &camera, &light, &shadow_map);
+
+                light_buffer.draw(
+                    &self.sphere.vertex_buffer,
+                    &self.sphere.index_buffer,
+                    program,
+                    &uniforms.to_uniforms(),
+                    &draw_params,
+                )?;
+            }
+        }
+
         Ok(())
     }
 
+    /// Creates a cone with its apex at the origin, opening towards positive
+    /// Z, with a length and base radius of 1.
+    fn create_cone_mesh<F: glium::backend::Facade>(
+        facade: &F,
+    ) -> Result<Mesh<basic_obj::Vertex>, CreationError> {
+        let num_sectors = 16;
+
+        // Scale the base so that the polygon encloses the unit circle
+        let base_scale = 1.0 / (std::f32::consts::PI / num_sectors as f32).cos();
+
+        let mut positions = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        let mut normals = vec![[0.0, 0.0, -1.0], [0.0, 0.0, 1.0]];
+        let mut indices = Vec::new();
+
+        for i in 0..num_sectors {
+            let theta = i as f32 / num_sectors as f32 * 2.0 * std::f32::consts::PI;
+            let (x, y) = (theta.cos() * base_scale, theta.sin() * base_scale);
+
+            positions.push([x, y, 1.0]);
+            normals.push([x, y, -1.0]);
+
+            let current = i + 2;
+            let next = (i + 1) % num_sectors + 2;
+
+            // Side and base, counter-clockwise when seen from the outside
+            indices.extend_from_slice(&[0, next, current]);
+            indices.extend_from_slice(&[1, current, next]);
+        }
+
+        basic_obj::mesh_from_slices(
+            facade,
+            glium::index::PrimitiveType::TrianglesList,
+            &positions,
+            &normals,
+            &vec![[0.0, 0.0]; positions.len()],
+            &indices,
+        )
+    }
+
     fn create_texture<F: glium::backend::Facade>(
         facade: &F,
         size: (u32, u32),
@@ -345,7 +641,26 @@ impl DeferredShading {
     }
 }
 
-fn light_radius(min_threshold: f32, light: &Light) -> f32 {
+fn light_radius(config: &Config, light: &Light) -> f32 {
+    falloff_radius(config.light_falloff, config.light_min_threshold, light)
+}
+
+/// Distance at which the intensity of a light with the given falloff drops
+/// below `min_threshold`.
+pub(crate) fn falloff_radius(falloff: LightFalloff, min_threshold: f32, light: &Light) -> f32 {
+    match falloff {
+        LightFalloff::Attenuation => attenuation_radius(min_threshold, light),
+        LightFalloff::InverseSquare => {
+            let i_max = light.color.x.max(light.color.y).max(light.color.z);
+
+            (i_max / min_threshold).sqrt()
+        }
+    }
+}
+
+/// Distance at which the intensity of a light, as given by its attenuation,
+/// drops below `min_threshold`.
+fn attenuation_radius(min_threshold: f32, light: &Light) -> f32 {
     let i_max = light.color.x.max(light.color.y).max(light.color.z);
 
     let radicand = light.attenuation.y.powi(2)
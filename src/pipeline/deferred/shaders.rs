@@ -1,5 +1,6 @@
 use glium::uniforms::UniformType;
 
+use crate::pipeline::deferred::{Config, LightFalloff, ShadingModel};
 use crate::pipeline::Light;
 use crate::{basic_obj, screen_quad, shader, Camera, Context};
 
@@ -13,10 +14,20 @@ pub const F_WORLD_NORMAL: (&str, shader::FragmentOutDef) = (
     shader::FragmentOutDef(shader::Type::FloatVec4, shader::FragmentOutQualifier::Yield),
 );
 
-/// Shader core transform for writing position/normal/color into separate
-/// buffers, so that they may be combined in a subsequent pass.
+/// Emissive light, which is added to the scene in the composition pass
+/// without being lit. This is only written if the glow effect is disabled,
+/// since emissive light is part of the glow buffer otherwise.
+pub const F_EMISSIVE_COLOR: (&str, shader::FragmentOutDef) = (
+    "f_emissive_color",
+    shader::FragmentOutDef(shader::Type::FloatVec3, shader::FragmentOutQualifier::Yield),
+);
+
+/// Shader core transform for writing position/normal/specular/color into
+/// separate buffers, so that they may be combined in a subsequent pass.
 pub fn scene_buffers_core_transform<P, I, V>(
+    shading_model: ShadingModel,
     always_include_shadow_out: bool,
+    include_emissive_out: bool,
     core: shader::Core<P, I, V>,
 ) -> shader::Core<P, I, V> {
     assert!(
@@ -32,12 +43,19 @@ pub fn scene_buffers_core_transform<P, I, V>(
         "FragmentCore needs F_COLOR output for deferred shading scene pass"
     );
 
+    // Cores may provide a more detailed normal than the interpolated one.
+    let normal_expr = if core.fragment.has_out("f_normal") {
+        "vec4(f_normal, 0.0)"
+    } else {
+        "vec4(v_world_normal, 0.0)"
+    };
+
     let mut fragment = core
         .fragment
         .with_in_def(shader::defs::V_WORLD_POS)
         .with_in_def(shader::defs::V_WORLD_NORMAL)
         .with_out(F_WORLD_POS, "v_world_pos")
-        .with_out(F_WORLD_NORMAL, "vec4(v_world_normal, 0.0)");
+        .with_out(F_WORLD_NORMAL, normal_expr);
 
     // We may have the case that we want to attach an `f_shadow` output, but
     // the given `core` does not provide any shadow values (i.e. it wants to
@@ -46,12 +64,35 @@ pub fn scene_buffers_core_transform<P, I, V>(
         fragment = fragment.with_out(shader::defs::F_SHADOW, "1.0");
     }
 
-    // This is a bit sneaky: we turn `f_shadow` from a local variable into
-    // something that is output by the fragment shader.
-    fragment = fragment.with_out_def((
-        "f_shadow",
-        shader::FragmentOutDef(shader::Type::Float, shader::FragmentOutQualifier::Yield),
-    ));
+    if include_emissive_out {
+        let emissive_color = if fragment.has_out_def(shader::defs::F_EMISSIVE) {
+            "f_emissive"
+        } else {
+            "vec3(0.0)"
+        };
+
+        fragment = fragment.with_out(F_EMISSIVE_COLOR, emissive_color);
+    }
+
+    // Provide default material parameters for fragments that do not specify
+    // any. Without specular parameters, there are no specular highlights.
+    let (material_def, default_material) = material_out_def(shading_model);
+    let (material_name, material_type) = (material_def.0, (material_def.1).0);
+    if !fragment.has_out(material_name) {
+        fragment = fragment.with_out(material_def, default_material);
+    }
+
+    // This is a bit sneaky: we turn `f_shadow` and the material from local
+    // variables into something that is output by the fragment shader.
+    fragment = fragment
+        .with_out_def((
+            "f_shadow",
+            shader::FragmentOutDef(shader::Type::Float, shader::FragmentOutQualifier::Yield),
+        ))
+        .with_out_def((
+            material_name,
+            shader::FragmentOutDef(material_type, shader::FragmentOutQualifier::Yield),
+        ));
 
     shader::Core {
         vertex: core.vertex,
@@ -59,6 +100,17 @@ pub fn scene_buffers_core_transform<P, I, V>(
     }
 }
 
+/// Returns the fragment output that holds material parameters for the given
+/// shading model, together with its default value.
+pub fn material_out_def(
+    shading_model: ShadingModel,
+) -> ((&'static str, shader::FragmentOutDef), &'static str) {
+    match shading_model {
+        ShadingModel::BlinnPhong => (shader::defs::F_SPECULAR, "vec2(0.0, 1.0)"),
+        ShadingModel::PhysicallyBased => (shader::defs::F_MATERIAL, "vec3(0.0, 0.8, 1.0)"),
+    }
+}
+
 const V_LIGHT_POS: (&str, shader::VertexOutDef) = (
     "v_light_pos",
     shader::VertexOutDef(shader::Type::FloatVec3, shader::VertexOutQualifier::Flat),
@@ -74,81 +126,327 @@ const V_LIGHT_ATTENUATION: (&str, shader::VertexOutDef) = (
     shader::VertexOutDef(shader::Type::FloatVec4, shader::VertexOutQualifier::Flat),
 );
 
-fn light_fragment_core() -> shader::FragmentCore<Camera> {
-    shader::FragmentCore::empty()
-        .with_extra_uniform("position_texture", UniformType::Sampler2d)
-        .with_extra_uniform("normal_texture", UniformType::Sampler2d)
+const V_LIGHT_DIRECTION: (&str, shader::VertexOutDef) = (
+    "v_light_direction",
+    shader::VertexOutDef(shader::Type::FloatVec3, shader::VertexOutQualifier::Flat),
+);
+
+const V_LIGHT_CONE: (&str, shader::VertexOutDef) = (
+    "v_light_cone",
+    shader::VertexOutDef(shader::Type::FloatVec3, shader::VertexOutQualifier::Flat),
+);
+
+const V_EYE_POS: (&str, shader::VertexOutDef) = (
+    "v_eye_pos",
+    shader::VertexOutDef(shader::Type::FloatVec3, shader::VertexOutQualifier::Flat),
+);
+
+const V_LIGHT_RADIUS: (&str, shader::VertexOutDef) = (
+    "v_light_radius",
+    shader::VertexOutDef(shader::Type::Float, shader::VertexOutQualifier::Flat),
+);
+
+/// Vertex core that passes on the light's parameters to the fragment shader.
+fn light_vertex_core<V>() -> shader::VertexCore<Camera, Light, V> {
+    shader::VertexCore::default()
+        .with_out(V_LIGHT_POS, "light_position")
+        .with_out(V_LIGHT_COLOR, "light_color")
+        .with_out(V_LIGHT_ATTENUATION, "light_attenuation")
+        .with_out(V_LIGHT_DIRECTION, "light_direction")
+        .with_out(V_LIGHT_CONE, "light_cone")
+        .with_out(V_LIGHT_RADIUS, "light_radius")
+        .with_out(
+            V_EYE_POS,
+            "-transpose(mat3(camera_view)) * camera_view[3].xyz",
+        )
+}
+
+/// Scale of the sphere mesh relative to the light's radius when drawing
+/// light volumes. The sphere mesh has a radius of 0.5, so it needs to be
+/// scaled by two. Since it is tessellated into 10 stacks and sectors, its
+/// faces come as close as 0.488 to the center, so we add some slack to make
+/// sure that the volume encloses the whole radius.
+const LIGHT_VOLUME_SCALE: f32 = 2.1;
+
+const BLINN_PHONG_SHADING: &str = "
+    // Material texture holds specular intensity and shininess
+    float specular = n_dot_l > 0.0
+        ? material.x * pow(max(dot(normal, half_dir), 0.0), material.y)
+        : 0.0;
+
+    vec3 radiance = vec3((n_dot_l + specular) * intensity);
+";
+
+const PHYSICALLY_BASED_SHADING: &str = "
+    // Cook-Torrance BRDF. For reference:
+    // https://learnopengl.com/PBR/Lighting
+    //
+    // Material texture holds metallic, roughness and ambient occlusion.
+    float metallic = material.x;
+    float roughness = material.y;
+
+    float n_dot_v = max(dot(normal, view_dir), 0.0001);
+    float n_dot_h = max(dot(normal, half_dir), 0.0);
+    float h_dot_v = max(dot(half_dir, view_dir), 0.0);
+
+    // Normal distribution (Trowbridge-Reitz GGX)
+    float alpha = roughness * roughness;
+    float alpha_sq = alpha * alpha;
+    float ndf_denom = n_dot_h * n_dot_h * (alpha_sq - 1.0) + 1.0;
+    float ndf = alpha_sq / (3.14159265 * ndf_denom * ndf_denom);
+
+    // Geometry (Smith with Schlick-GGX)
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float geometry = n_dot_v / (n_dot_v * (1.0 - k) + k)
+        * n_dot_l / (n_dot_l * (1.0 - k) + k);
+
+    // Fresnel (Schlick)
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+    vec3 fresnel = f0 + (1.0 - f0) * pow(1.0 - h_dot_v, 5.0);
+
+    vec3 specular = ndf * geometry * fresnel / (4.0 * n_dot_v * n_dot_l + 0.0001);
+    vec3 k_diffuse = (vec3(1.0) - fresnel) * (1.0 - metallic);
+
+    vec3 radiance = (k_diffuse * albedo / 3.14159265 + specular) * n_dot_l * intensity;
+";
+
+/// Returns the uniform type of the scene buffers that are read in the light
+/// pass.
+fn scene_buffer_type(num_samples: Option<u32>) -> UniformType {
+    if num_samples.is_some() {
+        UniformType::Sampler2dMultisample
+    } else {
+        UniformType::Sampler2d
+    }
+}
+
+/// GLSL expression for the intensity of a light at `light_distance` and
+/// `light_distance_sq`, given the names of the light's attenuation and radius.
+///
+/// This is shared with clustered forward lighting, so that lights look the
+/// same in both paths.
+pub(crate) fn light_falloff_expr(falloff: LightFalloff, attenuation: &str, radius: &str) -> String {
+    match falloff {
+        LightFalloff::Attenuation => format!(
+            "
+            1.0 / (
+                dot({attenuation}.xyz, vec3(1, light_distance, light_distance_sq))
+                * exp({attenuation}.w * light_distance_sq)
+            )
+            ",
+            attenuation = attenuation,
+        ),
+        LightFalloff::InverseSquare => {
+            // Windowed so that the light reaches zero at its radius, as in:
+            // https://seblagarde.files.wordpress.com/2015/07/course_notes_moving_frostbite_to_pbr_v32.pdf
+            format!(
+                "
+                pow(clamp(1.0 - pow(light_distance / {radius}, 4.0), 0.0, 1.0), 2.0)
+                    / max(light_distance_sq, 0.0001)
+                ",
+                radius = radius,
+            )
+        }
+    }
+}
+
+/// Fragment core for evaluating a light in the light pass.
+///
+/// With `num_samples`, the scene buffers are multisampled. Pixels whose
+/// samples all belong to the same primitive are lit once, while edge pixels
+/// are lit per sample and averaged.
+///
+/// The `shadow` expression is evaluated for each sample and multiplied with
+/// the light's contribution. It can refer to `position`, `normal` and
+/// `sample_index`.
+fn light_fragment_core(
+    config: &Config,
+    num_samples: Option<u32>,
+    shadow: &str,
+) -> shader::FragmentCore<Camera> {
+    let falloff = light_falloff_expr(
+        config.light_falloff,
+        "v_light_attenuation",
+        "v_light_radius",
+    );
+
+    let shading = match config.shading_model {
+        ShadingModel::BlinnPhong => BLINN_PHONG_SHADING,
+        ShadingModel::PhysicallyBased => PHYSICALLY_BASED_SHADING,
+    };
+
+    let scene_buffer_fetch = if let Some(num_samples) = num_samples {
+        format!(
+            "
+            const int NUM_SAMPLES = {num_samples};
+
+            vec4 scene_buffer_fetch(sampler2DMS buffer, int sample_index) {{
+                return texelFetch(buffer, ivec2(gl_FragCoord.xy), sample_index);
+            }}
+
+            // Samples that were written by the same fragment hold the same
+            // values, so any difference means that we are at an edge.
+            bool is_edge_pixel() {{
+                vec4 position = scene_buffer_fetch(position_texture, 0);
+                vec4 normal = scene_buffer_fetch(normal_texture, 0);
+
+                for (int i = 1; i < NUM_SAMPLES; i++) {{
+                    if (scene_buffer_fetch(position_texture, i) != position
+                        || scene_buffer_fetch(normal_texture, i) != normal)
+                        return true;
+                }}
+
+                return false;
+            }}
+            ",
+            num_samples = num_samples,
+        )
+    } else {
+        "
+        const int NUM_SAMPLES = 1;
+
+        vec4 scene_buffer_fetch(sampler2D buffer, int sample_index) {
+            return texture(buffer, gl_FragCoord.xy / camera_viewport_size);
+        }
+
+        bool is_edge_pixel() {
+            return false;
+        }
+        "
+        .to_owned()
+    };
+
+    // Only the physically based shading model needs the albedo per sample.
+    // With Blinn-Phong, it is applied in the composition pass.
+    let albedo = match config.shading_model {
+        ShadingModel::BlinnPhong => "vec3(0.0)",
+        ShadingModel::PhysicallyBased => "scene_buffer_fetch(albedo_texture, sample_index).rgb",
+    };
+
+    let mut fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("position_texture", scene_buffer_type(num_samples))
+        .with_extra_uniform("normal_texture", scene_buffer_type(num_samples))
+        .with_extra_uniform("material_texture", scene_buffer_type(num_samples))
         .with_in_def(V_LIGHT_POS)
         .with_in_def(V_LIGHT_COLOR)
         .with_in_def(V_LIGHT_ATTENUATION)
-        .with_body(
+        .with_in_def(V_LIGHT_DIRECTION)
+        .with_in_def(V_LIGHT_CONE)
+        .with_in_def(V_LIGHT_RADIUS)
+        .with_in_def(V_EYE_POS);
+
+    if config.shading_model == ShadingModel::PhysicallyBased {
+        fragment = fragment.with_extra_uniform("albedo_texture", scene_buffer_type(num_samples));
+    }
+
+    fragment
+        .with_defs(&scene_buffer_fetch)
+        .with_defs(&format!(
             "
-            vec2 tex_coord = gl_FragCoord.xy / camera_viewport_size;
-            vec3 position = texture(position_texture, tex_coord).xyz;
-            vec3 normal = texture(normal_texture, tex_coord).xyz;
+            vec3 light_radiance(vec3 position, vec3 normal, vec3 material, vec3 albedo) {{
+                vec3 light_dir;
+                float intensity;
+
+                if (v_light_cone.z > 0.5) {{
+                    // Directional light
+                    light_dir = -v_light_direction;
+                    intensity = 1.0;
+                }} else {{
+                    vec3 light_vector = v_light_pos - position;
+                    float light_distance_sq = dot(light_vector, light_vector);
+                    float light_distance = sqrt(light_distance_sq);
+
+                    light_dir = light_vector / light_distance;
+                    intensity = {falloff};
+                }}
 
-            vec3 light_vector = v_light_pos - position;
-            float light_distance_sq = dot(light_vector, light_vector);
-            float light_distance = sqrt(light_distance_sq);
+                // Spot light falloff. For other kinds of lights, the cone
+                // parameters are chosen such that this evaluates to 1.
+                intensity *= smoothstep(
+                    v_light_cone.y,
+                    v_light_cone.x,
+                    dot(-light_dir, v_light_direction)
+                );
 
-            float diffuse = max(dot(normal, light_vector / light_distance), 0.0);
-            float attenuation = dot(
-                v_light_attenuation.xyz,
-                vec3(1, light_distance, light_distance_sq)
-            ) * exp(v_light_attenuation.w * light_distance_sq);
-            diffuse /= attenuation;
+                vec3 view_dir = normalize(v_eye_pos - position);
+                vec3 half_dir = normalize(light_dir + view_dir);
+                float n_dot_l = max(dot(normal, light_dir), 0.0);
+
+                {shading}
+
+                return v_light_color * radiance;
+            }}
+            ",
+            falloff = falloff,
+            shading = shading,
+        ))
+        .with_body(&format!(
+            "
+            int num_light_samples = is_edge_pixel() ? NUM_SAMPLES : 1;
+            vec3 light_color = vec3(0.0);
+
+            for (int sample_index = 0; sample_index < num_light_samples; sample_index++) {{
+                vec3 position = scene_buffer_fetch(position_texture, sample_index).xyz;
+                vec3 normal = scene_buffer_fetch(normal_texture, sample_index).xyz;
+                vec3 material = scene_buffer_fetch(material_texture, sample_index).xyz;
+                vec3 albedo = {albedo};
+
+                light_color += light_radiance(position, normal, material, albedo) * ({shadow});
+            }}
+
+            light_color /= float(num_light_samples);
 
             // Discarding here means that additive blending does not need to
             // be performed. This got me a speed-up in scenes with many lights.
-            if (diffuse < 0.0001)
+            if (max(light_color.r, max(light_color.g, light_color.b)) < 0.0001)
                 discard;
-
-            float radiance = diffuse;
-            //float radiance = 1.0;
             ",
-        )
-        .with_out(shader::defs::F_COLOR, "vec4(v_light_color * radiance, 1.0)")
+            albedo = albedo,
+            shadow = shadow,
+        ))
+        .with_out(shader::defs::F_COLOR, "vec4(light_color, 1.0)")
 }
 
 /// Shader core for rendering a light source, given the position/normal buffers
 /// from the scene pass.
 pub fn main_light_screen_quad_core(
+    config: &Config,
+    num_samples: Option<u32>,
     have_shadows: bool,
 ) -> shader::Core<Camera, Light, screen_quad::Vertex> {
-    let vertex = shader::VertexCore::default()
-        .with_out(V_LIGHT_POS, "light_position")
-        .with_out(V_LIGHT_COLOR, "light_color")
-        .with_out(V_LIGHT_ATTENUATION, "light_attenuation")
-        .with_out(shader::defs::V_POS, "position");
+    let vertex = light_vertex_core().with_out(shader::defs::V_POS, "position");
 
-    let mut fragment = light_fragment_core();
-    if have_shadows {
-        fragment = fragment
-            .with_extra_uniform("shadow_texture", UniformType::Sampler2d)
-            .with_out_expr(
-                "f_color",
-                "vec4(f_color.rgb * texture(shadow_texture, tex_coord).r, 1.0)",
-            );
-    }
+    let fragment = if have_shadows {
+        light_fragment_core(
+            config,
+            num_samples,
+            "scene_buffer_fetch(shadow_texture, sample_index).r",
+        )
+        .with_extra_uniform("shadow_texture", scene_buffer_type(num_samples))
+    } else {
+        light_fragment_core(config, num_samples, "1.0")
+    };
 
     shader::Core { vertex, fragment }
 }
 
-pub fn light_object_core() -> shader::Core<Camera, Light, basic_obj::Vertex> {
-    let vertex = shader::VertexCore::default()
-        .with_out(V_LIGHT_POS, "light_position")
-        .with_out(V_LIGHT_COLOR, "light_color")
-        .with_out(V_LIGHT_ATTENUATION, "light_attenuation")
-        .with_out(
-            shader::defs::V_POS,
+pub fn light_object_core(
+    config: &Config,
+    num_samples: Option<u32>,
+) -> shader::Core<Camera, Light, basic_obj::Vertex> {
+    let vertex = light_vertex_core().with_out(
+        shader::defs::V_POS,
+        &format!(
             "
                 camera_projection
                 * camera_view
-                * (vec4(position * light_radius, 1.0) + vec4(light_position, 0))
+                * (vec4(position * light_radius * {scale:.2}, 1.0) + vec4(light_position, 0))
             ",
-        );
+            scale = LIGHT_VOLUME_SCALE,
+        ),
+    );
 
-    let fragment = light_fragment_core();
+    let fragment = light_fragment_core(config, num_samples, "1.0");
 
     // Uncomment the following line to debug light volumes:
     //let fragment = fragment.with_out_expr("f_color", "vec4(1, 1, 1, 1)");
@@ -156,8 +454,113 @@ pub fn light_object_core() -> shader::Core<Camera, Light, basic_obj::Vertex> {
     shader::Core { vertex, fragment }
 }
 
+/// Shader core for rendering a spot light, using a cone as its light volume.
+///
+/// The cone mesh is expected to have its apex at the origin and to open
+/// towards positive Z, with a length and base radius of 1.
+pub fn spot_light_object_core(
+    config: &Config,
+    num_samples: Option<u32>,
+) -> shader::Core<Camera, Light, basic_obj::Vertex> {
+    let vertex = light_vertex_core()
+        .with_body(
+            "
+            // Rotate the cone so that it opens towards the light direction
+            vec3 cone_up = abs(light_direction.z) < 0.99 ? vec3(0, 0, 1) : vec3(1, 0, 0);
+            vec3 cone_x = normalize(cross(cone_up, light_direction));
+            vec3 cone_y = cross(light_direction, cone_x);
+
+            float cos_outer = light_cone.y;
+            float cone_radius = light_radius * sqrt(1.0 - cos_outer * cos_outer) / cos_outer;
+
+            vec3 cone_pos = light_position
+                + light_direction * position.z * light_radius
+                + (cone_x * position.x + cone_y * position.y) * cone_radius;
+            ",
+        )
+        .with_out(
+            shader::defs::V_POS,
+            "camera_projection * camera_view * vec4(cone_pos, 1.0)",
+        );
+
+    let fragment = light_fragment_core(config, num_samples, "1.0");
+
+    shader::Core { vertex, fragment }
+}
+
+/// Shader core for rendering directional lights, which cover the whole
+/// screen.
+pub fn directional_light_screen_quad_core(
+    config: &Config,
+    num_samples: Option<u32>,
+) -> shader::Core<Camera, Light, screen_quad::Vertex> {
+    let vertex = light_vertex_core().with_out(shader::defs::V_POS, "position");
+    let fragment = light_fragment_core(config, num_samples, "1.0");
+
+    shader::Core { vertex, fragment }
+}
+
+/// Shader core for rendering a point light that casts shadows. The light's
+/// depth cube map is compared against the distance of each fragment to the
+/// light.
+///
+/// The cube map is expected to have been rendered with a perspective
+/// projection ranging from `near` to the light's radius.
+pub fn shadowed_light_object_core(
+    config: &Config,
+    num_samples: Option<u32>,
+    near: f32,
+    cube_size: u32,
+) -> shader::Core<Camera, Light, basic_obj::Vertex> {
+    let vertex = light_object_core(config, num_samples).vertex;
+
+    let shadow_calculation = format!(
+        "
+        float point_shadow_calculation(vec3 position, vec3 normal) {{
+            vec3 light_to_frag = position - v_light_pos;
+
+            // Offset along the normal by roughly one texel in order to avoid
+            // shadow acne. Texels grow linearly with the distance.
+            float major_axis = max(
+                max(abs(light_to_frag.x), abs(light_to_frag.y)),
+                abs(light_to_frag.z)
+            );
+            light_to_frag += normal * (0.02 + 2.0 * major_axis / {cube_size:.1});
+
+            // Convert the distance along the major axis to the same
+            // nonlinear depth that is stored in the cube map.
+            float z = max(
+                max(abs(light_to_frag.x), abs(light_to_frag.y)),
+                abs(light_to_frag.z)
+            );
+            float n = {near:.4};
+            float f = max(v_light_radius, 2.0 * n);
+            float depth = ((f + n) / (f - n) - 2.0 * f * n / ((f - n) * z)) * 0.5 + 0.5;
+
+            float closest_depth = texture(light_shadow_map, light_to_frag).r;
+
+            return depth > closest_depth ? 0.0 : 1.0;
+        }}
+        ",
+        near = near,
+        cube_size = cube_size as f32,
+    );
+
+    let fragment = light_fragment_core(
+        config,
+        num_samples,
+        "point_shadow_calculation(position, normal)",
+    )
+    .with_extra_uniform("light_shadow_map", UniformType::SamplerCube)
+    .with_defs(&shadow_calculation);
+
+    shader::Core { vertex, fragment }
+}
+
 /// Composition shader core transform for composing our buffers.
 pub fn composition_core_transform(
+    shading_model: ShadingModel,
+    have_emissive: bool,
     core: shader::Core<Context, (), screen_quad::Vertex>,
 ) -> shader::Core<Context, (), screen_quad::Vertex> {
     assert!(
@@ -172,21 +575,58 @@ pub fn composition_core_transform(
     let fragment = core
         .fragment
         .with_extra_uniform("light_texture", UniformType::Sampler2d)
-        .with_extra_uniform("normal_texture", UniformType::Sampler2d)
-        .with_body(
-            "
-            vec4 light_value = texture(light_texture, v_tex_coord);
-            vec4 normal_value = texture(normal_texture, v_tex_coord);
+        .with_extra_uniform("normal_texture", UniformType::Sampler2d);
 
-            vec4 lighting = vec4(light_value.rgb + context_ambient_light, 1.0);
+    let fragment = match shading_model {
+        ShadingModel::BlinnPhong => fragment
+            .with_body(
+                "
+                vec4 light_value = texture(light_texture, v_tex_coord);
+                vec4 normal_value = texture(normal_texture, v_tex_coord);
 
-            // Keep background color as-is.
-            // TODO: There are definitely more efficient ways to do this,
-            // without having to read the normal texture.
-            lighting += step(0.001, 1.0 - length(normal_value.rgb)) * vec4(1.0, 1.0, 1.0, 0.0);
-            ",
-        )
-        .with_out_expr("f_color", "f_color * lighting");
+                vec4 lighting = vec4(light_value.rgb + context_ambient_light, 1.0);
+
+                // Keep background color as-is. The alpha of the normal buffer
+                // is zero for geometry and one for the background. With MSAA,
+                // it holds the fraction of background samples in the pixel.
+                // TODO: There are definitely more efficient ways to do this,
+                // without having to read the normal texture.
+                lighting += normal_value.a * vec4(1.0, 1.0, 1.0, 0.0);
+                ",
+            )
+            .with_out_expr("f_color", "f_color * lighting"),
+        ShadingModel::PhysicallyBased => fragment
+            .with_extra_uniform("material_texture", UniformType::Sampler2d)
+            .with_body(
+                "
+                vec4 light_value = texture(light_texture, v_tex_coord);
+                vec4 normal_value = texture(normal_texture, v_tex_coord);
+                float ambient_occlusion = texture(material_texture, v_tex_coord).z;
+
+                // Keep background color as-is. The alpha of the normal buffer
+                // holds the fraction of background samples in the pixel.
+                float background = normal_value.a;
+
+                // The light buffer already contains the albedo, so we only
+                // need to add ambient light.
+                vec3 lit_color = f_color.rgb
+                    * mix(context_ambient_light * ambient_occlusion, vec3(1.0), background)
+                    + light_value.rgb;
+                ",
+            )
+            .with_out_expr("f_color", "vec4(lit_color, f_color.a)"),
+    };
+
+    let fragment = if have_emissive {
+        fragment
+            .with_extra_uniform("emissive_texture", UniformType::Sampler2d)
+            .with_out_expr(
+                "f_color",
+                "f_color + vec4(texture(emissive_texture, v_tex_coord).rgb, 0.0)",
+            )
+    } else {
+        fragment
+    };
 
     shader::Core {
         vertex: core.vertex,
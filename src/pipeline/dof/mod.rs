@@ -0,0 +1,303 @@
+//! Depth of field.
+//!
+//! The circle of confusion of each pixel is computed from the scene depth
+//! with a thin lens model. At half resolution, we then gather the blurred
+//! far field, i.e. everything behind the focus distance, and the near field
+//! in front of it. The near field keeps track of its coverage, so that
+//! blurry foreground objects spread over sharp pixels behind them. Finally,
+//! both fields are composited with the sharp scene at full resolution.
+
+pub mod shaders;
+
+use log::info;
+
+use nalgebra as na;
+
+use glium::framebuffer::{MultiOutputFrameBuffer, SimpleFrameBuffer};
+use glium::texture::{DepthTexture2d, MipmapsOption, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+use glium::{uniform, Program, Surface, Texture2d};
+
+use crate::shader::ToUniforms;
+use crate::{shader, Context, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+/// Parameters of the camera lens, given in world units.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Lens {
+    /// Distance from the camera at which objects are perfectly sharp.
+    pub focus_distance: f32,
+
+    pub focal_length: f32,
+
+    /// Ratio of focal length to aperture diameter. Smaller values give
+    /// stronger blur.
+    pub f_number: f32,
+
+    /// Height of the camera sensor. Together with the focal length, this
+    /// should match the field of view of the projection.
+    pub sensor_height: f32,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        // 50mm lens on a 35mm camera, assuming that world units are meters.
+        Self {
+            focus_distance: 10.0,
+            focal_length: 0.05,
+            f_number: 2.8,
+            sensor_height: 0.024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    pub lens: Lens,
+
+    /// Maximal radius of the circle of confusion, in pixels.
+    pub max_coc_radius: f32,
+
+    /// Number of samples that are gathered for blurring each pixel.
+    pub num_samples: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            lens: Default::default(),
+            max_coc_radius: 8.0,
+            num_samples: 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Params {
+    lens: Lens,
+    max_coc_radius: f32,
+    inverse_projection: na::Matrix4<f32>,
+}
+
+impl_uniform_input!(
+    Params,
+    self => {
+        dof_focus_distance: f32 = self.lens.focus_distance,
+        dof_focal_length: f32 = self.lens.focal_length,
+        dof_aperture: f32 = self.lens.focal_length / self.lens.f_number,
+        dof_sensor_height: f32 = self.lens.sensor_height,
+        dof_max_coc_radius: f32 = self.max_coc_radius,
+        dof_inverse_projection: [[f32; 4]; 4] = self.inverse_projection,
+    },
+);
+
+pub struct DepthOfField {
+    config: Config,
+
+    /// Downsampled scene color, with the circle of confusion in the alpha
+    /// channel.
+    coc_texture: Texture2d,
+
+    far_texture: Texture2d,
+    near_texture: Texture2d,
+    output_texture: Texture2d,
+
+    coc_program: Program,
+    blur_program: Program,
+    composite_program: Program,
+
+    screen_quad: ScreenQuad,
+}
+
+impl DepthOfField {
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        let [coc_texture, far_texture, near_texture, output_texture] =
+            Self::create_textures(facade, target_size)?;
+
+        info!("Creating depth of field programs");
+        let coc_program =
+            shaders::coc_core().build_program(facade, shader::InstancingMode::Uniforms)?;
+        let blur_program = shaders::blur_core(config.num_samples)
+            .build_program(facade, shader::InstancingMode::Uniforms)?;
+        let composite_program =
+            shaders::composite_core().build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        info!("Creating screen quad");
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(DepthOfField {
+            config: config.clone(),
+            coc_texture,
+            far_texture,
+            near_texture,
+            output_texture,
+            coc_program,
+            blur_program,
+            composite_program,
+            screen_quad,
+        })
+    }
+
+    pub fn lens(&self) -> &Lens {
+        &self.config.lens
+    }
+
+    pub fn set_lens(&mut self, lens: Lens) {
+        self.config.lens = lens;
+    }
+
+    /// Texture holding the result of the last `draw` call.
+    pub fn output_texture(&self) -> &Texture2d {
+        &self.output_texture
+    }
+
+    /// Applies depth of field to `color_texture`, writing the result into
+    /// `output_texture`.
+    pub fn draw<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        color_texture: &Texture2d,
+        depth_texture: &DepthTexture2d,
+        context: &Context,
+    ) -> Result<(), DrawError> {
+        let params = Params {
+            lens: self.config.lens.clone(),
+            max_coc_radius: self.config.max_coc_radius,
+            inverse_projection: context
+                .camera
+                .projection
+                .try_inverse()
+                .unwrap_or_else(na::Matrix4::identity),
+        };
+
+        let color_sampler = Self::linear_sampler(color_texture);
+        let coc_sampler = Self::linear_sampler(&self.coc_texture);
+
+        {
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &self.coc_texture)?;
+
+            let textures = uniform! {
+                color_texture: color_sampler,
+                scene_depth_texture: depth_texture,
+            };
+
+            framebuffer.draw(
+                &self.screen_quad.vertex_buffer,
+                &self.screen_quad.index_buffer,
+                &self.coc_program,
+                &(context, &params, &textures).to_uniforms(),
+                &Default::default(),
+            )?;
+        }
+
+        {
+            let mut framebuffer = MultiOutputFrameBuffer::new(
+                facade,
+                vec![
+                    (shaders::F_DOF_FAR.0, &self.far_texture),
+                    (shaders::F_DOF_NEAR.0, &self.near_texture),
+                ],
+            )?;
+
+            let textures = uniform! {
+                coc_texture: coc_sampler,
+            };
+
+            framebuffer.draw(
+                &self.screen_quad.vertex_buffer,
+                &self.screen_quad.index_buffer,
+                &self.blur_program,
+                &(context, &params, &textures).to_uniforms(),
+                &Default::default(),
+            )?;
+        }
+
+        {
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &self.output_texture)?;
+
+            let textures = uniform! {
+                color_texture: color_texture,
+                scene_depth_texture: depth_texture,
+                far_texture: Self::linear_sampler(&self.far_texture),
+                near_texture: Self::linear_sampler(&self.near_texture),
+            };
+
+            framebuffer.draw(
+                &self.screen_quad.vertex_buffer,
+                &self.screen_quad.index_buffer,
+                &self.composite_program,
+                &(context, &params, &textures).to_uniforms(),
+                &Default::default(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        let [coc_texture, far_texture, near_texture, output_texture] =
+            Self::create_textures(facade, target_size)?;
+
+        self.coc_texture = coc_texture;
+        self.far_texture = far_texture;
+        self.near_texture = near_texture;
+        self.output_texture = output_texture;
+
+        Ok(())
+    }
+
+    fn linear_sampler(texture: &Texture2d) -> Sampler<'_, Texture2d> {
+        Sampler::new(texture)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .wrap_function(SamplerWrapFunction::Clamp)
+    }
+
+    /// Creates the half resolution textures for blurring, followed by the
+    /// full resolution output texture.
+    fn create_textures<F: glium::backend::Facade>(
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<[Texture2d; 4], CreationError> {
+        let half_size = (target_size.0.div_ceil(2), target_size.1.div_ceil(2));
+
+        Ok([
+            Self::create_texture(facade, half_size)?,
+            Self::create_texture(facade, half_size)?,
+            Self::create_texture(facade, half_size)?,
+            Self::create_texture(facade, target_size)?,
+        ])
+    }
+
+    fn create_texture<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<Texture2d, CreationError> {
+        Ok(Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+}
@@ -0,0 +1,164 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader, Context};
+
+use super::Params;
+
+pub const F_DOF_FAR: (&str, shader::FragmentOutDef) = (
+    "f_dof_far",
+    shader::FragmentOutDef(shader::Type::FloatVec4, shader::FragmentOutQualifier::Yield),
+);
+
+pub const F_DOF_NEAR: (&str, shader::FragmentOutDef) = (
+    "f_dof_near",
+    shader::FragmentOutDef(shader::Type::FloatVec4, shader::FragmentOutQualifier::Yield),
+);
+
+/// Defines `dof_coc`, which computes the signed radius of the circle of
+/// confusion in pixels for a value of the depth buffer. The radius is
+/// negative in front of the focus distance.
+const COC_DEFS: &str = "
+    float dof_coc(vec2 tex_coord, float depth) {
+        vec4 view_pos = dof_inverse_projection * vec4(vec3(tex_coord, depth) * 2.0 - 1.0, 1.0);
+        float z = max(-view_pos.z / view_pos.w, 1e-5);
+
+        // Thin lens model
+        float coc = dof_aperture * dof_focal_length * (z - dof_focus_distance)
+            / (z * max(dof_focus_distance - dof_focal_length, 1e-5));
+
+        // Convert diameter on the sensor into radius in pixels
+        float radius = 0.5 * coc / dof_sensor_height * context_camera_viewport_size.y;
+
+        return clamp(radius, -dof_max_coc_radius, dof_max_coc_radius);
+    }
+";
+
+fn screen_quad_vertex() -> shader::VertexCore<(Context, Params), (), screen_quad::Vertex> {
+    shader::VertexCore::empty()
+        .with_out(shader::defs::V_TEX_COORD, "tex_coord")
+        .with_out(shader::defs::V_POS, "position")
+}
+
+/// Shader core for downsampling the scene color, storing the circle of
+/// confusion in the alpha channel.
+pub fn coc_core() -> shader::Core<(Context, Params), (), screen_quad::Vertex> {
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("color_texture", UniformType::Sampler2d)
+        .with_extra_uniform("scene_depth_texture", UniformType::Sampler2d)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_defs(COC_DEFS)
+        .with_out(
+            shader::defs::F_COLOR,
+            "vec4(
+                texture(color_texture, v_tex_coord).rgb,
+                dof_coc(v_tex_coord, texture(scene_depth_texture, v_tex_coord).r)
+            )",
+        );
+
+    shader::Core {
+        vertex: screen_quad_vertex(),
+        fragment,
+    }
+}
+
+/// Shader core for blurring the near and far field.
+///
+/// We gather samples on a disk, following the spiral approach of:
+/// http://blog.tuxedolabs.com/2018/05/04/bokeh-depth-of-field-in-single-pass.html
+///
+/// The far field only takes samples behind the focus distance, so that sharp
+/// objects do not bleed into the blurred background. The near field takes
+/// samples in front of the focus distance, whose blur may extend onto
+/// sharp pixels. Its alpha channel holds the coverage.
+pub fn blur_core(num_samples: usize) -> shader::Core<(Context, Params), (), screen_quad::Vertex> {
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("coc_texture", UniformType::Sampler2d)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_body(&format!(
+            "
+            const int NUM_SAMPLES = {num_samples};
+            const float GOLDEN_ANGLE = 2.39996323;
+
+            vec4 center = texture(coc_texture, v_tex_coord);
+
+            vec3 far_color = center.rgb;
+            float far_weight = 1.0;
+
+            vec3 near_color = vec3(0.0);
+            float near_weight = 0.0;
+            float near_max_radius = 0.0;
+
+            for (int i = 0; i < NUM_SAMPLES; i++) {{
+                float radius = sqrt((float(i) + 0.5) / float(NUM_SAMPLES)) * dof_max_coc_radius;
+                float angle = float(i) * GOLDEN_ANGLE;
+                vec2 offset = vec2(cos(angle), sin(angle)) * radius;
+
+                vec4 s = texture(coc_texture, v_tex_coord + offset / context_camera_viewport_size);
+
+                if (s.a > 0.0) {{
+                    // Do not let the background spread over pixels in front
+                    float size = s.a > center.a ? min(s.a, max(center.a, 0.0) * 2.0) : s.a;
+                    float m = smoothstep(radius - 0.5, radius + 0.5, size);
+
+                    far_color += mix(far_color / far_weight, s.rgb, m);
+                    far_weight += 1.0;
+                }} else {{
+                    float m = smoothstep(radius - 0.5, radius + 0.5, -s.a);
+
+                    near_color += s.rgb * m;
+                    near_weight += m;
+                    near_max_radius = max(near_max_radius, -s.a * step(0.001, m));
+                }}
+            }}
+
+            // Normalize coverage by the fraction of samples that lie within
+            // the largest near field radius.
+            float near_area = near_max_radius / dof_max_coc_radius;
+            float near_coverage = clamp(
+                near_weight / (float(NUM_SAMPLES) * max(near_area * near_area, 1e-3)),
+                0.0,
+                1.0
+            );
+        ",
+            num_samples = num_samples,
+        ))
+        .with_out(F_DOF_FAR, "vec4(far_color / far_weight, 1.0)")
+        .with_out(
+            F_DOF_NEAR,
+            "vec4(near_color / max(near_weight, 1e-5), near_coverage)",
+        );
+
+    shader::Core {
+        vertex: screen_quad_vertex(),
+        fragment,
+    }
+}
+
+/// Shader core for compositing the blurred fields with the sharp scene.
+pub fn composite_core() -> shader::Core<(Context, Params), (), screen_quad::Vertex> {
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("color_texture", UniformType::Sampler2d)
+        .with_extra_uniform("scene_depth_texture", UniformType::Sampler2d)
+        .with_extra_uniform("far_texture", UniformType::Sampler2d)
+        .with_extra_uniform("near_texture", UniformType::Sampler2d)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_defs(COC_DEFS)
+        .with_body(
+            "
+            float coc = dof_coc(v_tex_coord, texture(scene_depth_texture, v_tex_coord).r);
+
+            vec3 color = texture(color_texture, v_tex_coord).rgb;
+            vec3 far = texture(far_texture, v_tex_coord).rgb;
+            vec4 near = texture(near_texture, v_tex_coord);
+
+            color = mix(color, far, smoothstep(0.5, 1.5, coc));
+            color = mix(color, near.rgb, near.a);
+            ",
+        )
+        .with_out(shader::defs::F_COLOR, "vec4(color, 1.0)");
+
+    shader::Core {
+        vertex: screen_quad_vertex(),
+        fragment,
+    }
+}
@@ -0,0 +1,185 @@
+//! Distance and height fog.
+//!
+//! For reference:
+//! https://iquilezles.org/articles/fog/
+//!
+//! Fog is applied in the composition pass, before tone mapping, by
+//! reconstructing the world position of each pixel from the scene depth
+//! texture. The amount of fog grows exponentially with the distance to the
+//! camera. Additionally, height fog has a density that falls off
+//! exponentially with increasing height, i.e. along the Z axis, which we
+//! integrate along the view ray.
+
+pub mod shaders;
+
+use nalgebra as na;
+
+use crate::pipeline::render_pass::{
+    CompositionPassComponent, HasCompositionPassParams, HasScenePassParams, RenderPassComponent,
+    ScenePassComponent,
+};
+use crate::{screen_quad, shader, Context, DrawError};
+
+/// Tints fog in the direction of the main light, as if it were scattering
+/// the light toward the camera.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct MainLightTint {
+    pub color: na::Vector3<f32>,
+
+    /// Larger values concentrate the tint around the main light direction.
+    pub exponent: f32,
+}
+
+impl Default for MainLightTint {
+    fn default() -> Self {
+        Self {
+            color: na::Vector3::new(1.0, 0.9, 0.7),
+            exponent: 8.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    pub color: na::Vector3<f32>,
+
+    /// Density of fog that depends only on the distance to the camera.
+    pub distance_density: f32,
+
+    /// Density of the height fog at `height_offset`.
+    pub height_density: f32,
+
+    /// Height at which the height fog has density `height_density`.
+    pub height_offset: f32,
+
+    /// How quickly the height fog thins out with increasing height.
+    pub height_falloff: f32,
+
+    pub main_light_tint: Option<MainLightTint>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            color: na::Vector3::new(0.5, 0.6, 0.7),
+            distance_density: 0.005,
+            height_density: 0.05,
+            height_offset: 0.0,
+            height_falloff: 0.5,
+            main_light_tint: None,
+        }
+    }
+}
+
+pub struct Fog {
+    config: Config,
+}
+
+impl RenderPassComponent for Fog {
+    fn clear_buffers<F: glium::backend::Facade>(&self, _: &F) -> Result<(), DrawError> {
+        Ok(())
+    }
+}
+
+pub struct Params {
+    config: Config,
+}
+
+impl_uniform_input!(
+    Params,
+    self => {
+        fog_color: [f32; 3] = self.config.color,
+        fog_distance_density: f32 = self.config.distance_density,
+        fog_height_density: f32 = self.config.height_density,
+        fog_height_offset: f32 = self.config.height_offset,
+        fog_height_falloff: f32 = self.config.height_falloff,
+        fog_main_light_tint_color: [f32; 3] = self
+            .config
+            .main_light_tint
+            .as_ref()
+            .map_or([0.0; 3], |tint| tint.color.into()),
+        fog_main_light_tint_exponent: f32 = self
+            .config
+            .main_light_tint
+            .as_ref()
+            .map_or(1.0, |tint| tint.exponent),
+    },
+);
+
+/// Parameters for reconstructing world positions in the composition pass.
+pub struct CompositionPassParams {
+    inverse_view_projection: na::Matrix4<f32>,
+}
+
+impl_uniform_input!(
+    CompositionPassParams,
+    self => {
+        fog_inverse_view_projection: [[f32; 4]; 4] = self.inverse_view_projection,
+    },
+);
+
+impl<'u> HasScenePassParams<'u> for Fog {
+    type Params = Params;
+}
+
+impl ScenePassComponent for Fog {
+    fn core_transform<P, I, V>(
+        &self,
+        core: shader::Core<(Context, P), I, V>,
+    ) -> shader::Core<(Context, P), I, V> {
+        shaders::scene_core_transform(self.config.main_light_tint.is_some(), core)
+    }
+
+    fn params(&self, _: &Context) -> Params {
+        Params {
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<'u> HasCompositionPassParams<'u> for Fog {
+    type Params = (Params, CompositionPassParams);
+}
+
+impl CompositionPassComponent for Fog {
+    fn core_transform(
+        &self,
+        core: shader::Core<Context, (), screen_quad::Vertex>,
+    ) -> shader::Core<Context, (), screen_quad::Vertex> {
+        shaders::composition_core_transform(self.config.main_light_tint.is_some(), core)
+    }
+
+    fn params(&self, context: &Context) -> (Params, CompositionPassParams) {
+        let camera = &context.camera;
+        let inverse_view_projection = (camera.projection * camera.view)
+            .try_inverse()
+            .unwrap_or_else(na::Matrix4::identity);
+
+        (
+            Params {
+                config: self.config.clone(),
+            },
+            CompositionPassParams {
+                inverse_view_projection,
+            },
+        )
+    }
+}
+
+impl Fog {
+    pub fn create(config: &Config) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+}
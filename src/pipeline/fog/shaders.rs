@@ -0,0 +1,132 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader, Context};
+
+/// Defines `fog`, which blends a color with the fog color, depending on the
+/// distance and the height of the given world position.
+fn fog_fragment_transform<P>(
+    main_light_tint: bool,
+    fragment: shader::FragmentCore<P>,
+) -> shader::FragmentCore<P> {
+    let fog_color_expr = if main_light_tint {
+        "
+            // Tint fog toward the main light
+            vec3 light_dir = normalize(context_main_light_pos - context_main_light_center);
+            float tint = pow(max(dot(view_dir, light_dir), 0.0), fog_main_light_tint_exponent);
+            vec3 scatter_color = mix(fog_color, fog_main_light_tint_color, tint);
+        "
+    } else {
+        "vec3 scatter_color = fog_color;"
+    };
+
+    let defs = format!(
+        "
+        vec3 fog(vec3 color, vec3 world_pos) {{
+            vec3 eye_pos = -transpose(mat3(context_camera_view)) * context_camera_view[3].xyz;
+            vec3 view_vector = world_pos - eye_pos;
+            float distance = length(view_vector);
+            vec3 view_dir = view_vector / distance;
+
+            // Exponential distance fog
+            float optical_depth = fog_distance_density * distance;
+
+            // Exponential height fog, integrated analytically along the view
+            // ray
+            float height_falloff = max(fog_height_falloff, 1e-5);
+            float eye_density = fog_height_density
+                * exp(-height_falloff * (eye_pos.z - fog_height_offset));
+            float falloff_z = height_falloff * view_vector.z;
+            float height_integral = abs(falloff_z) > 1e-4
+                ? (1.0 - exp(-falloff_z)) / falloff_z
+                : 1.0;
+            optical_depth += eye_density * distance * height_integral;
+
+            {fog_color_expr}
+
+            float fog_amount = 1.0 - exp(-max(optical_depth, 0.0));
+
+            return mix(color, scatter_color, fog_amount);
+        }}
+        ",
+        fog_color_expr = fog_color_expr,
+    );
+
+    let mut fragment = fragment
+        .with_extra_uniform("fog_color", UniformType::FloatVec3)
+        .with_extra_uniform("fog_distance_density", UniformType::Float)
+        .with_extra_uniform("fog_height_density", UniformType::Float)
+        .with_extra_uniform("fog_height_offset", UniformType::Float)
+        .with_extra_uniform("fog_height_falloff", UniformType::Float)
+        .with_defs(&defs);
+
+    if main_light_tint {
+        fragment = fragment
+            .with_extra_uniform("fog_main_light_tint_color", UniformType::FloatVec3)
+            .with_extra_uniform("fog_main_light_tint_exponent", UniformType::Float);
+    }
+
+    fragment
+}
+
+/// Shader core transform for applying fog to the composed scene.
+///
+/// The world position of each pixel is reconstructed from the scene depth
+/// texture. The background, i.e. pixels at the far plane, is not fogged.
+pub fn composition_core_transform(
+    main_light_tint: bool,
+    core: shader::Core<Context, (), screen_quad::Vertex>,
+) -> shader::Core<Context, (), screen_quad::Vertex> {
+    assert!(
+        core.fragment.has_out_def(shader::defs::F_COLOR),
+        "FragmentCore needs F_COLOR output for fog composition pass"
+    );
+
+    let fragment = fog_fragment_transform(main_light_tint, core.fragment)
+        .with_extra_uniform("scene_depth_texture", UniformType::Sampler2d)
+        .with_extra_uniform("fog_inverse_view_projection", UniformType::FloatMat4)
+        .with_body(
+            "
+            float fog_depth = texture(scene_depth_texture, v_tex_coord).r;
+
+            vec4 fog_clip_pos = vec4(vec3(v_tex_coord, fog_depth) * 2.0 - 1.0, 1.0);
+            vec4 fog_world_pos = fog_inverse_view_projection * fog_clip_pos;
+            fog_world_pos /= fog_world_pos.w;
+            ",
+        )
+        .with_out_expr(
+            "f_color",
+            "fog_depth < 1.0 ? vec4(fog(f_color.rgb, fog_world_pos.xyz), f_color.a) : f_color",
+        );
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
+
+/// Shader core transform for applying fog to objects in the scene pass.
+pub fn scene_core_transform<P, I, V>(
+    main_light_tint: bool,
+    core: shader::Core<(Context, P), I, V>,
+) -> shader::Core<(Context, P), I, V> {
+    assert!(
+        core.fragment.has_out_def(shader::defs::F_COLOR),
+        "FragmentCore needs F_COLOR output for fog"
+    );
+    assert!(
+        core.vertex.has_out_def(shader::defs::V_WORLD_POS),
+        "VertexCore needs V_WORLD_POS output for fog"
+    );
+
+    let fragment = fog_fragment_transform(main_light_tint, core.fragment)
+        .with_in_def(shader::defs::V_WORLD_POS)
+        .with_out_expr(
+            "f_color",
+            "vec4(fog(f_color.rgb, v_world_pos.xyz), f_color.a)",
+        );
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
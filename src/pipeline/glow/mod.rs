@@ -14,7 +14,12 @@ use crate::{screen_quad, shader, Context, DrawError, ScreenQuad};
 
 pub use crate::CreationError;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Config {
     pub num_blur_passes: usize,
 }
@@ -85,7 +90,7 @@ impl CompositionPassComponent for Glow {
         shaders::composition_core_transform(core)
     }
 
-    fn params(&self) -> CompositionPassParams {
+    fn params(&self, _: &Context) -> CompositionPassParams<'_> {
         CompositionPassParams {
             glow_texture: &self.glow_texture,
         }
@@ -15,7 +15,13 @@ pub const F_GLOW_COLOR: (&str, shader::FragmentOutDef) = (
 /// Shader core transform for rendering color into a texture so that it can be
 /// blurred and composed for a glow effect later in the pipeline.
 pub fn glow_map_core_transform<P, I, V>(core: shader::Core<P, I, V>) -> shader::Core<P, I, V> {
-    let fragment = core.fragment.with_out(F_GLOW_COLOR, "vec3(f_color)");
+    let glow_color = if core.fragment.has_out_def(shader::defs::F_EMISSIVE) {
+        "vec3(f_color) + f_emissive"
+    } else {
+        "vec3(f_color)"
+    };
+
+    let fragment = core.fragment.with_out(F_GLOW_COLOR, glow_color);
 
     shader::Core {
         vertex: core.vertex,
@@ -24,9 +30,16 @@ pub fn glow_map_core_transform<P, I, V>(core: shader::Core<P, I, V>) -> shader::
 }
 
 /// Shader core for non-glowing objects. This is necessary because otherwise
-/// glowing objects will glow through non-glowing objects.
+/// glowing objects will glow through non-glowing objects. Emissive light
+/// given by `F_EMISSIVE` still glows.
 pub fn no_glow_map_core_transform<P, I, V>(core: shader::Core<P, I, V>) -> shader::Core<P, I, V> {
-    let fragment = core.fragment.with_out(F_GLOW_COLOR, "vec3(0.0, 0.0, 0.0)");
+    let glow_color = if core.fragment.has_out_def(shader::defs::F_EMISSIVE) {
+        "f_emissive"
+    } else {
+        "vec3(0.0, 0.0, 0.0)"
+    };
+
+    let fragment = core.fragment.with_out(F_GLOW_COLOR, glow_color);
 
     shader::Core {
         vertex: core.vertex,
@@ -45,6 +58,24 @@ pub fn brighten_color_core_transform<P, I, V>(
     }
 }
 
+/// Shader core transform for adding emissive light to the color of
+/// fragments. This is used when the glow effect is disabled.
+pub fn emissive_color_core_transform<P, I, V>(
+    core: shader::Core<P, I, V>,
+) -> shader::Core<P, I, V> {
+    let fragment = if core.fragment.has_out_def(shader::defs::F_EMISSIVE) {
+        core.fragment
+            .with_out_expr("f_color", "f_color + vec4(f_emissive, 0.0)")
+    } else {
+        core.fragment
+    };
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
+
 /// Shader core for blurring the glow texture.
 pub fn blur_core() -> shader::Core<(), (), screen_quad::Vertex> {
     let vertex = shader::VertexCore::empty()
@@ -1,35 +1,70 @@
+pub mod capture;
+pub mod clustered;
+pub mod color_grading;
 mod config;
 pub mod deferred;
+pub mod dof;
+pub mod fog;
 pub mod glow;
+pub mod motion_blur;
+pub mod msaa;
+pub mod object_id;
+pub mod oit;
+pub mod outline;
 pub mod render_pass;
 pub mod shaders;
 pub mod shadow;
+pub mod taa;
+pub mod upscale;
+pub mod velocity;
 
 mod components;
 
 use coarse_prof::profile;
 use log::info;
+use nalgebra as na;
 
-use glium::framebuffer::{MultiOutputFrameBuffer, SimpleFrameBuffer};
+use glium::framebuffer::{
+    ColorAttachment, MultiOutputFrameBuffer, SimpleFrameBuffer, ToColorAttachment,
+    ToDepthAttachment,
+};
 use glium::texture::{
     DepthFormat, DepthTexture2d, MipmapsOption, Texture2d, UncompressedFloatFormat,
 };
 use glium::{uniform, Program, Surface};
 
 use crate::fxaa::{self, FXAA};
+use crate::pipeline::capture::DepthCapture;
+use crate::pipeline::dof::DepthOfField;
+use crate::pipeline::motion_blur::MotionBlur;
+use crate::pipeline::msaa::MSAA;
+use crate::pipeline::oit::OIT;
+use crate::pipeline::outline::Outline;
+use crate::pipeline::render_pass::ScenePassComponent;
+use crate::pipeline::taa::TAA;
+use crate::pipeline::upscale::Upscale;
 use crate::scene::{BuildProgram, CoreInput, SceneCore};
 use crate::shader::{InstancingMode, ToUniforms};
+use crate::smaa::{self, SMAA};
 use crate::{shader, Context, DrawError, Drawable, Light, ScreenQuad};
 
 use components::Components;
-use render_pass::CompositionPassComponent;
+use render_pass::{CompositionPassComponent, PassCore, PassProgram};
 
 pub use config::Config;
-pub use render_pass::{PlainScenePass, ShadedScenePass, ShadedScenePassSetup, ShadowPass};
+pub use render_pass::{
+    OutlinePass, PlainScenePass, ShadedScenePass, ShadedScenePassSetup, ShadowPass,
+    TransparentScenePass,
+};
 
 pub struct Pipeline {
+    config: Config,
     components: Components,
 
+    /// Incremented when reconfiguring changes the programs of passes, so
+    /// that passes know to rebuild them.
+    generation: u64,
+
     target_size: (u32, u32),
     scene_color_texture: Texture2d,
     scene_depth_texture: DepthTexture2d,
@@ -39,8 +74,16 @@ pub struct Pipeline {
     composition_program: Program,
     copy_texture_program: Program,
 
+    msaa: Option<MSAA>,
+    taa: Option<TAA>,
+    depth_of_field: Option<DepthOfField>,
+    motion_blur: Option<MotionBlur>,
     fxaa: Option<FXAA>,
+    smaa: Option<SMAA>,
+    outline: Option<Outline>,
+    upscale: Option<Upscale>,
 
+    depth_capture: DepthCapture,
     screen_quad: ScreenQuad,
 }
 
@@ -49,7 +92,12 @@ struct StepContext<'a, F, S> {
     pipeline: &'a mut Pipeline,
     facade: &'a F,
     context: Context,
+    lights: &'a [Light],
     target: &'a mut S,
+
+    /// Whether plain scene passes have been drawn into the multisampled
+    /// color texture, which then needs to be resolved.
+    plain_scene_pass_multisampled: bool,
 }
 
 #[must_use]
@@ -64,12 +112,18 @@ pub struct ShadedScenePassStep<'a, F, S>(StepContext<'a, F, S>);
 #[must_use]
 pub struct AfterComposeStep<'a, F, S>(StepContext<'a, F, S>);
 
+#[must_use]
+pub struct TransparentScenePassStep<'a, F, S>(StepContext<'a, F, S>);
+
 #[must_use]
 pub struct PlainScenePassStep<'a, F, S>(StepContext<'a, F, S>);
 
 #[must_use]
 pub struct AfterPostprocessStep<'a, F, S>(StepContext<'a, F, S>);
 
+#[must_use]
+pub struct OutlinePassStep<'a, F, S>(StepContext<'a, F, S>);
+
 #[must_use]
 pub struct PlainScenePassAfterPostprocessStep<'a, F, S>(StepContext<'a, F, S>);
 
@@ -79,36 +133,41 @@ impl Pipeline {
         config: &Config,
         target_size: (u32, u32),
     ) -> Result<Pipeline, CreationError> {
-        let components = Components::create(facade, config, target_size)?;
+        let render_size = Self::render_size_for(config, target_size);
 
-        let scene_color_texture = Self::create_color_texture(facade, target_size)?;
-        let scene_depth_texture = Self::create_depth_texture(facade, target_size)?;
+        let components = Components::create(facade, config, render_size)?;
 
-        let composition_core = components.composition_core(config);
-        let composition_program = composition_core
-            .build_program(facade, shader::InstancingMode::Uniforms)
-            .map_err(crate::CreationError::from)?;
-        let composition_texture = Self::create_color_texture(facade, target_size)?;
+        let scene_color_texture = Self::create_color_texture(facade, render_size)?;
+        let scene_depth_texture = Self::create_depth_texture(facade, render_size)?;
+
+        let composition_program = Self::create_composition_program(facade, config, &components)?;
+        let composition_texture = Self::create_color_texture(facade, render_size)?;
 
         let postprocess_texture = Self::create_color_texture(facade, target_size)?;
 
-        let fxaa = config
-            .fxaa
-            .as_ref()
-            .map(|config| fxaa::FXAA::create(facade, config))
-            .transpose()
-            .map_err(CreationError::FXAA)?;
+        let msaa = Self::create_msaa(facade, config, &components, render_size)?;
+        let taa = Self::create_taa(facade, config, render_size)?;
+        let depth_of_field = Self::create_depth_of_field(facade, config, render_size)?;
+        let motion_blur = Self::create_motion_blur(facade, config, render_size)?;
+        let fxaa = Self::create_fxaa(facade, config)?;
+        let smaa = Self::create_smaa(facade, config, render_size)?;
+        let outline = Self::create_outline(facade, config, target_size)?;
+        let upscale = Self::create_upscale(facade, config, target_size)?;
         let copy_texture_program = shaders::composition_core::<()>()
             .build_program(facade, shader::InstancingMode::Uniforms)
             .map_err(crate::CreationError::from)?;
 
+        let depth_capture = DepthCapture::create(facade)?;
+
         info!("Creating screen quad");
         let screen_quad = ScreenQuad::create(facade)?;
 
         info!("Pipeline initialized");
 
         Ok(Pipeline {
+            config: config.clone(),
             components,
+            generation: 0,
             target_size,
             scene_color_texture,
             scene_depth_texture,
@@ -116,23 +175,45 @@ impl Pipeline {
             postprocess_texture,
             composition_program,
             copy_texture_program,
+            msaa,
+            taa,
+            depth_of_field,
+            motion_blur,
             fxaa,
+            smaa,
+            outline,
+            upscale,
+            depth_capture,
             screen_quad,
         })
     }
 
+    /// Creates a pass for drawing objects into the shadow map. Drawing is
+    /// skipped while shadow mapping is disabled.
     pub fn create_shadow_pass<F, C>(
         &self,
         facade: &F,
         scene_core: C,
         instancing_mode: InstancingMode,
-    ) -> Result<Option<ShadowPass<C>>, crate::CreationError>
+    ) -> Result<ShadowPass<C>, crate::CreationError>
     where
         F: glium::backend::Facade,
         C: SceneCore,
     {
-        self.components
-            .create_shadow_pass(facade, scene_core, instancing_mode)
+        info!(
+            "Creating shadow pass for `C={}`",
+            std::any::type_name::<C>()
+        );
+
+        let scene_core = scene_core.scene_core();
+        let program =
+            self.build_shadow_pass_program::<F, C>(facade, &scene_core, instancing_mode)?;
+
+        Ok(ShadowPass {
+            instancing_mode,
+            scene_core,
+            program: PassProgram::new(self.generation, program),
+        })
     }
 
     pub fn create_shaded_scene_pass<F, C>(
@@ -146,8 +227,54 @@ impl Pipeline {
         F: glium::backend::Facade,
         C: SceneCore,
     {
-        self.components
-            .create_shaded_scene_pass(facade, scene_core, instancing_mode, setup)
+        info!("Creating scene pass for `C={}`", std::any::type_name::<C>());
+
+        let scene_core = scene_core.scene_core();
+        let program = self.build_shaded_scene_pass_program::<F, C>(
+            facade,
+            &scene_core,
+            instancing_mode,
+            &setup,
+        )?;
+
+        Ok(ShadedScenePass {
+            instancing_mode,
+            setup,
+            scene_core,
+            program: PassProgram::new(self.generation, program),
+        })
+    }
+
+    /// Creates a pass for drawing transparent objects. The objects are lit
+    /// by the main light and, with clustered lighting, by the lights given
+    /// to `start_frame`.
+    pub fn create_transparent_scene_pass<F, C>(
+        &self,
+        facade: &F,
+        scene_core: C,
+        instancing_mode: InstancingMode,
+    ) -> Result<TransparentScenePass<C>, crate::CreationError>
+    where
+        F: glium::backend::Facade,
+        C: SceneCore,
+    {
+        info!(
+            "Creating transparent scene pass for `C={}`",
+            std::any::type_name::<C>()
+        );
+
+        let scene_core = scene_core.scene_core();
+        let program = self.build_transparent_scene_pass_program::<F, C>(
+            facade,
+            &scene_core,
+            instancing_mode,
+        )?;
+
+        Ok(TransparentScenePass {
+            instancing_mode,
+            scene_core,
+            program: PassProgram::new(self.generation, program),
+        })
     }
 
     pub fn create_plain_scene_pass<F, C>(
@@ -164,16 +291,233 @@ impl Pipeline {
 
         Ok(PlainScenePass {
             instancing_mode,
-            program,
-            _phantom: std::marker::PhantomData,
+            scene_core: None,
+            program: PassProgram::fixed((program, false)),
+        })
+    }
+
+    /// Creates a plain scene pass whose objects can be picked with `pick`.
+    /// Unlike `create_plain_scene_pass`, this needs a `SceneCore`, so that
+    /// the object ID output can be added to the shader.
+    pub fn create_pickable_plain_scene_pass<F, C>(
+        &self,
+        facade: &F,
+        scene_core: C,
+        instancing_mode: InstancingMode,
+    ) -> Result<PlainScenePass<C>, crate::CreationError>
+    where
+        F: glium::backend::Facade,
+        C: SceneCore,
+    {
+        let scene_core = scene_core.scene_core();
+        let program = self.build_pickable_plain_scene_pass_program::<F, C>(
+            facade,
+            &scene_core,
+            instancing_mode,
+        )?;
+
+        Ok(PlainScenePass {
+            instancing_mode,
+            scene_core: Some(scene_core),
+            program: PassProgram::new(self.generation, program),
         })
     }
 
+    /// Creates a pass for drawing objects that should be outlined. Drawing
+    /// is skipped while outlines are disabled.
+    pub fn create_outline_pass<F, C>(
+        &self,
+        facade: &F,
+        scene_core: C,
+        instancing_mode: InstancingMode,
+    ) -> Result<OutlinePass<C>, crate::CreationError>
+    where
+        F: glium::backend::Facade,
+        C: SceneCore,
+    {
+        info!(
+            "Creating outline pass for `C={}`",
+            std::any::type_name::<C>()
+        );
+
+        let scene_core = scene_core.scene_core();
+        let program =
+            self.build_outline_pass_program::<F, C>(facade, &scene_core, instancing_mode)?;
+
+        Ok(OutlinePass {
+            instancing_mode,
+            scene_core,
+            program: PassProgram::new(self.generation, program),
+        })
+    }
+
+    /// Changes the configuration of the pipeline. Only the components whose
+    /// configuration has changed are recreated. Passes that have been
+    /// created by this pipeline are rebuilt for the new configuration the
+    /// next time that they are drawn.
+    ///
+    /// Recreated components lose their state, e.g. the history of temporal
+    /// anti-aliasing, or lookup tables that have been set with
+    /// `set_color_grading_luts`. If this fails, the pipeline may be left
+    /// partially reconfigured, and should be created anew.
+    pub fn reconfigure<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        config: &Config,
+    ) -> Result<(), CreationError> {
+        info!("Reconfiguring pipeline");
+
+        let old_config = std::mem::replace(&mut self.config, config.clone());
+        let old_output_names = Self::shaded_scene_pass_output_names(&self.components);
+
+        // Resize the textures of the components that are kept.
+        let render_size = self.render_size();
+        if Self::render_size_for(&old_config, self.target_size) != render_size {
+            self.on_target_resize(facade, self.target_size)?;
+        }
+
+        let components_changed =
+            self.components
+                .reconfigure(facade, &old_config, config, render_size)?;
+
+        let color_correction_changed = config.hdr != old_config.hdr
+            || config.color_grading.is_some() != old_config.color_grading.is_some()
+            || config.gamma_correction != old_config.gamma_correction;
+
+        if components_changed || color_correction_changed {
+            self.composition_program =
+                Self::create_composition_program(facade, config, &self.components)?;
+        }
+
+        // Transparent scene passes depend on color correction as well.
+        if components_changed || color_correction_changed || config.outline != old_config.outline {
+            self.generation += 1;
+        }
+
+        if config.msaa != old_config.msaa
+            || Self::shaded_scene_pass_output_names(&self.components) != old_output_names
+        {
+            self.msaa = Self::create_msaa(facade, config, &self.components, render_size)?;
+        }
+
+        if config.taa != old_config.taa {
+            self.taa = Self::create_taa(facade, config, render_size)?;
+        }
+
+        if config.depth_of_field != old_config.depth_of_field {
+            self.depth_of_field = Self::create_depth_of_field(facade, config, render_size)?;
+        }
+
+        if config.motion_blur != old_config.motion_blur {
+            self.motion_blur = Self::create_motion_blur(facade, config, render_size)?;
+        }
+
+        if config.fxaa != old_config.fxaa {
+            self.fxaa = Self::create_fxaa(facade, config)?;
+        }
+
+        if config.smaa != old_config.smaa {
+            self.smaa = Self::create_smaa(facade, config, render_size)?;
+        }
+
+        if config.outline != old_config.outline {
+            self.outline = Self::create_outline(facade, config, self.target_size)?;
+        }
+
+        if config.upscale != old_config.upscale {
+            self.upscale = Self::create_upscale(facade, config, self.target_size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Changes the lens parameters for depth of field. This has no effect if
+    /// depth of field is disabled.
+    pub fn set_depth_of_field_lens(&mut self, lens: dof::Lens) {
+        if let Some(depth_of_field) = self.depth_of_field.as_mut() {
+            depth_of_field.set_lens(lens);
+        }
+    }
+
+    /// Replaces the color grading lookup tables. The transition between
+    /// `lut` and `next_lut` is controlled with
+    /// `set_color_grading_blend_factor`. This has no effect if color grading
+    /// is disabled.
+    pub fn set_color_grading_luts<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        lut: &color_grading::Lut,
+        next_lut: Option<&color_grading::Lut>,
+    ) -> Result<(), crate::CreationError> {
+        if let Some(color_grading) = self.components.color_grading.as_mut() {
+            color_grading.set_luts(facade, lut, next_lut)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the blend factor between the two color grading lookup tables.
+    /// This has no effect if color grading is disabled.
+    pub fn set_color_grading_blend_factor(&mut self, blend_factor: f32) {
+        if let Some(color_grading) = self.components.color_grading.as_mut() {
+            color_grading.set_blend_factor(blend_factor);
+        }
+    }
+
+    /// Returns the ID of the object at the given pixel in the last frame,
+    /// with the origin at the top left. This waits for the GPU to finish
+    /// drawing, see `request_pick` for an alternative.
+    ///
+    /// Returns `None` if there is no object at the pixel, or if the object
+    /// ID buffer is disabled.
+    pub fn pick(&self, x: u32, y: u32) -> Option<u32> {
+        self.components
+            .object_id
+            .as_ref()
+            .and_then(|object_id| object_id.pick((x, y), self.target_size))
+    }
+
+    /// Starts reading the ID of the object at the given pixel in the last
+    /// frame, without waiting for the GPU. The result can be retrieved with
+    /// `take_pick` in the next frame. This has no effect if the object ID
+    /// buffer is disabled.
+    pub fn request_pick(&mut self, x: u32, y: u32) {
+        if let Some(object_id) = self.components.object_id.as_mut() {
+            object_id.request_pick((x, y), self.target_size);
+        }
+    }
+
+    /// Returns the result of the last call to `request_pick`, if any.
+    pub fn take_pick(&mut self) -> Option<object_id::Pick> {
+        self.components
+            .object_id
+            .as_mut()
+            .and_then(|object_id| object_id.take_pick())
+    }
+
+    /// Forgets previous frames. This should be called after camera cuts, so
+    /// that temporal anti-aliasing does not blend with the old view. To
+    /// avoid smearing the whole screen with motion blur, the next frame
+    /// should be started without `Context::previous_camera`.
+    pub fn reset_history(&mut self) {
+        if let Some(taa) = self.taa.as_mut() {
+            taa.reset_history();
+        }
+    }
+
+    /// Starts rendering a frame that is lit by the main light of `context`
+    /// and by `lights`.
+    ///
+    /// Point lights in `lights` that have `casts_shadow` set additionally get
+    /// their shadows rendered in the shadow pass, up to the limit given in
+    /// `shadow::Config`. With clustered lighting, `lights` are also evaluated
+    /// in the shaded and transparent scene passes.
     pub fn start_frame<'a, F: glium::backend::Facade, S: Surface>(
         &'a mut self,
         facade: &'a F,
         clear_color: (f32, f32, f32),
-        context: Context,
+        mut context: Context,
+        lights: &'a [Light],
         target: &'a mut S,
     ) -> Result<StartFrameStep<'a, F, S>, DrawError> {
         let prof_guard = coarse_prof::enter("pipeline");
@@ -198,25 +542,388 @@ impl Pipeline {
 
         self.components.clear_buffers(facade)?;
 
+        if let Some(msaa) = self.msaa.as_ref() {
+            msaa.clear_buffers(
+                facade,
+                (clear_color.0, clear_color.1, clear_color.2, 1.0),
+                &self
+                    .components
+                    .shaded_scene_pass_output_textures(&Self::all_outputs_setup()),
+            )?;
+        }
+
+        if let Some(velocity) = self.components.velocity.as_mut() {
+            velocity.start_frame(&context);
+        }
+
+        // The scene is drawn with a jittered projection, which is undone at
+        // the start of postprocessing. Motion vectors use the camera without
+        // jitter.
+        if let Some(taa) = self.taa.as_mut() {
+            taa.start_frame(Self::render_size_for(&self.config, self.target_size));
+            context.camera.projection = taa.jitter_projection(&context.camera.projection);
+        }
+
+        // The viewport size is restored when upscaling at the end of
+        // postprocessing.
+        if self.upscale.is_some() {
+            let render_size = self.render_size();
+            context.camera.viewport_size =
+                na::Vector2::new(render_size.0 as f32, render_size.1 as f32);
+        }
+
+        if let Some(clustered_lighting) = self.components.clustered_lighting.as_mut() {
+            clustered_lighting.set_lights(&context.camera, lights);
+        }
+
+        if let (Some(shadow_mapping), Some(deferred_shading)) = (
+            self.components.shadow_mapping.as_mut(),
+            self.components.deferred_shading.as_ref(),
+        ) {
+            let point_lights: Vec<_> = shadow_mapping
+                .shadow_casting_point_lights(lights)
+                .map(|light| (light.position, deferred_shading.light_radius(light)))
+                .collect();
+
+            shadow_mapping.set_point_lights(facade, &point_lights)?;
+        }
+
         Ok(StartFrameStep(StepContext {
             _prof_guard: prof_guard,
             pipeline: self,
             facade,
             context,
+            lights,
             target,
+            plain_scene_pass_multisampled: false,
         }))
     }
 
+    /// Reads the composed scene of the last frame, i.e. the scene before
+    /// postprocessing, including transparent and plain scene passes.
+    pub fn read_composition_texture(&self) -> capture::Image<[f32; 4]> {
+        capture::read_texture(&self.composition_texture)
+    }
+
+    /// Reads the final image of the last frame, as it was presented to the
+    /// target.
+    pub fn read_postprocess_texture(&self) -> capture::Image<[f32; 4]> {
+        capture::read_texture(&self.postprocess_texture)
+    }
+
+    /// Reads the additional outputs of the shaded scene pass, e.g. the
+    /// G-buffer of deferred shading, together with their output names.
+    pub fn read_scene_pass_output_textures(&self) -> Vec<(&'static str, capture::Image<[f32; 4]>)> {
+        self.components
+            .shaded_scene_pass_output_textures(&Self::all_outputs_setup())
+            .into_iter()
+            .map(|(name, texture)| (name, capture::read_texture(texture)))
+            .collect()
+    }
+
+    /// Reads one cascade of the main light's shadow map. Returns `None` if
+    /// shadow mapping is disabled or if there is no such cascade.
+    pub fn read_shadow_map<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        cascade: usize,
+    ) -> Result<Option<capture::Image<f32>>, DrawError> {
+        match self.components.shadow_mapping.as_ref() {
+            Some(shadow_mapping) if cascade < shadow_mapping.config().num_cascades => self
+                .depth_capture
+                .read_layer(facade, shadow_mapping.shadow_texture(), cascade as u32)
+                .map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the size at which the scene is drawn. This is the target size,
+    /// unless upscaling is enabled.
+    pub fn render_size(&self) -> (u32, u32) {
+        Self::render_size_for(&self.config, self.target_size)
+    }
+
+    fn render_size_for(config: &Config, target_size: (u32, u32)) -> (u32, u32) {
+        config
+            .upscale
+            .as_ref()
+            .map_or(target_size, |upscale| upscale.render_size(target_size))
+    }
+
+    /// Depth buffer at the target size for drawing after postprocessing.
+    fn postprocess_depth_texture(&self) -> &DepthTexture2d {
+        self.upscale
+            .as_ref()
+            .map_or(&self.scene_depth_texture, |upscale| upscale.depth_texture())
+    }
+
+    /// Setup for which the shaded scene pass has all of its outputs.
+    fn all_outputs_setup() -> ShadedScenePassSetup {
+        ShadedScenePassSetup {
+            draw_shadowed: true,
+            draw_glowing: true,
+        }
+    }
+
+    /// Names of the additional outputs of the shaded scene pass.
+    fn shaded_scene_pass_output_names(components: &Components) -> Vec<&'static str> {
+        components
+            .shaded_scene_pass_output_textures(&Self::all_outputs_setup())
+            .iter()
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    fn build_shadow_pass_program<F, C>(
+        &self,
+        facade: &F,
+        scene_core: &PassCore<C>,
+        instancing_mode: InstancingMode,
+    ) -> Result<Option<Program>, crate::CreationError>
+    where
+        F: glium::backend::Facade,
+        C: SceneCore,
+    {
+        Ok(self
+            .components
+            .shadow_pass_core(scene_core.clone())
+            .map(|shader_core| shader_core.build_program(facade, instancing_mode))
+            .transpose()?)
+    }
+
+    fn build_shaded_scene_pass_program<F, C>(
+        &self,
+        facade: &F,
+        scene_core: &PassCore<C>,
+        instancing_mode: InstancingMode,
+        setup: &ShadedScenePassSetup,
+    ) -> Result<Program, crate::CreationError>
+    where
+        F: glium::backend::Facade,
+        C: SceneCore,
+    {
+        Ok(self
+            .components
+            .shaded_scene_pass_core(scene_core.clone(), setup)
+            .build_program(facade, instancing_mode)?)
+    }
+
+    fn build_transparent_scene_pass_program<F, C>(
+        &self,
+        facade: &F,
+        scene_core: &PassCore<C>,
+        instancing_mode: InstancingMode,
+    ) -> Result<Program, crate::CreationError>
+    where
+        F: glium::backend::Facade,
+        C: SceneCore,
+    {
+        Ok(self
+            .components
+            .transparent_scene_pass_core(&self.config, scene_core.clone())
+            .build_program(facade, instancing_mode)?)
+    }
+
+    fn build_pickable_plain_scene_pass_program<F, C>(
+        &self,
+        facade: &F,
+        scene_core: &PassCore<C>,
+        instancing_mode: InstancingMode,
+    ) -> Result<(Program, bool), crate::CreationError>
+    where
+        F: glium::backend::Facade,
+        C: CoreInput,
+    {
+        let (shader_core, writes_object_id) = self
+            .components
+            .pickable_plain_scene_pass_core(scene_core.clone());
+
+        Ok((
+            shader_core.build_program(facade, instancing_mode)?,
+            writes_object_id,
+        ))
+    }
+
+    fn build_outline_pass_program<F, C>(
+        &self,
+        facade: &F,
+        scene_core: &PassCore<C>,
+        instancing_mode: InstancingMode,
+    ) -> Result<Option<Program>, crate::CreationError>
+    where
+        F: glium::backend::Facade,
+        C: SceneCore,
+    {
+        Ok(self
+            .outline
+            .as_ref()
+            .map(|outline| {
+                outline
+                    .mask_pass_core_transform(scene_core.clone())
+                    .build_program(facade, instancing_mode)
+            })
+            .transpose()?)
+    }
+
+    fn create_composition_program<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        components: &Components,
+    ) -> Result<Program, crate::CreationError> {
+        Ok(components
+            .composition_core(config)
+            .build_program(facade, shader::InstancingMode::Uniforms)?)
+    }
+
+    fn create_msaa<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        components: &Components,
+        render_size: (u32, u32),
+    ) -> Result<Option<MSAA>, CreationError> {
+        config
+            .msaa
+            .as_ref()
+            .map(|config| {
+                let outputs =
+                    components.shaded_scene_pass_output_textures(&Self::all_outputs_setup());
+
+                MSAA::create(facade, config, &outputs, render_size)
+            })
+            .transpose()
+            .map_err(CreationError::MSAA)
+    }
+
+    fn create_taa<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        render_size: (u32, u32),
+    ) -> Result<Option<TAA>, CreationError> {
+        config
+            .taa
+            .as_ref()
+            .map(|config| TAA::create(facade, config, render_size))
+            .transpose()
+            .map_err(CreationError::TAA)
+    }
+
+    fn create_depth_of_field<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        render_size: (u32, u32),
+    ) -> Result<Option<DepthOfField>, CreationError> {
+        config
+            .depth_of_field
+            .as_ref()
+            .map(|config| DepthOfField::create(facade, config, render_size))
+            .transpose()
+            .map_err(CreationError::DepthOfField)
+    }
+
+    fn create_motion_blur<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        render_size: (u32, u32),
+    ) -> Result<Option<MotionBlur>, CreationError> {
+        config
+            .motion_blur
+            .as_ref()
+            .map(|config| MotionBlur::create(facade, config, render_size))
+            .transpose()
+            .map_err(CreationError::MotionBlur)
+    }
+
+    fn create_fxaa<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+    ) -> Result<Option<FXAA>, CreationError> {
+        config
+            .fxaa
+            .as_ref()
+            .map(|config| FXAA::create(facade, config))
+            .transpose()
+            .map_err(CreationError::FXAA)
+    }
+
+    fn create_smaa<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        render_size: (u32, u32),
+    ) -> Result<Option<SMAA>, CreationError> {
+        config
+            .smaa
+            .as_ref()
+            .map(|config| SMAA::create(facade, config, render_size))
+            .transpose()
+            .map_err(CreationError::SMAA)
+    }
+
+    fn create_outline<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Option<Outline>, CreationError> {
+        config
+            .outline
+            .as_ref()
+            .map(|config| Outline::create(facade, config, target_size))
+            .transpose()
+            .map_err(CreationError::Outline)
+    }
+
+    fn create_upscale<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Option<Upscale>, CreationError> {
+        config
+            .upscale
+            .as_ref()
+            .map(|config| Upscale::create(facade, config, target_size))
+            .transpose()
+            .map_err(CreationError::Upscale)
+    }
+
     fn on_target_resize<F: glium::backend::Facade>(
         &mut self,
         facade: &F,
         target_size: (u32, u32),
     ) -> Result<(), crate::CreationError> {
-        self.components.on_target_resize(facade, target_size)?;
+        let render_size = Self::render_size_for(&self.config, target_size);
+
+        self.components.on_target_resize(facade, render_size)?;
+
+        if let Some(msaa) = self.msaa.as_mut() {
+            msaa.on_target_resize(facade, render_size)?;
+        }
+
+        if let Some(taa) = self.taa.as_mut() {
+            taa.on_target_resize(facade, render_size)?;
+        }
+
+        if let Some(depth_of_field) = self.depth_of_field.as_mut() {
+            depth_of_field.on_target_resize(facade, render_size)?;
+        }
+
+        if let Some(motion_blur) = self.motion_blur.as_mut() {
+            motion_blur.on_target_resize(facade, render_size)?;
+        }
+
+        if let Some(smaa) = self.smaa.as_mut() {
+            smaa.on_target_resize(facade, render_size)?;
+        }
+
+        if let Some(outline) = self.outline.as_mut() {
+            outline.on_target_resize(facade, target_size)?;
+        }
+
+        if let Some(upscale) = self.upscale.as_mut() {
+            upscale.on_target_resize(facade, target_size)?;
+        }
 
-        self.scene_color_texture = Self::create_color_texture(facade, target_size)?;
-        self.scene_depth_texture = Self::create_depth_texture(facade, target_size)?;
-        self.composition_texture = Self::create_color_texture(facade, target_size)?;
+        self.scene_color_texture = Self::create_color_texture(facade, render_size)?;
+        self.scene_depth_texture = Self::create_depth_texture(facade, render_size)?;
+        self.composition_texture = Self::create_color_texture(facade, render_size)?;
         self.postprocess_texture = Self::create_color_texture(facade, target_size)?;
 
         Ok(())
@@ -280,7 +987,7 @@ impl<'a, F: glium::backend::Facade, S> StartFrameStep<'a, F, S> {
 impl<'a, F: glium::backend::Facade, S: Surface> ShadowPassStep<'a, F, S> {
     pub fn draw<C, D, P>(
         self,
-        pass: &Option<ShadowPass<C>>,
+        pass: &ShadowPass<C>,
         drawable: &D,
         params: &P,
         draw_params: &glium::DrawParameters,
@@ -290,16 +997,25 @@ impl<'a, F: glium::backend::Facade, S: Surface> ShadowPassStep<'a, F, S> {
         D: Drawable<C::Instance, C::Vertex>,
         P: shader::input::CompatibleWith<C::Params>,
     {
-        if let (Some(pass), Some(shadow_mapping)) = (
-            pass.as_ref(),
-            self.0.pipeline.components.shadow_mapping.as_ref(),
-        ) {
-            assert_eq!(pass.instancing_mode, D::INSTANCING_MODE);
+        assert_eq!(pass.instancing_mode, D::INSTANCING_MODE);
 
+        let pipeline = &self.0.pipeline;
+        let program = pass.program.get(pipeline.generation, || {
+            pipeline.build_shadow_pass_program::<F, C>(
+                self.0.facade,
+                &pass.scene_core,
+                pass.instancing_mode,
+            )
+        })?;
+
+        if let (Some(program), Some(shadow_mapping)) = (
+            program.as_ref(),
+            pipeline.components.shadow_mapping.as_ref(),
+        ) {
             shadow_mapping.shadow_pass(
                 self.0.facade,
                 drawable,
-                &pass.program,
+                program,
                 (&self.0.context, params),
                 draw_params,
             )?;
@@ -329,17 +1045,18 @@ impl<'a, F: glium::backend::Facade, S: Surface> ShadedScenePassStep<'a, F, S> {
         assert_eq!(pass.instancing_mode, D::INSTANCING_MODE);
 
         let pipeline = &self.0.pipeline;
+        let program = pass.program.get(pipeline.generation, || {
+            pipeline.build_shaded_scene_pass_program::<F, C>(
+                self.0.facade,
+                &pass.scene_core,
+                pass.instancing_mode,
+                &pass.setup,
+            )
+        })?;
 
-        let mut output_textures = pipeline
+        let output_textures = pipeline
             .components
             .shaded_scene_pass_output_textures(&pass.setup);
-        output_textures.push((shader::defs::F_COLOR.0, &pipeline.scene_color_texture));
-
-        let mut framebuffer = MultiOutputFrameBuffer::with_depth_buffer(
-            self.0.facade,
-            output_textures.into_iter(),
-            &pipeline.scene_depth_texture,
-        )?;
 
         let draw_params = glium::DrawParameters {
             depth: glium::Depth {
@@ -350,21 +1067,92 @@ impl<'a, F: glium::backend::Facade, S: Surface> ShadedScenePassStep<'a, F, S> {
             ..draw_params.clone()
         };
 
-        pipeline.components.scene_pass::<C, _, _, _>(
-            drawable,
-            &pass.program,
-            (&self.0.context, params),
-            &draw_params,
-            &mut framebuffer,
-        )?;
+        // The object ID texture has an integer format, so we need to mix
+        // attachment types.
+        let object_id_attachment = pipeline.components.object_id.as_ref().map(|object_id| {
+            (
+                object_id::shaders::F_OBJECT_ID.0,
+                object_id.color_attachment(),
+            )
+        });
+
+        if let Some(msaa) = pipeline.msaa.as_ref() {
+            let mut attachments: Vec<_> = output_textures
+                .iter()
+                .filter_map(|(name, _)| {
+                    msaa.output_texture(name)
+                        .map(|texture| (*name, texture.to_color_attachment()))
+                })
+                .collect();
+            attachments.push((
+                shader::defs::F_COLOR.0,
+                msaa.color_texture().to_color_attachment(),
+            ));
+            attachments.extend(object_id_attachment);
+
+            let mut framebuffer = MultiOutputFrameBuffer::with_depth_buffer(
+                self.0.facade,
+                attachments,
+                msaa.depth_texture(),
+            )?;
+
+            pipeline.components.scene_pass::<C, _, _, _>(
+                drawable,
+                &program,
+                (&self.0.context, params),
+                &draw_params,
+                &mut framebuffer,
+            )?;
+        } else {
+            let mut attachments: Vec<_> = output_textures
+                .iter()
+                .map(|(name, texture)| (*name, texture.to_color_attachment()))
+                .collect();
+            attachments.push((
+                shader::defs::F_COLOR.0,
+                pipeline.scene_color_texture.to_color_attachment(),
+            ));
+            attachments.extend(object_id_attachment);
+
+            let mut framebuffer = MultiOutputFrameBuffer::with_depth_buffer(
+                self.0.facade,
+                attachments,
+                &pipeline.scene_depth_texture,
+            )?;
+
+            pipeline.components.scene_pass::<C, _, _, _>(
+                drawable,
+                &program,
+                (&self.0.context, params),
+                &draw_params,
+                &mut framebuffer,
+            )?;
+        }
 
         Ok(self)
     }
 
-    pub fn compose(mut self, lights: &[Light]) -> Result<AfterComposeStep<'a, F, S>, DrawError> {
+    pub fn compose(mut self) -> Result<AfterComposeStep<'a, F, S>, DrawError> {
         let pipeline = &mut self.0.pipeline;
         let components = &mut pipeline.components;
 
+        // Resolve the multisampled scene. The light pass of deferred shading
+        // still reads the multisampled buffers, but needs the resolved depth
+        // for testing light volumes.
+        if let Some(msaa) = pipeline.msaa.as_ref() {
+            profile!("msaa_resolve");
+
+            msaa.resolve(
+                self.0.facade,
+                &pipeline.scene_color_texture,
+                &pipeline.scene_depth_texture,
+            )?;
+            msaa.resolve_outputs(
+                self.0.facade,
+                &components.shaded_scene_pass_output_textures(&Pipeline::all_outputs_setup()),
+            )?;
+        }
+
         // Render light sources into a buffer
         if let Some(deferred_shading) = components.deferred_shading.as_mut() {
             profile!("light_pass");
@@ -372,8 +1160,11 @@ impl<'a, F: glium::backend::Facade, S: Surface> ShadedScenePassStep<'a, F, S> {
             deferred_shading.light_pass(
                 self.0.facade,
                 &pipeline.scene_depth_texture,
+                &pipeline.scene_color_texture,
                 &self.0.context.camera,
-                lights,
+                self.0.lights,
+                components.shadow_mapping.as_ref(),
+                pipeline.msaa.as_ref(),
             )?;
         }
 
@@ -393,21 +1184,28 @@ impl<'a, F: glium::backend::Facade, S: Surface> ShadedScenePassStep<'a, F, S> {
 
             let color_uniform = uniform! {
                 color_texture: &pipeline.scene_color_texture,
+                scene_depth_texture: &pipeline.scene_depth_texture,
             };
+            let context = &self.0.context;
             let deferred_shading_uniforms = components
                 .deferred_shading
                 .as_ref()
-                .map(|c| CompositionPassComponent::params(c));
+                .map(|c| CompositionPassComponent::params(c, context));
             let glow_uniforms = components
                 .glow
                 .as_ref()
-                .map(|c| CompositionPassComponent::params(c));
+                .map(|c| CompositionPassComponent::params(c, context));
+            let fog_uniforms = components
+                .fog
+                .as_ref()
+                .map(|c| CompositionPassComponent::params(c, context));
+            let color_grading_uniforms = components.color_grading.as_ref().map(|c| c.params());
 
             let uniforms = (
                 &color_uniform,
-                &deferred_shading_uniforms,
-                &glow_uniforms,
-                &self.0.context,
+                (&deferred_shading_uniforms, &glow_uniforms, &fog_uniforms),
+                &color_grading_uniforms,
+                context,
             );
 
             target_buffer.draw(
@@ -424,19 +1222,173 @@ impl<'a, F: glium::backend::Facade, S: Surface> ShadedScenePassStep<'a, F, S> {
 }
 
 impl<'a, F: glium::backend::Facade, S: Surface> StepContext<'a, F, S> {
-    fn postprocess(self) -> Result<AfterPostprocessStep<'a, F, S>, DrawError> {
+    /// Draws a plain scene pass onto the given attachments. The object IDs
+    /// are only drawn if the pass writes them.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_plain_scene_pass<'b, C, D, P, T>(
+        &self,
+        pass: &PlainScenePass<C>,
+        drawable: &D,
+        params: &P,
+        draw_params: &glium::DrawParameters,
+        color: ColorAttachment<'b>,
+        depth: T,
+        object_id: Option<ColorAttachment<'b>>,
+    ) -> Result<(), DrawError>
+    where
+        C: CoreInput,
+        D: Drawable<C::Instance, C::Vertex>,
+        P: shader::input::CompatibleWith<C::Params>,
+        T: ToDepthAttachment<'b>,
+    {
+        // Only pickable passes are rebuilt, the programs of other plain
+        // scene passes do not depend on the configuration.
+        let built = pass.program.get(self.pipeline.generation, || {
+            self.pipeline
+                .build_pickable_plain_scene_pass_program::<F, C>(
+                    self.facade,
+                    pass.scene_core.as_ref().unwrap(),
+                    pass.instancing_mode,
+                )
+        })?;
+        let (program, writes_object_id) = (&built.0, built.1);
+
+        match object_id.filter(|_| writes_object_id) {
+            Some(object_id) => {
+                let mut framebuffer = MultiOutputFrameBuffer::with_depth_buffer(
+                    self.facade,
+                    vec![
+                        (shader::defs::F_COLOR.0, color),
+                        (object_id::shaders::F_OBJECT_ID.0, object_id),
+                    ],
+                    depth,
+                )?;
+
+                drawable.draw(
+                    program,
+                    &(&self.context, params),
+                    draw_params,
+                    &mut framebuffer,
+                )
+            }
+            None => {
+                let mut framebuffer =
+                    SimpleFrameBuffer::with_depth_buffer(self.facade, color, depth)?;
+
+                drawable.draw(
+                    program,
+                    &(&self.context, params),
+                    draw_params,
+                    &mut framebuffer,
+                )
+            }
+        }
+    }
+
+    fn postprocess(mut self) -> Result<AfterPostprocessStep<'a, F, S>, DrawError> {
         profile!("postprocess");
 
+        if let (Some(msaa), true) = (
+            self.pipeline.msaa.as_ref(),
+            self.plain_scene_pass_multisampled,
+        ) {
+            profile!("msaa_resolve");
+
+            msaa.resolve(
+                self.facade,
+                &self.pipeline.composition_texture,
+                &self.pipeline.scene_depth_texture,
+            )?;
+        }
+
+        if let Some(object_id) = self.pipeline.components.object_id.as_ref() {
+            profile!("object_id_resolve");
+
+            object_id.resolve(self.facade)?;
+        }
+
+        if let Some(velocity) = self.pipeline.components.velocity.as_ref() {
+            profile!("velocity_background");
+
+            velocity.background_pass(self.facade, &self.pipeline.scene_depth_texture)?;
+        }
+
+        if let (Some(taa), Some(velocity)) = (
+            self.pipeline.taa.as_mut(),
+            self.pipeline.components.velocity.as_ref(),
+        ) {
+            profile!("taa");
+
+            taa.draw(
+                self.facade,
+                &self.pipeline.composition_texture,
+                &self.pipeline.scene_depth_texture,
+                velocity.velocity_texture(),
+            )?;
+
+            // Plain scene passes after postprocessing are not anti-aliased,
+            // so they should not be jittered.
+            self.context.camera.projection =
+                taa.unjitter_projection(&self.context.camera.projection);
+        }
+
+        let color_texture = if let Some(taa) = self.pipeline.taa.as_ref() {
+            taa.output_texture()
+        } else {
+            &self.pipeline.composition_texture
+        };
+
+        let color_texture = if let Some(depth_of_field) = self.pipeline.depth_of_field.as_ref() {
+            profile!("depth_of_field");
+
+            depth_of_field.draw(
+                self.facade,
+                color_texture,
+                &self.pipeline.scene_depth_texture,
+                &self.context,
+            )?;
+
+            depth_of_field.output_texture()
+        } else {
+            color_texture
+        };
+
+        let color_texture = if let (Some(motion_blur), Some(velocity)) = (
+            self.pipeline.motion_blur.as_ref(),
+            self.pipeline.components.velocity.as_ref(),
+        ) {
+            profile!("motion_blur");
+
+            motion_blur.draw(self.facade, color_texture, velocity.velocity_texture())?;
+
+            motion_blur.output_texture()
+        } else {
+            color_texture
+        };
+
+        // With upscaling, anti-aliasing is still done at the render size.
+        let output_texture = self
+            .pipeline
+            .upscale
+            .as_ref()
+            .map_or(&self.pipeline.postprocess_texture, |upscale| {
+                upscale.color_texture()
+            });
+
         let mut framebuffer = SimpleFrameBuffer::with_depth_buffer(
             self.facade,
-            &self.pipeline.postprocess_texture,
+            output_texture,
             &self.pipeline.scene_depth_texture,
         )?;
 
-        if let Some(fxaa) = self.pipeline.fxaa.as_ref() {
+        if let Some(smaa) = self.pipeline.smaa.as_ref() {
+            profile!("smaa");
+
+            smaa.draw(self.facade, color_texture, &mut framebuffer)?;
+        } else if let Some(fxaa) = self.pipeline.fxaa.as_ref() {
             profile!("fxaa");
 
-            fxaa.draw(&self.pipeline.composition_texture, &mut framebuffer)?;
+            fxaa.draw(color_texture, &mut framebuffer)?;
         } else {
             profile!("copy_to_target");
 
@@ -446,12 +1398,28 @@ impl<'a, F: glium::backend::Facade, S: Surface> StepContext<'a, F, S> {
                 &self.pipeline.screen_quad.index_buffer,
                 &self.pipeline.copy_texture_program,
                 &uniform! {
-                    color_texture: &self.pipeline.composition_texture,
+                    color_texture: color_texture,
                 },
                 &Default::default(),
             )?;
         }
 
+        if let Some(upscale) = self.pipeline.upscale.as_ref() {
+            profile!("upscale");
+
+            upscale.draw(
+                self.facade,
+                &self.pipeline.scene_depth_texture,
+                &self.pipeline.postprocess_texture,
+            )?;
+
+            // Outlines and plain scene passes after postprocessing are drawn
+            // at the target size.
+            let target_size = self.pipeline.target_size;
+            self.context.camera.viewport_size =
+                na::Vector2::new(target_size.0 as f32, target_size.1 as f32);
+        }
+
         Ok(AfterPostprocessStep(self))
     }
 
@@ -472,6 +1440,10 @@ impl<'a, F: glium::backend::Facade, S: Surface> StepContext<'a, F, S> {
 }
 
 impl<'a, F: glium::backend::Facade, S: Surface> AfterComposeStep<'a, F, S> {
+    pub fn transparent_scene_pass(self) -> TransparentScenePassStep<'a, F, S> {
+        TransparentScenePassStep(self.0)
+    }
+
     pub fn plain_scene_pass(self) -> PlainScenePassStep<'a, F, S> {
         PlainScenePassStep(self.0)
     }
@@ -481,9 +1453,109 @@ impl<'a, F: glium::backend::Facade, S: Surface> AfterComposeStep<'a, F, S> {
     }
 }
 
-impl<'a, F: glium::backend::Facade, S: Surface> PlainScenePassStep<'a, F, S> {
+impl<'a, F: glium::backend::Facade, S: Surface> TransparentScenePassStep<'a, F, S> {
+    /// Draws transparent objects. They are depth tested against the opaque
+    /// scene, but do not write depth themselves.
     pub fn draw<C, D, P>(
         self,
+        pass: &TransparentScenePass<C>,
+        drawable: &D,
+        params: &P,
+        draw_params: &glium::DrawParameters,
+    ) -> Result<Self, DrawError>
+    where
+        C: SceneCore,
+        D: Drawable<C::Instance, C::Vertex>,
+        P: shader::input::CompatibleWith<C::Params>,
+    {
+        assert_eq!(pass.instancing_mode, D::INSTANCING_MODE);
+
+        let pipeline = &self.0.pipeline;
+        let program = pass.program.get(pipeline.generation, || {
+            pipeline.build_transparent_scene_pass_program::<F, C>(
+                self.0.facade,
+                &pass.scene_core,
+                pass.instancing_mode,
+            )
+        })?;
+
+        if let Some(oit) = pipeline.components.oit.as_ref() {
+            let mut framebuffer = MultiOutputFrameBuffer::with_depth_buffer(
+                self.0.facade,
+                oit.output_textures(),
+                &pipeline.scene_depth_texture,
+            )?;
+
+            pipeline.components.transparent_scene_pass::<C, _, _, _>(
+                drawable,
+                &program,
+                (&self.0.context, params),
+                &OIT::accumulate_draw_parameters(draw_params),
+                &mut framebuffer,
+            )?;
+        } else {
+            let mut framebuffer = SimpleFrameBuffer::with_depth_buffer(
+                self.0.facade,
+                &pipeline.composition_texture,
+                &pipeline.scene_depth_texture,
+            )?;
+
+            let draw_params = glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::DepthTest::IfLess,
+                    write: false,
+                    ..Default::default()
+                },
+                blend: glium::Blend::alpha_blending(),
+                ..draw_params.clone()
+            };
+
+            pipeline.components.transparent_scene_pass::<C, _, _, _>(
+                drawable,
+                &program,
+                (&self.0.context, params),
+                &draw_params,
+                &mut framebuffer,
+            )?;
+        }
+
+        Ok(self)
+    }
+
+    pub fn plain_scene_pass(self) -> Result<PlainScenePassStep<'a, F, S>, DrawError> {
+        Ok(PlainScenePassStep(self.resolve()?))
+    }
+
+    pub fn postprocess(self) -> Result<AfterPostprocessStep<'a, F, S>, DrawError> {
+        self.resolve()?.postprocess()
+    }
+
+    fn resolve(self) -> Result<StepContext<'a, F, S>, DrawError> {
+        let pipeline = &self.0.pipeline;
+
+        if let Some(oit) = pipeline.components.oit.as_ref() {
+            profile!("oit_resolve_pass");
+
+            let color_grading_uniforms = pipeline
+                .components
+                .color_grading
+                .as_ref()
+                .map(|c| c.params());
+
+            oit.resolve_pass(
+                self.0.facade,
+                &color_grading_uniforms,
+                &pipeline.composition_texture,
+            )?;
+        }
+
+        Ok(self.0)
+    }
+}
+
+impl<'a, F: glium::backend::Facade, S: Surface> PlainScenePassStep<'a, F, S> {
+    pub fn draw<C, D, P>(
+        mut self,
         pass: &PlainScenePass<C>,
         drawable: &D,
         params: &P,
@@ -496,18 +1568,44 @@ impl<'a, F: glium::backend::Facade, S: Surface> PlainScenePassStep<'a, F, S> {
     {
         assert_eq!(pass.instancing_mode, D::INSTANCING_MODE);
 
-        let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(
-            self.0.facade,
-            &self.0.pipeline.composition_texture,
-            &self.0.pipeline.scene_depth_texture,
-        )?;
+        if let Some(msaa) = self.0.pipeline.msaa.as_ref() {
+            // Continue drawing on top of the composed scene. The
+            // multisampled depth texture still holds the shaded scene pass.
+            if !self.0.plain_scene_pass_multisampled {
+                msaa.upsample_color(self.0.facade, &self.0.pipeline.composition_texture)?;
+                self.0.plain_scene_pass_multisampled = true;
+            }
 
-        drawable.draw(
-            &pass.program,
-            &(&self.0.context, params),
-            &draw_params,
-            &mut framebuffer,
-        )?;
+            self.0.draw_plain_scene_pass(
+                pass,
+                drawable,
+                params,
+                draw_params,
+                msaa.color_texture().to_color_attachment(),
+                msaa.depth_texture(),
+                self.0
+                    .pipeline
+                    .components
+                    .object_id
+                    .as_ref()
+                    .map(|object_id| object_id.color_attachment()),
+            )?;
+        } else {
+            self.0.draw_plain_scene_pass(
+                pass,
+                drawable,
+                params,
+                draw_params,
+                self.0.pipeline.composition_texture.to_color_attachment(),
+                &self.0.pipeline.scene_depth_texture,
+                self.0
+                    .pipeline
+                    .components
+                    .object_id
+                    .as_ref()
+                    .map(|object_id| object_id.color_attachment()),
+            )?;
+        }
 
         Ok(self)
     }
@@ -518,6 +1616,16 @@ impl<'a, F: glium::backend::Facade, S: Surface> PlainScenePassStep<'a, F, S> {
 }
 
 impl<'a, F: glium::backend::Facade, S: Surface> AfterPostprocessStep<'a, F, S> {
+    /// Starts drawing the objects that should be outlined. This has no
+    /// effect if outlines are disabled.
+    pub fn outline_pass(self) -> Result<OutlinePassStep<'a, F, S>, DrawError> {
+        if let Some(outline) = self.0.pipeline.outline.as_ref() {
+            outline.clear_buffers(self.0.facade)?;
+        }
+
+        Ok(OutlinePassStep(self.0))
+    }
+
     pub fn plain_scene_pass(self) -> PlainScenePassAfterPostprocessStep<'a, F, S> {
         PlainScenePassAfterPostprocessStep(self.0)
     }
@@ -527,6 +1635,69 @@ impl<'a, F: glium::backend::Facade, S: Surface> AfterPostprocessStep<'a, F, S> {
     }
 }
 
+impl<'a, F: glium::backend::Facade, S: Surface> OutlinePassStep<'a, F, S> {
+    /// Draws objects into the outline mask. They are outlined with `color`,
+    /// whose alpha needs to be greater than zero.
+    pub fn draw<C, D, P>(
+        self,
+        pass: &OutlinePass<C>,
+        drawable: &D,
+        params: &P,
+        color: &na::Vector4<f32>,
+        draw_params: &glium::DrawParameters,
+    ) -> Result<Self, DrawError>
+    where
+        C: SceneCore,
+        D: Drawable<C::Instance, C::Vertex>,
+        P: shader::input::CompatibleWith<C::Params>,
+    {
+        assert_eq!(pass.instancing_mode, D::INSTANCING_MODE);
+
+        let pipeline = &self.0.pipeline;
+        let program = pass.program.get(pipeline.generation, || {
+            pipeline.build_outline_pass_program::<F, C>(
+                self.0.facade,
+                &pass.scene_core,
+                pass.instancing_mode,
+            )
+        })?;
+
+        if let (Some(program), Some(outline)) = (program.as_ref(), pipeline.outline.as_ref()) {
+            outline.mask_pass(
+                self.0.facade,
+                drawable,
+                program,
+                (&self.0.context, params),
+                color,
+                self.0.pipeline.postprocess_depth_texture(),
+                draw_params,
+            )?;
+        }
+
+        Ok(self)
+    }
+
+    pub fn plain_scene_pass(
+        self,
+    ) -> Result<PlainScenePassAfterPostprocessStep<'a, F, S>, DrawError> {
+        Ok(PlainScenePassAfterPostprocessStep(self.composite()?))
+    }
+
+    pub fn present(self) -> Result<(), DrawError> {
+        self.composite()?.present()
+    }
+
+    fn composite(self) -> Result<StepContext<'a, F, S>, DrawError> {
+        if let Some(outline) = self.0.pipeline.outline.as_ref() {
+            profile!("outline");
+
+            outline.composite(self.0.facade, &self.0.pipeline.postprocess_texture)?;
+        }
+
+        Ok(self.0)
+    }
+}
+
 impl<'a, F: glium::backend::Facade, S: Surface> PlainScenePassAfterPostprocessStep<'a, F, S> {
     pub fn draw<C, D, P>(
         self,
@@ -542,17 +1713,22 @@ impl<'a, F: glium::backend::Facade, S: Surface> PlainScenePassAfterPostprocessSt
     {
         assert_eq!(pass.instancing_mode, D::INSTANCING_MODE);
 
-        let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::with_depth_buffer(
-            self.0.facade,
-            &self.0.pipeline.postprocess_texture,
-            &self.0.pipeline.scene_depth_texture,
-        )?;
-
-        drawable.draw(
-            &pass.program,
-            &(&self.0.context, params),
-            &draw_params,
-            &mut framebuffer,
+        // Object IDs have been resolved at the start of postprocessing. With
+        // upscaling, they are at the render size and can not be drawn here.
+        self.0.draw_plain_scene_pass(
+            pass,
+            drawable,
+            params,
+            draw_params,
+            self.0.pipeline.postprocess_texture.to_color_attachment(),
+            self.0.pipeline.postprocess_depth_texture(),
+            self.0
+                .pipeline
+                .components
+                .object_id
+                .as_ref()
+                .filter(|_| self.0.pipeline.upscale.is_none())
+                .map(|object_id| object_id.resolved_color_attachment()),
         )?;
 
         Ok(self)
@@ -565,7 +1741,14 @@ impl<'a, F: glium::backend::Facade, S: Surface> PlainScenePassAfterPostprocessSt
 
 #[derive(Debug)]
 pub enum CreationError {
+    MSAA(msaa::CreationError),
+    TAA(taa::CreationError),
+    DepthOfField(dof::CreationError),
+    MotionBlur(motion_blur::CreationError),
     FXAA(fxaa::CreationError),
+    SMAA(smaa::CreationError),
+    Outline(outline::CreationError),
+    Upscale(upscale::CreationError),
     Components(components::CreationError),
     CreationError(crate::CreationError),
 }
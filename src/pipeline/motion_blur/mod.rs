@@ -0,0 +1,137 @@
+//! Camera and object motion blur.
+//!
+//! Each pixel is blurred along its motion vector, as given by the velocity
+//! buffer of the shaded scene pass. Since the blur is gathered per pixel,
+//! moving objects are smeared over the background within their own
+//! silhouette, but static pixels next to them stay sharp.
+
+pub mod shaders;
+
+use log::info;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{MipmapsOption, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+use glium::{uniform, Program, Surface, Texture2d};
+
+use crate::{shader, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    /// Fraction of the time between two frames during which the shutter is
+    /// open. Larger values give longer blur.
+    pub shutter: f32,
+
+    /// Maximal radius of the blur, in pixels.
+    pub max_radius: f32,
+
+    /// Number of samples taken along the motion vector of each pixel.
+    pub num_samples: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            shutter: 0.5,
+            max_radius: 32.0,
+            num_samples: 12,
+        }
+    }
+}
+
+pub struct MotionBlur {
+    config: Config,
+    output_texture: Texture2d,
+    program: Program,
+    screen_quad: ScreenQuad,
+}
+
+impl MotionBlur {
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        let output_texture = Self::create_texture(facade, target_size)?;
+
+        info!("Creating motion blur program");
+        let program = shaders::blur_core(config.num_samples)
+            .build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        info!("Creating screen quad");
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(MotionBlur {
+            config: config.clone(),
+            output_texture,
+            program,
+            screen_quad,
+        })
+    }
+
+    /// Texture holding the result of the last `draw` call.
+    pub fn output_texture(&self) -> &Texture2d {
+        &self.output_texture
+    }
+
+    /// Blurs `color_texture` along `velocity_texture`, writing the result
+    /// into `output_texture`.
+    pub fn draw<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        color_texture: &Texture2d,
+        velocity_texture: &Texture2d,
+    ) -> Result<(), DrawError> {
+        let mut framebuffer = SimpleFrameBuffer::new(facade, &self.output_texture)?;
+
+        let color_texture = Sampler::new(color_texture)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .wrap_function(SamplerWrapFunction::Clamp);
+
+        framebuffer.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.program,
+            &uniform! {
+                color_texture: color_texture,
+                velocity_texture: velocity_texture,
+                motion_blur_shutter: self.config.shutter,
+                motion_blur_max_radius: self.config.max_radius,
+            },
+            &Default::default(),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        self.output_texture = Self::create_texture(facade, target_size)?;
+
+        Ok(())
+    }
+
+    fn create_texture<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<Texture2d, CreationError> {
+        Ok(Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+}
@@ -0,0 +1,46 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader};
+
+/// Shader core for blurring the scene along the motion vectors of the
+/// velocity buffer.
+pub fn blur_core(num_samples: usize) -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty()
+        .with_out(shader::defs::V_TEX_COORD, "tex_coord")
+        .with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("color_texture", UniformType::Sampler2d)
+        .with_extra_uniform("velocity_texture", UniformType::Sampler2d)
+        .with_extra_uniform("motion_blur_shutter", UniformType::Float)
+        .with_extra_uniform("motion_blur_max_radius", UniformType::Float)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_body(&format!(
+            "
+            const int NUM_SAMPLES = {num_samples};
+
+            vec2 velocity = texture(velocity_texture, v_tex_coord).xy * motion_blur_shutter;
+
+            // Limit the length of the blur in pixels
+            float length_px = length(velocity * vec2(textureSize(velocity_texture, 0)));
+            velocity *= min(1.0, 2.0 * motion_blur_max_radius / max(length_px, 1e-5));
+
+            vec3 color = vec3(0.0);
+
+            // Sample centered around the pixel, so that the blur covers the
+            // time from half a shutter interval before to half after the
+            // frame.
+            for (int i = 0; i < NUM_SAMPLES; i++) {{
+                float t = float(i) / float(NUM_SAMPLES - 1) - 0.5;
+                color += texture(color_texture, v_tex_coord - velocity * t).rgb;
+            }}
+            ",
+            num_samples = num_samples.max(2),
+        ))
+        .with_out(
+            shader::defs::F_COLOR,
+            "vec4(color / float(NUM_SAMPLES), 1.0)",
+        );
+
+    shader::Core { vertex, fragment }
+}
@@ -0,0 +1,360 @@
+//! Multisample anti-aliasing for the scene passes.
+//!
+//! With MSAA, the shaded scene pass and plain scene passes are drawn into
+//! multisampled textures. These are resolved into the pipeline's regular
+//! textures before composition and postprocessing, respectively. Color
+//! buffers are resolved by averaging their samples, while the depth buffer
+//! keeps the closest sample.
+//!
+//! Deferred shading needs special care, since the average of the positions
+//! and normals of different surfaces is meaningless. Instead, the light pass
+//! reads the multisampled scene buffers directly. Pixels whose samples were
+//! all written by the same fragment are lit once, while edge pixels are lit
+//! per sample, averaging the results. The composition pass then works with
+//! the resolved buffers, keeping the background color for the fraction of
+//! samples that are not covered by any object.
+//!
+//! The transparent scene pass is not multisampled. It is depth tested
+//! against the resolved depth buffer.
+
+pub mod shaders;
+
+use log::info;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{
+    DepthFormat, DepthTexture2d, DepthTexture2dMultisample, InternalFormat, MipmapsOption,
+    Texture2d, Texture2dMultisample, TextureFormat, UncompressedFloatFormat,
+};
+use glium::{uniform, CapabilitiesSource, Program, Surface};
+
+use crate::{shader, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    /// Number of samples per pixel. This must not exceed the maximum that
+    /// is supported by the OpenGL implementation for the formats of the
+    /// multisampled textures, including integer textures for object IDs.
+    pub num_samples: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { num_samples: 4 }
+    }
+}
+
+const COLOR_FORMAT: UncompressedFloatFormat = UncompressedFloatFormat::F32F32F32F32;
+const DEPTH_FORMAT: DepthFormat = DepthFormat::F32;
+
+/// Checks that multisampled textures with the given `format` can have
+/// `num_samples` samples. If the OpenGL implementation does not report its
+/// supported sample counts, only zero samples are rejected.
+pub fn check_num_samples<F: glium::backend::Facade>(
+    facade: &F,
+    format: TextureFormat,
+    num_samples: u32,
+) -> Result<(), CreationError> {
+    let max_samples = facade
+        .get_context()
+        .get_capabilities()
+        .internal_formats_textures
+        .get(&format)
+        .and_then(|infos| infos.multisamples.as_ref())
+        .map(|samples| samples.iter().copied().max().unwrap_or(0) as u32);
+
+    match max_samples {
+        _ if num_samples == 0 => Err(CreationError::InvalidConfig(
+            "Number of MSAA samples must be at least 1".to_string(),
+        )),
+        Some(max_samples) if num_samples > max_samples => {
+            Err(CreationError::InvalidConfig(format!(
+                "Number of MSAA samples must be at most {} for {:?}, got {}",
+                max_samples, format, num_samples,
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub struct MSAA {
+    config: Config,
+
+    color_texture: Texture2dMultisample,
+    depth_texture: DepthTexture2dMultisample,
+
+    /// Multisampled versions of the additional outputs of the shaded scene
+    /// pass, e.g. the deferred shading buffers, with their formats.
+    output_textures: Vec<(&'static str, UncompressedFloatFormat, Texture2dMultisample)>,
+
+    resolve_program: Program,
+    resolve_depth_program: Program,
+    upsample_program: Program,
+
+    screen_quad: ScreenQuad,
+}
+
+impl MSAA {
+    /// Creates multisampled textures for the color and depth buffers, and
+    /// for the given additional outputs of the shaded scene pass. The
+    /// multisampled outputs have the same number of channels as `outputs`.
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        outputs: &[(&'static str, &Texture2d)],
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        let output_formats = outputs
+            .iter()
+            .map(|(name, texture)| (*name, Self::output_format(texture)))
+            .collect::<Vec<_>>();
+
+        check_num_samples(facade, COLOR_FORMAT.to_texture_format(), config.num_samples)?;
+        check_num_samples(facade, DEPTH_FORMAT.to_texture_format(), config.num_samples)?;
+        for (_, format) in output_formats.iter() {
+            check_num_samples(facade, format.to_texture_format(), config.num_samples)?;
+        }
+
+        info!(
+            "Creating multisampled textures with {} samples",
+            config.num_samples
+        );
+        let color_texture = Self::create_texture(facade, config, COLOR_FORMAT, target_size)?;
+        let depth_texture = Self::create_depth_texture(facade, config, target_size)?;
+        let output_textures = output_formats
+            .into_iter()
+            .map(|(name, format)| {
+                let texture = Self::create_texture(facade, config, format, target_size)?;
+                Ok((name, format, texture))
+            })
+            .collect::<Result<_, CreationError>>()?;
+
+        info!("Creating MSAA programs");
+        let resolve_program = shaders::resolve_core(config.num_samples)
+            .build_program(facade, shader::InstancingMode::Uniforms)?;
+        let resolve_depth_program = shaders::resolve_depth_core(config.num_samples)
+            .build_program(facade, shader::InstancingMode::Uniforms)?;
+        let upsample_program =
+            shaders::upsample_core().build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        info!("Creating screen quad");
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(MSAA {
+            config: config.clone(),
+            color_texture,
+            depth_texture,
+            output_textures,
+            resolve_program,
+            resolve_depth_program,
+            upsample_program,
+            screen_quad,
+        })
+    }
+
+    pub fn num_samples(&self) -> u32 {
+        self.config.num_samples
+    }
+
+    pub fn color_texture(&self) -> &Texture2dMultisample {
+        &self.color_texture
+    }
+
+    pub fn depth_texture(&self) -> &DepthTexture2dMultisample {
+        &self.depth_texture
+    }
+
+    /// Returns the multisampled version of the shaded scene pass output with
+    /// the given name.
+    pub fn output_texture(&self, name: &str) -> Option<&Texture2dMultisample> {
+        self.output_textures
+            .iter()
+            .find(|(output_name, _, _)| *output_name == name)
+            .map(|(_, _, texture)| texture)
+    }
+
+    /// Clears the multisampled textures. The additional outputs are set to
+    /// the contents of `cleared_outputs`, which are expected to have been
+    /// cleared already by their components.
+    pub fn clear_buffers<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        clear_color: (f32, f32, f32, f32),
+        cleared_outputs: &[(&'static str, &Texture2d)],
+    ) -> Result<(), DrawError> {
+        let mut framebuffer =
+            SimpleFrameBuffer::with_depth_buffer(facade, &self.color_texture, &self.depth_texture)?;
+        framebuffer.clear_color_and_depth(clear_color, 1.0);
+
+        for (name, texture) in cleared_outputs {
+            if let Some(target) = self.output_texture(name) {
+                self.upsample(facade, texture, target)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies `texture` into all samples of the multisampled color texture,
+    /// so that we can continue drawing onto it.
+    pub fn upsample_color<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        texture: &Texture2d,
+    ) -> Result<(), DrawError> {
+        self.upsample(facade, texture, &self.color_texture)
+    }
+
+    /// Resolves the multisampled color and depth textures.
+    pub fn resolve<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        color_texture: &Texture2d,
+        depth_texture: &DepthTexture2d,
+    ) -> Result<(), DrawError> {
+        self.resolve_texture(facade, &self.color_texture, color_texture)?;
+
+        let mut framebuffer = SimpleFrameBuffer::depth_only(facade, depth_texture)?;
+        framebuffer.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.resolve_depth_program,
+            &uniform! {
+                multisample_texture: &self.depth_texture,
+            },
+            &glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::DepthTest::Overwrite,
+                    write: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// Resolves the additional outputs of the shaded scene pass into the
+    /// given textures.
+    pub fn resolve_outputs<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        outputs: &[(&'static str, &Texture2d)],
+    ) -> Result<(), DrawError> {
+        for (name, texture) in outputs {
+            if let Some(source) = self.output_texture(name) {
+                self.resolve_texture(facade, source, texture)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        self.color_texture = Self::create_texture(facade, &self.config, COLOR_FORMAT, target_size)?;
+        self.depth_texture = Self::create_depth_texture(facade, &self.config, target_size)?;
+
+        for (_, format, texture) in self.output_textures.iter_mut() {
+            *texture = Self::create_texture(facade, &self.config, *format, target_size)?;
+        }
+
+        Ok(())
+    }
+
+    fn resolve_texture<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        source: &Texture2dMultisample,
+        target: &Texture2d,
+    ) -> Result<(), DrawError> {
+        let mut framebuffer = SimpleFrameBuffer::new(facade, target)?;
+
+        framebuffer.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.resolve_program,
+            &uniform! {
+                multisample_texture: source,
+            },
+            &Default::default(),
+        )?;
+
+        Ok(())
+    }
+
+    fn upsample<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        source: &Texture2d,
+        target: &Texture2dMultisample,
+    ) -> Result<(), DrawError> {
+        let mut framebuffer = SimpleFrameBuffer::new(facade, target)?;
+
+        framebuffer.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.upsample_program,
+            &uniform! {
+                color_texture: source,
+            },
+            &Default::default(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns the format for the multisampled version of the output
+    /// `texture`. All outputs use 32-bit floats, so only the number of
+    /// channels needs to be taken from the texture.
+    fn output_format(texture: &Texture2d) -> UncompressedFloatFormat {
+        match texture.get_internal_format() {
+            Ok(InternalFormat::OneComponent { .. }) => UncompressedFloatFormat::F32,
+            Ok(InternalFormat::TwoComponents { .. }) => UncompressedFloatFormat::F32F32,
+            Ok(InternalFormat::ThreeComponents { .. }) => UncompressedFloatFormat::F32F32F32,
+            _ => UncompressedFloatFormat::F32F32F32F32,
+        }
+    }
+
+    fn create_texture<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        format: UncompressedFloatFormat,
+        size: (u32, u32),
+    ) -> Result<Texture2dMultisample, CreationError> {
+        Ok(Texture2dMultisample::empty_with_format(
+            facade,
+            format,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+            config.num_samples,
+        )?)
+    }
+
+    fn create_depth_texture<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        size: (u32, u32),
+    ) -> Result<DepthTexture2dMultisample, CreationError> {
+        Ok(DepthTexture2dMultisample::empty_with_format(
+            facade,
+            DEPTH_FORMAT,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+            config.num_samples,
+        )?)
+    }
+}
@@ -0,0 +1,60 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader};
+
+/// Shader core for averaging the samples of a multisampled texture.
+pub fn resolve_core(num_samples: u32) -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty().with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("multisample_texture", UniformType::Sampler2dMultisample)
+        .with_body(&format!(
+            "
+            vec4 color = vec4(0.0);
+            for (int i = 0; i < {num_samples}; i++)
+                color += texelFetch(multisample_texture, ivec2(gl_FragCoord.xy), i);
+            color /= {num_samples:.1};
+            ",
+            num_samples = num_samples,
+        ))
+        .with_out(shader::defs::F_COLOR, "color");
+
+    shader::Core { vertex, fragment }
+}
+
+/// Shader core for resolving a multisampled depth texture. We keep the
+/// closest sample, so that pixels at the edges of objects are considered
+/// covered by them.
+pub fn resolve_depth_core(num_samples: u32) -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty().with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("multisample_texture", UniformType::Sampler2dMultisample)
+        .with_body(&format!(
+            "
+            float depth = 1.0;
+            for (int i = 0; i < {num_samples}; i++)
+                depth = min(depth, texelFetch(multisample_texture, ivec2(gl_FragCoord.xy), i).r);
+            gl_FragDepth = depth;
+            ",
+            num_samples = num_samples,
+        ))
+        .with_out(shader::defs::F_COLOR, "vec4(depth)");
+
+    shader::Core { vertex, fragment }
+}
+
+/// Shader core for copying a texture into all samples of a multisampled
+/// texture.
+pub fn upsample_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty().with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("color_texture", UniformType::Sampler2d)
+        .with_out(
+            shader::defs::F_COLOR,
+            "texelFetch(color_texture, ivec2(gl_FragCoord.xy), 0)",
+        );
+
+    shader::Core { vertex, fragment }
+}
@@ -0,0 +1,307 @@
+//! Object ID buffer for picking objects on the GPU.
+//!
+//! The shaded scene pass writes a `u32` object ID per pixel into an integer
+//! texture. Scene cores provide the IDs of their instances through
+//! `V_OBJECT_ID`, e.g. `basic_obj::Instance::object_id`. Plain scene passes
+//! write object IDs only if they were created with
+//! `Pipeline::create_pickable_plain_scene_pass`. Transparent objects are not
+//! pickable. With upscaling, plain scene passes after postprocessing do not
+//! write object IDs, since they are drawn at a different size.
+//!
+//! The ID at a pixel can be read back synchronously, which stalls until the
+//! GPU has finished the frame, or asynchronously through a pixel buffer,
+//! whose result is then available in the next frame.
+
+pub mod shaders;
+
+use log::info;
+
+use glium::framebuffer::{ColorAttachment, SimpleFrameBuffer, ToColorAttachment};
+use glium::texture::pixel_buffer::PixelBuffer;
+use glium::texture::{
+    MipmapsOption, UncompressedUintFormat, UnsignedTexture2d, UnsignedTexture2dMultisample,
+};
+use glium::{uniform, Program, Rect, Surface};
+
+use crate::pipeline::msaa;
+use crate::pipeline::render_pass::{HasScenePassParams, RenderPassComponent, ScenePassComponent};
+use crate::{shader, Context, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {}
+
+/// Format of the object ID textures.
+const FORMAT: UncompressedUintFormat = UncompressedUintFormat::U32;
+
+/// Result of an asynchronous pick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pick {
+    /// The pixel that was picked.
+    pub position: (u32, u32),
+
+    /// The ID of the object at `position`, if there is one.
+    pub object_id: Option<u32>,
+}
+
+/// Multisampled object IDs, used with MSAA.
+struct Multisample {
+    texture: UnsignedTexture2dMultisample,
+    num_samples: u32,
+    resolve_program: Program,
+    screen_quad: ScreenQuad,
+}
+
+pub struct ObjectId {
+    object_id_texture: UnsignedTexture2d,
+    multisample: Option<Multisample>,
+
+    pick_buffer: PixelBuffer<u32>,
+    pending_pick: Option<(u32, u32)>,
+}
+
+impl RenderPassComponent for ObjectId {
+    fn clear_buffers<F: glium::backend::Facade>(&self, _: &F) -> Result<(), DrawError> {
+        self.object_id_texture
+            .main_level()
+            .first_layer()
+            .into_image(None)
+            .unwrap()
+            .raw_clear_buffer([0u32; 4]);
+
+        if let Some(multisample) = self.multisample.as_ref() {
+            multisample
+                .texture
+                .main_level()
+                .first_layer()
+                .into_image(None)
+                .unwrap()
+                .raw_clear_buffer([0u32; 4]);
+        }
+
+        Ok(())
+    }
+}
+
+impl<'u> HasScenePassParams<'u> for ObjectId {
+    type Params = ();
+}
+
+impl ScenePassComponent for ObjectId {
+    fn core_transform<P, I, V>(
+        &self,
+        core: shader::Core<(Context, P), I, V>,
+    ) -> shader::Core<(Context, P), I, V> {
+        shaders::object_id_core_transform(core)
+    }
+
+    fn params(&self, _: &Context) {}
+}
+
+impl ObjectId {
+    /// Creates the component. If `msaa_config` is given, object IDs are
+    /// drawn into a multisampled texture, which needs to be resolved with
+    /// `resolve`.
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        _config: &Config,
+        msaa_config: Option<&msaa::Config>,
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        let object_id_texture = Self::create_texture(facade, target_size)?;
+
+        let multisample = msaa_config
+            .map(|msaa_config| {
+                msaa::check_num_samples(
+                    facade,
+                    FORMAT.to_texture_format(),
+                    msaa_config.num_samples,
+                )?;
+
+                info!("Creating object ID resolve program");
+                let resolve_program = shaders::resolve_core()
+                    .build_program(facade, shader::InstancingMode::Uniforms)?;
+
+                Ok::<_, CreationError>(Multisample {
+                    texture: Self::create_multisample_texture(
+                        facade,
+                        msaa_config.num_samples,
+                        target_size,
+                    )?,
+                    num_samples: msaa_config.num_samples,
+                    resolve_program,
+                    screen_quad: ScreenQuad::create(facade)?,
+                })
+            })
+            .transpose()?;
+
+        let pick_buffer = PixelBuffer::new_empty(facade, 1);
+
+        Ok(ObjectId {
+            object_id_texture,
+            multisample,
+            pick_buffer,
+            pending_pick: None,
+        })
+    }
+
+    /// Returns the attachment that scene passes write object IDs into. With
+    /// MSAA, this is the multisampled texture.
+    pub fn color_attachment(&self) -> ColorAttachment<'_> {
+        if let Some(multisample) = self.multisample.as_ref() {
+            multisample.texture.to_color_attachment()
+        } else {
+            self.object_id_texture.to_color_attachment()
+        }
+    }
+
+    /// Returns the attachment of the resolved object IDs, for drawing after
+    /// `resolve`.
+    pub fn resolved_color_attachment(&self) -> ColorAttachment<'_> {
+        self.object_id_texture.to_color_attachment()
+    }
+
+    pub fn object_id_texture(&self) -> &UnsignedTexture2d {
+        &self.object_id_texture
+    }
+
+    /// Resolves the multisampled object IDs. This has no effect without
+    /// MSAA.
+    pub fn resolve<F: glium::backend::Facade>(&self, facade: &F) -> Result<(), DrawError> {
+        if let Some(multisample) = self.multisample.as_ref() {
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &self.object_id_texture)?;
+
+            framebuffer.draw(
+                &multisample.screen_quad.vertex_buffer,
+                &multisample.screen_quad.index_buffer,
+                &multisample.resolve_program,
+                &uniform! {
+                    multisample_texture: &multisample.texture,
+                },
+                &Default::default(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the object ID at the given pixel of a target with size
+    /// `target_size`, with the origin at the top left as in
+    /// `Camera::project_to_viewport`. The target may be larger than the
+    /// object ID texture if the scene is upscaled. This waits for the GPU
+    /// to finish drawing.
+    ///
+    /// Returns `None` if there is no object at the pixel, or if it is
+    /// outside of the target.
+    pub fn pick(&self, position: (u32, u32), target_size: (u32, u32)) -> Option<u32> {
+        let rect = self.pixel_rect(position, target_size)?;
+
+        let data: Vec<Vec<u32>> = self
+            .object_id_texture
+            .main_level()
+            .first_layer()
+            .into_image(None)
+            .unwrap()
+            .raw_read(&rect);
+
+        Some(data[0][0]).filter(|&object_id| object_id != 0)
+    }
+
+    /// Starts reading the object ID at the given pixel into a pixel buffer,
+    /// without waiting for the GPU. The result can be retrieved with
+    /// `take_pick`, preferably in the next frame. This replaces previous
+    /// requests whose result has not been taken yet.
+    pub fn request_pick(&mut self, position: (u32, u32), target_size: (u32, u32)) {
+        self.pending_pick = self.pixel_rect(position, target_size).map(|rect| {
+            self.object_id_texture
+                .main_level()
+                .first_layer()
+                .into_image(None)
+                .unwrap()
+                .raw_read_to_pixel_buffer(&rect, &self.pick_buffer);
+
+            position
+        });
+    }
+
+    /// Returns the result of the last call to `request_pick`, if any.
+    pub fn take_pick(&mut self) -> Option<Pick> {
+        let position = self.pending_pick.take()?;
+        let object_id = self.pick_buffer.read().ok()?[0];
+
+        Some(Pick {
+            position,
+            object_id: Some(object_id).filter(|&object_id| object_id != 0),
+        })
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        self.object_id_texture = Self::create_texture(facade, target_size)?;
+
+        if let Some(multisample) = self.multisample.as_mut() {
+            multisample.texture =
+                Self::create_multisample_texture(facade, multisample.num_samples, target_size)?;
+        }
+
+        // Pixel positions refer to the old size.
+        self.pending_pick = None;
+
+        Ok(())
+    }
+
+    fn pixel_rect(&self, position: (u32, u32), target_size: (u32, u32)) -> Option<Rect> {
+        let (width, height) = self.object_id_texture.dimensions();
+
+        if position.0 < target_size.0 && position.1 < target_size.1 {
+            let x = (u64::from(position.0) * u64::from(width) / u64::from(target_size.0)) as u32;
+            let y = (u64::from(position.1) * u64::from(height) / u64::from(target_size.1)) as u32;
+
+            Some(Rect {
+                left: x,
+                bottom: height - 1 - y,
+                width: 1,
+                height: 1,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn create_texture<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<UnsignedTexture2d, CreationError> {
+        Ok(UnsignedTexture2d::empty_with_format(
+            facade,
+            FORMAT,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+
+    fn create_multisample_texture<F: glium::backend::Facade>(
+        facade: &F,
+        num_samples: u32,
+        size: (u32, u32),
+    ) -> Result<UnsignedTexture2dMultisample, CreationError> {
+        Ok(UnsignedTexture2dMultisample::empty_with_format(
+            facade,
+            FORMAT,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+            num_samples,
+        )?)
+    }
+}
@@ -0,0 +1,47 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader};
+
+pub const F_OBJECT_ID: (&str, shader::FragmentOutDef) = (
+    "f_object_id",
+    shader::FragmentOutDef(
+        shader::Type::UnsignedInt,
+        shader::FragmentOutQualifier::Yield,
+    ),
+);
+
+/// Shader core transform for writing the object ID of fragments into
+/// `F_OBJECT_ID`.
+///
+/// If the core does not provide `V_OBJECT_ID`, its fragments are written
+/// with ID 0, so that they hide the objects behind them without being
+/// pickable themselves.
+pub fn object_id_core_transform<P, I, V>(core: shader::Core<P, I, V>) -> shader::Core<P, I, V> {
+    let fragment = if core.vertex.has_out_def(shader::defs::V_OBJECT_ID) {
+        core.fragment
+            .with_in_def(shader::defs::V_OBJECT_ID)
+            .with_out(F_OBJECT_ID, "v_object_id")
+    } else {
+        core.fragment.with_out(F_OBJECT_ID, "0u")
+    };
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
+
+/// Shader core for resolving a multisampled object ID texture. Object IDs
+/// can not be averaged, so we just take the first sample.
+pub fn resolve_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty().with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("multisample_texture", UniformType::USampler2dMultisample)
+        .with_out(
+            F_OBJECT_ID,
+            "texelFetch(multisample_texture, ivec2(gl_FragCoord.xy), 0).r",
+        );
+
+    shader::Core { vertex, fragment }
+}
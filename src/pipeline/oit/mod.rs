@@ -0,0 +1,238 @@
+//! Weighted blended order-independent transparency.
+//!
+//! For reference:
+//! http://jcgt.org/published/0002/02/09/
+//!
+//! Transparent objects are drawn after composition, depth-tested against the
+//! opaque scene but without writing depth. Instead of blending fragments in
+//! sorted order, each fragment adds its weighted color into an accumulation
+//! texture, and multiplies its `1 - alpha` into the revealage, i.e. the
+//! fraction of the background that remains visible. Weights fall off with
+//! view depth, so that closer fragments dominate. In the resolve pass, the
+//! weighted average color is blended onto the composed scene.
+
+pub mod shaders;
+
+use log::info;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{MipmapsOption, UncompressedFloatFormat};
+use glium::{uniform, Program, Surface, Texture2d};
+
+use crate::pipeline::render_pass::{HasScenePassParams, RenderPassComponent, ScenePassComponent};
+use crate::pipeline::shaders::color_correction_core_transform;
+use crate::shader::ToUniforms;
+use crate::{shader, Context, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    /// Scale of view depths when weighting fragments. The weight function
+    /// is tuned for transparent objects at a distance between 0.1 and 500
+    /// from the camera. For scenes of a different scale, set this to the
+    /// factor by which the scene is larger.
+    pub depth_scale: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { depth_scale: 1.0 }
+    }
+}
+
+pub struct OIT {
+    config: Config,
+
+    /// Sum of weighted, premultiplied colors. The alpha channel holds the
+    /// revealage.
+    accum_texture: Texture2d,
+
+    /// Sum of weights.
+    weight_texture: Texture2d,
+
+    resolve_program: Program,
+    screen_quad: ScreenQuad,
+}
+
+impl RenderPassComponent for OIT {
+    fn clear_buffers<F: glium::backend::Facade>(&self, facade: &F) -> Result<(), DrawError> {
+        let mut framebuffer = SimpleFrameBuffer::new(facade, &self.accum_texture)?;
+        framebuffer.clear_color(0.0, 0.0, 0.0, 1.0);
+
+        let mut framebuffer = SimpleFrameBuffer::new(facade, &self.weight_texture)?;
+        framebuffer.clear_color(0.0, 0.0, 0.0, 0.0);
+
+        Ok(())
+    }
+}
+
+pub struct ScenePassParams {
+    depth_scale: f32,
+}
+
+impl_uniform_input!(
+    ScenePassParams,
+    self => {
+        oit_depth_scale: f32 = self.depth_scale,
+    },
+);
+
+impl<'u> HasScenePassParams<'u> for OIT {
+    type Params = ScenePassParams;
+}
+
+impl ScenePassComponent for OIT {
+    fn core_transform<P, I, V>(
+        &self,
+        core: shader::Core<(Context, P), I, V>,
+    ) -> shader::Core<(Context, P), I, V> {
+        shaders::accumulate_core_transform(core)
+    }
+
+    fn output_textures(&self) -> Vec<(&'static str, &Texture2d)> {
+        vec![
+            (shaders::F_OIT_ACCUM.0, &self.accum_texture),
+            (shaders::F_OIT_WEIGHT.0, &self.weight_texture),
+        ]
+    }
+
+    fn params(&self, _: &Context) -> ScenePassParams {
+        ScenePassParams {
+            depth_scale: self.config.depth_scale,
+        }
+    }
+}
+
+impl OIT {
+    /// Creates the component. Since transparent objects are resolved after
+    /// composition, `hdr`, `color_grading` and `gamma_correction` need to
+    /// match the pipeline configuration.
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        hdr: Option<f32>,
+        color_grading: bool,
+        gamma_correction: Option<f32>,
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        let accum_texture =
+            Self::create_texture(facade, UncompressedFloatFormat::F32F32F32F32, target_size)?;
+        let weight_texture =
+            Self::create_texture(facade, UncompressedFloatFormat::F32, target_size)?;
+
+        info!("Creating OIT resolve program");
+        let resolve_core = color_correction_core_transform(
+            hdr,
+            color_grading,
+            gamma_correction,
+            shaders::resolve_core(),
+        );
+        let resolve_program =
+            resolve_core.build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        info!("Creating screen quad");
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(OIT {
+            config: config.clone(),
+            accum_texture,
+            weight_texture,
+            resolve_program,
+            screen_quad,
+        })
+    }
+
+    /// Adjusts the given draw parameters for accumulating transparent
+    /// fragments.
+    ///
+    /// The color channels of both outputs are summed up, while the alpha
+    /// channel of the accumulation texture is multiplied with `1 - alpha`.
+    pub fn accumulate_draw_parameters<'a>(
+        draw_params: &glium::DrawParameters<'a>,
+    ) -> glium::DrawParameters<'a> {
+        glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::DepthTest::IfLess,
+                write: false,
+                ..Default::default()
+            },
+            blend: glium::Blend {
+                color: glium::BlendingFunction::Addition {
+                    source: glium::LinearBlendingFactor::One,
+                    destination: glium::LinearBlendingFactor::One,
+                },
+                alpha: glium::BlendingFunction::Addition {
+                    source: glium::LinearBlendingFactor::Zero,
+                    destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+                },
+                constant_value: (0.0, 0.0, 0.0, 0.0),
+            },
+            ..draw_params.clone()
+        }
+    }
+
+    /// Blends the average color of the accumulated transparent fragments
+    /// onto `target`. The `params` are passed on to the color correction of
+    /// the resolve program.
+    pub fn resolve_pass<F: glium::backend::Facade, U: ToUniforms>(
+        &self,
+        facade: &F,
+        params: &U,
+        target: &Texture2d,
+    ) -> Result<(), DrawError> {
+        let mut framebuffer = SimpleFrameBuffer::new(facade, target)?;
+
+        let draw_params = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            ..Default::default()
+        };
+
+        let uniforms = uniform! {
+            oit_accum_texture: &self.accum_texture,
+            oit_weight_texture: &self.weight_texture,
+        };
+
+        framebuffer.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.resolve_program,
+            &(&uniforms, params).to_uniforms(),
+            &draw_params,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        self.accum_texture =
+            Self::create_texture(facade, UncompressedFloatFormat::F32F32F32F32, target_size)?;
+        self.weight_texture =
+            Self::create_texture(facade, UncompressedFloatFormat::F32, target_size)?;
+
+        Ok(())
+    }
+
+    fn create_texture<F: glium::backend::Facade>(
+        facade: &F,
+        format: UncompressedFloatFormat,
+        size: (u32, u32),
+    ) -> Result<Texture2d, CreationError> {
+        Ok(Texture2d::empty_with_format(
+            facade,
+            format,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+}
@@ -0,0 +1,98 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader, Context};
+
+pub const F_OIT_ACCUM: (&str, shader::FragmentOutDef) = (
+    "f_oit_accum",
+    shader::FragmentOutDef(shader::Type::FloatVec4, shader::FragmentOutQualifier::Yield),
+);
+
+pub const F_OIT_WEIGHT: (&str, shader::FragmentOutDef) = (
+    "f_oit_weight",
+    shader::FragmentOutDef(shader::Type::Float, shader::FragmentOutQualifier::Yield),
+);
+
+/// Shader core transform for accumulating transparent fragments.
+///
+/// The color of each fragment is weighted by its alpha and by a function
+/// that falls off with view depth. The weighted, premultiplied colors are
+/// summed up in `F_OIT_ACCUM`, whose alpha channel holds the product of
+/// `1 - alpha` (the revealage). The sum of the weighted alphas goes into
+/// `F_OIT_WEIGHT`, so that the resolve pass gets the weighted average color.
+pub fn accumulate_core_transform<P, I, V>(
+    mut core: shader::Core<(Context, P), I, V>,
+) -> shader::Core<(Context, P), I, V> {
+    assert!(
+        core.fragment.has_out_def(shader::defs::F_COLOR),
+        "FragmentCore needs F_COLOR output for transparent pass"
+    );
+    assert!(
+        core.vertex.has_out_def(shader::defs::V_WORLD_POS),
+        "VertexCore needs V_WORLD_POS output for transparent pass"
+    );
+
+    // The color is only used for computing the accumulation outputs.
+    for (_, out_def) in core.fragment.out_defs.iter_mut() {
+        out_def.1 = shader::FragmentOutQualifier::Local;
+    }
+
+    let fragment = core
+        .fragment
+        .with_extra_uniform("oit_depth_scale", UniformType::Float)
+        .with_in_def(shader::defs::V_WORLD_POS)
+        .with_body(
+            "
+            // Weight function (7) from McGuire and Bavoil, 2013
+            float oit_alpha = clamp(f_color.a, 0.0, 1.0);
+            float oit_depth = -(context_camera_view * v_world_pos).z / oit_depth_scale;
+            float oit_weight = oit_alpha * clamp(
+                10.0 / (1e-5 + pow(oit_depth / 5.0, 2.0) + pow(oit_depth / 200.0, 6.0)),
+                1e-2,
+                3e3
+            );
+        ",
+        )
+        .with_out(
+            F_OIT_ACCUM,
+            "vec4(f_color.rgb * oit_alpha * oit_weight, oit_alpha)",
+        )
+        .with_out(F_OIT_WEIGHT, "oit_alpha * oit_weight");
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
+
+/// Shader core for resolving the accumulated transparent fragments into
+/// their average color. The alpha of the output is the coverage of the
+/// transparent fragments, so that the result can be blended onto the opaque
+/// scene.
+pub fn resolve_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty()
+        .with_out(shader::defs::V_TEX_COORD, "tex_coord")
+        .with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("oit_accum_texture", UniformType::Sampler2d)
+        .with_extra_uniform("oit_weight_texture", UniformType::Sampler2d)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_body(
+            "
+            vec4 accum = texture(oit_accum_texture, v_tex_coord);
+            float revealage = accum.a;
+
+            // Skip fragments that are not covered by any transparent object
+            if (revealage >= 1.0)
+                discard;
+
+            float weight = texture(oit_weight_texture, v_tex_coord).r;
+        ",
+        )
+        .with_out(
+            shader::defs::F_COLOR,
+            "vec4(accum.rgb / max(weight, 1e-5), 1.0 - revealage)",
+        );
+
+    shader::Core { vertex, fragment }
+}
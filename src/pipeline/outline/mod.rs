@@ -0,0 +1,275 @@
+//! Outlines around selected objects.
+//!
+//! Selected objects are drawn with their outline color into a mask after
+//! postprocessing. The outlines are then found with the jump flood
+//! algorithm: in a logarithmic number of passes, each pixel finds the
+//! nearest pixel that is covered by the mask. Pixels outside of the mask
+//! that are within the outline width of the mask are finally blended onto
+//! the postprocessed scene.
+//!
+//! For reference:
+//! https://www.comp.nus.edu.sg/~tants/jfa/i3d06.pdf
+
+pub mod shaders;
+
+use log::info;
+use nalgebra as na;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{DepthTexture2d, MipmapsOption, UncompressedFloatFormat};
+use glium::{uniform, Program, Surface, Texture2d};
+
+use crate::shader::ToUniforms;
+use crate::{shader, Context, DrawError, Drawable, ScreenQuad};
+
+pub use crate::CreationError;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    /// Width of the outlines, in pixels.
+    pub width: f32,
+
+    /// If true, outlines are drawn around the whole object, even where it is
+    /// hidden behind other objects. Otherwise, only the visible parts of
+    /// the object are outlined.
+    pub show_occluded: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            width: 2.0,
+            show_occluded: false,
+        }
+    }
+}
+
+pub struct Outline {
+    config: Config,
+
+    mask_texture: Texture2d,
+
+    /// Ping-pong textures for the jump flood, storing the position of the
+    /// nearest masked pixel.
+    seed_textures: [Texture2d; 2],
+
+    seed_program: Program,
+    jump_flood_program: Program,
+    composite_program: Program,
+    screen_quad: ScreenQuad,
+}
+
+impl Outline {
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        let mask_texture = Self::create_mask_texture(facade, target_size)?;
+        let seed_textures = [
+            Self::create_seed_texture(facade, target_size)?,
+            Self::create_seed_texture(facade, target_size)?,
+        ];
+
+        info!("Creating outline programs");
+        let seed_program =
+            shaders::seed_core().build_program(facade, shader::InstancingMode::Uniforms)?;
+        let jump_flood_program =
+            shaders::jump_flood_core().build_program(facade, shader::InstancingMode::Uniforms)?;
+        let composite_program =
+            shaders::composite_core().build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        info!("Creating screen quad");
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(Outline {
+            config: config.clone(),
+            mask_texture,
+            seed_textures,
+            seed_program,
+            jump_flood_program,
+            composite_program,
+            screen_quad,
+        })
+    }
+
+    pub fn mask_pass_core_transform<P, I, V>(
+        &self,
+        core: shader::Core<P, I, V>,
+    ) -> shader::Core<P, I, V> {
+        shaders::mask_core_transform(core)
+    }
+
+    pub fn clear_buffers<F: glium::backend::Facade>(&self, facade: &F) -> Result<(), DrawError> {
+        let mut framebuffer = SimpleFrameBuffer::new(facade, &self.mask_texture)?;
+        framebuffer.clear_color(0.0, 0.0, 0.0, 0.0);
+
+        Ok(())
+    }
+
+    /// Draws objects into the outline mask with the given color. Unless
+    /// `Config::show_occluded` is set, the objects are depth tested against
+    /// `depth_texture`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn mask_pass<F, I, V, P>(
+        &self,
+        facade: &F,
+        drawable: &impl Drawable<I, V>,
+        program: &glium::Program,
+        params: (&Context, P),
+        color: &na::Vector4<f32>,
+        depth_texture: &DepthTexture2d,
+        draw_params: &glium::DrawParameters,
+    ) -> Result<(), DrawError>
+    where
+        F: glium::backend::Facade,
+        V: glium::vertex::Vertex,
+        P: ToUniforms,
+    {
+        let color: [f32; 4] = (*color).into();
+        let uniforms = (params.0, params.1, &uniform! { outline_color: color });
+
+        if self.config.show_occluded {
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &self.mask_texture)?;
+
+            let draw_params = glium::DrawParameters {
+                depth: Default::default(),
+                ..draw_params.clone()
+            };
+
+            drawable.draw(program, &uniforms, &draw_params, &mut framebuffer)
+        } else {
+            let mut framebuffer =
+                SimpleFrameBuffer::with_depth_buffer(facade, &self.mask_texture, depth_texture)?;
+
+            // With MSAA or TAA, the scene depth was not taken at the pixel
+            // centers, so we pull the objects a bit towards the camera to
+            // make sure that they pass the test against their own depth.
+            let draw_params = glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::DepthTest::IfLessOrEqual,
+                    write: false,
+                    ..Default::default()
+                },
+                polygon_offset: glium::draw_parameters::PolygonOffset {
+                    factor: -2.0,
+                    units: -2.0,
+                    fill: true,
+                    ..Default::default()
+                },
+                ..draw_params.clone()
+            };
+
+            drawable.draw(program, &uniforms, &draw_params, &mut framebuffer)
+        }
+    }
+
+    /// Finds the outlines of the mask and blends them onto `target`.
+    pub fn composite<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        target: &Texture2d,
+    ) -> Result<(), DrawError> {
+        {
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &self.seed_textures[0])?;
+
+            framebuffer.draw(
+                &self.screen_quad.vertex_buffer,
+                &self.screen_quad.index_buffer,
+                &self.seed_program,
+                &uniform! {
+                    outline_mask_texture: &self.mask_texture,
+                },
+                &Default::default(),
+            )?;
+        }
+
+        // The steps need to add up to at least the largest distance at which
+        // pixels are still partially covered.
+        let max_distance = self.config.width.max(0.0).ceil() as u32 + 1;
+        let mut step_size = max_distance.next_power_of_two();
+        let mut current = 0;
+
+        while step_size > 0 {
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &self.seed_textures[1 - current])?;
+
+            framebuffer.draw(
+                &self.screen_quad.vertex_buffer,
+                &self.screen_quad.index_buffer,
+                &self.jump_flood_program,
+                &uniform! {
+                    outline_seed_texture: &self.seed_textures[current],
+                    outline_step_size: step_size as i32,
+                },
+                &Default::default(),
+            )?;
+
+            current = 1 - current;
+            step_size /= 2;
+        }
+
+        let mut framebuffer = SimpleFrameBuffer::new(facade, target)?;
+
+        framebuffer.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.composite_program,
+            &uniform! {
+                outline_mask_texture: &self.mask_texture,
+                outline_seed_texture: &self.seed_textures[current],
+                outline_width: self.config.width,
+            },
+            &glium::DrawParameters {
+                blend: glium::Blend::alpha_blending(),
+                ..Default::default()
+            },
+        )?;
+
+        Ok(())
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        self.mask_texture = Self::create_mask_texture(facade, target_size)?;
+        self.seed_textures = [
+            Self::create_seed_texture(facade, target_size)?,
+            Self::create_seed_texture(facade, target_size)?,
+        ];
+
+        Ok(())
+    }
+
+    fn create_mask_texture<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<Texture2d, CreationError> {
+        Ok(Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+
+    fn create_seed_texture<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<Texture2d, CreationError> {
+        Ok(Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32F32,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+}
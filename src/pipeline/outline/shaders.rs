@@ -0,0 +1,135 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader};
+
+pub const F_OUTLINE_COLOR: (&str, shader::FragmentOutDef) = (
+    "f_outline_color",
+    shader::FragmentOutDef(shader::Type::FloatVec4, shader::FragmentOutQualifier::Yield),
+);
+
+/// Shader core transform for drawing objects into the outline mask.
+///
+/// The original outputs are demoted to be local, so that only the outline
+/// color, given by the `outline_color` uniform, is written into
+/// `F_OUTLINE_COLOR`.
+pub fn mask_core_transform<P, I, V>(mut core: shader::Core<P, I, V>) -> shader::Core<P, I, V> {
+    for (_, out_def) in core.fragment.out_defs.iter_mut() {
+        out_def.1 = shader::FragmentOutQualifier::Local;
+    }
+
+    let fragment = core
+        .fragment
+        .with_extra_uniform("outline_color", UniformType::FloatVec4)
+        .with_out(F_OUTLINE_COLOR, "outline_color");
+
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
+}
+
+fn screen_quad_vertex_core() -> shader::VertexCore<(), (), screen_quad::Vertex> {
+    shader::VertexCore::empty().with_out(shader::defs::V_POS, "position")
+}
+
+/// Shader core for initializing the jump flood. Pixels that are covered by
+/// the mask store their own position, all other pixels store -1.
+pub fn seed_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("outline_mask_texture", UniformType::Sampler2d)
+        .with_out(
+            shader::defs::F_COLOR,
+            "texelFetch(outline_mask_texture, ivec2(gl_FragCoord.xy), 0).a > 0.0
+                ? vec4(gl_FragCoord.xy, 0.0, 0.0)
+                : vec4(-1.0)",
+        );
+
+    shader::Core {
+        vertex: screen_quad_vertex_core(),
+        fragment,
+    }
+}
+
+/// Shader core for one step of the jump flood. Each pixel keeps the nearest
+/// of the seeds found at its own position and at its eight neighbors
+/// `outline_step_size` pixels away.
+pub fn jump_flood_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("outline_seed_texture", UniformType::Sampler2d)
+        .with_extra_uniform("outline_step_size", UniformType::Int)
+        .with_body(
+            "
+            ivec2 size = textureSize(outline_seed_texture, 0);
+            ivec2 pos = ivec2(gl_FragCoord.xy);
+
+            vec2 nearest_seed = vec2(-1.0);
+            float nearest_distance = 1e20;
+
+            for (int y = -1; y <= 1; y++) {
+                for (int x = -1; x <= 1; x++) {
+                    ivec2 neighbor = pos + ivec2(x, y) * outline_step_size;
+
+                    if (any(lessThan(neighbor, ivec2(0))) || any(greaterThanEqual(neighbor, size)))
+                        continue;
+
+                    vec2 seed = texelFetch(outline_seed_texture, neighbor, 0).xy;
+
+                    if (seed.x < 0.0)
+                        continue;
+
+                    float seed_distance = distance(seed, gl_FragCoord.xy);
+
+                    if (seed_distance < nearest_distance) {
+                        nearest_seed = seed;
+                        nearest_distance = seed_distance;
+                    }
+                }
+            }
+            ",
+        )
+        .with_out(shader::defs::F_COLOR, "vec4(nearest_seed, 0.0, 0.0)");
+
+    shader::Core {
+        vertex: screen_quad_vertex_core(),
+        fragment,
+    }
+}
+
+/// Shader core for drawing the outlines, given the mask and the result of
+/// the jump flood. Pixels outside of the mask are covered according to
+/// their distance to the nearest seed, taking the color of that seed.
+pub fn composite_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("outline_mask_texture", UniformType::Sampler2d)
+        .with_extra_uniform("outline_seed_texture", UniformType::Sampler2d)
+        .with_extra_uniform("outline_width", UniformType::Float)
+        .with_body(
+            "
+            ivec2 pos = ivec2(gl_FragCoord.xy);
+            vec2 seed = texelFetch(outline_seed_texture, pos, 0).xy;
+
+            if (seed.x < 0.0 || texelFetch(outline_mask_texture, pos, 0).a > 0.0)
+                discard;
+
+            // The seed is the center of a masked pixel, so its edge is half a
+            // pixel closer. Pixels within `outline_width` of that edge are
+            // covered fully, with one pixel of falloff for anti-aliasing.
+            float coverage = clamp(
+                outline_width + 1.0 - distance(seed, gl_FragCoord.xy),
+                0.0,
+                1.0
+            );
+
+            if (coverage == 0.0)
+                discard;
+
+            vec4 color = texelFetch(outline_mask_texture, ivec2(seed), 0);
+            ",
+        )
+        .with_out(shader::defs::F_COLOR, "vec4(color.rgb, color.a * coverage)");
+
+    shader::Core {
+        vertex: screen_quad_vertex_core(),
+        fragment,
+    }
+}
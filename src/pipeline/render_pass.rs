@@ -1,16 +1,76 @@
+use std::cell::{Ref, RefCell};
+
+use log::info;
+
 use crate::pipeline::Context;
 use crate::scene::{CoreInput, SceneCore};
 use crate::shader::{InstancingMode, ToUniforms};
-use crate::{screen_quad, shader, DrawError};
+use crate::{screen_quad, shader, CreationError, DrawError};
+
+/// The untransformed shader core of a scene pass.
+pub(crate) type PassCore<C> = shader::Core<
+    (Context, <C as CoreInput>::Params),
+    <C as CoreInput>::Instance,
+    <C as CoreInput>::Vertex,
+>;
+
+/// The program of a pass. Programs depend on the configuration of the
+/// pipeline, so they are rebuilt when they are drawn after the pipeline has
+/// been reconfigured.
+pub struct PassProgram<T> {
+    /// The generation of the pipeline for which the program was built, or
+    /// `None` if the program does not depend on the configuration.
+    program: RefCell<(Option<u64>, T)>,
+}
+
+impl<T> PassProgram<T> {
+    pub(crate) fn new(generation: u64, program: T) -> Self {
+        Self {
+            program: RefCell::new((Some(generation), program)),
+        }
+    }
+
+    pub(crate) fn fixed(program: T) -> Self {
+        Self {
+            program: RefCell::new((None, program)),
+        }
+    }
+
+    /// Returns the program, rebuilding it with `build` first if it was built
+    /// for an older generation of the pipeline.
+    pub(crate) fn get<B>(&self, generation: u64, build: B) -> Result<Ref<'_, T>, CreationError>
+    where
+        B: FnOnce() -> Result<T, CreationError>,
+    {
+        let built_generation = self.program.borrow().0;
+
+        if matches!(built_generation, Some(built_generation) if built_generation != generation) {
+            info!("Rebuilding pass program for the new configuration");
+            *self.program.borrow_mut() = (Some(generation), build()?);
+        }
+
+        Ok(Ref::map(self.program.borrow(), |(_, program)| program))
+    }
+}
 
 pub struct ShadowPass<C: SceneCore> {
     pub instancing_mode: InstancingMode,
-    pub program: glium::Program,
 
-    /// The transformed shader core that was used for building the `program`.
-    /// Currently this is basically just phantom data.
-    #[allow(dead_code)]
-    pub shader_core: shader::Core<(Context, C::Params), C::Instance, C::Vertex>,
+    /// The untransformed shader core, from which the `program` is rebuilt.
+    pub(crate) scene_core: PassCore<C>,
+
+    /// The program, or `None` if shadow mapping is disabled.
+    pub(crate) program: PassProgram<Option<glium::Program>>,
+}
+
+pub struct OutlinePass<C: SceneCore> {
+    pub instancing_mode: InstancingMode,
+
+    /// The untransformed shader core, from which the `program` is rebuilt.
+    pub(crate) scene_core: PassCore<C>,
+
+    /// The program, or `None` if outlines are disabled.
+    pub(crate) program: PassProgram<Option<glium::Program>>,
 }
 
 #[derive(Debug, Clone)]
@@ -23,19 +83,32 @@ pub struct ShadedScenePass<C: SceneCore> {
     pub instancing_mode: InstancingMode,
     pub setup: ShadedScenePassSetup,
 
-    pub program: glium::Program,
+    /// The untransformed shader core, from which the `program` is rebuilt.
+    pub(crate) scene_core: PassCore<C>,
 
-    /// The transformed shader core that was used for building the `program`.
-    /// Currently this is basically just phantom data.
-    #[allow(dead_code)]
-    pub shader_core: shader::Core<(Context, C::Params), C::Instance, C::Vertex>,
+    pub(crate) program: PassProgram<glium::Program>,
+}
+
+pub struct TransparentScenePass<C: SceneCore> {
+    pub instancing_mode: InstancingMode,
+
+    /// The untransformed shader core, from which the `program` is rebuilt.
+    pub(crate) scene_core: PassCore<C>,
+
+    pub(crate) program: PassProgram<glium::Program>,
 }
 
 pub struct PlainScenePass<C: CoreInput> {
     pub instancing_mode: InstancingMode,
-    pub program: glium::Program,
 
-    pub _phantom: std::marker::PhantomData<C>,
+    /// The untransformed shader core of passes that write object IDs, from
+    /// which the `program` is rebuilt. Other plain scene passes do not
+    /// depend on the configuration.
+    pub(crate) scene_core: Option<PassCore<C>>,
+
+    /// The program, together with whether it writes into the object ID
+    /// buffer.
+    pub(crate) program: PassProgram<(glium::Program, bool)>,
 }
 
 pub trait RenderPassComponent {
@@ -71,5 +144,5 @@ pub trait CompositionPassComponent:
         core: shader::Core<Context, (), screen_quad::Vertex>,
     ) -> shader::Core<Context, (), screen_quad::Vertex>;
 
-    fn params(&self) -> <Self as HasCompositionPassParams<'_>>::Params;
+    fn params(&self, context: &Context) -> <Self as HasCompositionPassParams<'_>>::Params;
 }
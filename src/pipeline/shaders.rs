@@ -1,33 +1,87 @@
 use glium::uniforms::UniformType;
 
+use crate::pipeline::color_grading;
 use crate::{screen_quad, shader, Context};
 
+/// Shader core transform for lighting the scene in the forward path, i.e.
+/// without deferred shading.
+///
+/// If `clustered` is true, the core needs to have been transformed with
+/// `clustered_lighting_core_transform`, and the lights of each fragment's
+/// cluster are added to the main light.
 pub fn diffuse_scene_core_transform<P, I, V>(
+    clustered: bool,
     core: shader::Core<(Context, P), I, V>,
 ) -> shader::Core<(Context, P), I, V> {
-    let color_expr = if core.fragment.has_out("f_shadow") {
-        "vec4((0.3 + f_shadow * diffuse) * f_color.rgb, f_color.a)"
+    let main_light_expr = if core.fragment.has_out("f_shadow") {
+        "(0.3 + f_shadow * diffuse) * f_color.rgb + f_shadow * specular"
     } else {
-        "vec4((0.3 + diffuse) * f_color.rgb, f_color.a)"
+        "(0.3 + diffuse) * f_color.rgb + specular"
+    };
+
+    let color_expr = if clustered {
+        format!(
+            "vec4({} + clustered_diffuse * f_color.rgb + clustered_specular, f_color.a)",
+            main_light_expr,
+        )
+    } else {
+        format!("vec4({}, f_color.a)", main_light_expr)
+    };
+
+    let clustered_body = if clustered {
+        "
+            vec3 clustered_diffuse;
+            vec3 clustered_specular;
+            clustered_lighting(
+                v_world_pos.xyz,
+                world_normal,
+                eye_pos,
+                specular_params,
+                clustered_diffuse,
+                clustered_specular
+            );
+        "
+    } else {
+        ""
+    };
+
+    let normal = if core.fragment.has_out("f_normal") {
+        "f_normal"
+    } else {
+        "v_world_normal"
+    };
+
+    let specular_params = if core.fragment.has_out("f_specular") {
+        "f_specular"
+    } else {
+        "vec2(0.0, 1.0)"
     };
 
     let fragment = core
         .fragment
         .with_in_def(shader::defs::V_WORLD_NORMAL)
         .with_in_def(shader::defs::V_WORLD_POS)
-        .with_body(
+        .with_body(&format!(
             "
             float ambient = 0.3;
-            float diffuse = max(
-                dot(
-                    normalize(v_world_normal),
-                    normalize(context_main_light_pos - v_world_pos.xyz)
-                ),
-                0.05
-            );
+            vec3 world_normal = normalize({normal});
+            vec3 light_dir = normalize(context_main_light_pos - v_world_pos.xyz);
+            float diffuse = max(dot(world_normal, light_dir), 0.05);
+
+            // Blinn-Phong specular term
+            vec2 specular_params = {specular_params};
+            vec3 eye_pos = -transpose(mat3(context_camera_view)) * context_camera_view[3].xyz;
+            vec3 half_dir = normalize(light_dir + normalize(eye_pos - v_world_pos.xyz));
+            float specular = specular_params.x
+                * pow(max(dot(world_normal, half_dir), 0.0), specular_params.y)
+                * step(0.0, dot(world_normal, light_dir));
+            {clustered_body}
         ",
-        )
-        .with_out_expr("f_color", color_expr);
+            normal = normal,
+            specular_params = specular_params,
+            clustered_body = clustered_body,
+        ))
+        .with_out_expr("f_color", &color_expr);
 
     shader::Core {
         vertex: core.vertex,
@@ -51,9 +105,9 @@ pub fn composition_core<P>() -> shader::Core<P, (), screen_quad::Vertex> {
     shader::Core { vertex, fragment }
 }
 
-pub fn hdr_composition_core_transform<P>(
-    core: shader::Core<P, (), screen_quad::Vertex>,
-) -> shader::Core<P, (), screen_quad::Vertex> {
+pub fn hdr_composition_core_transform<P, I, V>(
+    core: shader::Core<P, I, V>,
+) -> shader::Core<P, I, V> {
     assert!(
         core.fragment.has_out_def(shader::defs::F_COLOR),
         "FragmentCore needs F_COLOR output for HDR composition pass"
@@ -64,7 +118,7 @@ pub fn hdr_composition_core_transform<P>(
         //.with_out_expr(shader::F_COLOR, "vec4(vec3(1.0) - exp(-f_color.rgb), 1.0)");
         .with_out_expr(
             "f_color",
-            "vec4(vec3(f_color) / (vec3(f_color) + 1.0), 1.0)",
+            "vec4(vec3(f_color) / (vec3(f_color) + 1.0), f_color.a)",
         );
 
     shader::Core {
@@ -73,10 +127,10 @@ pub fn hdr_composition_core_transform<P>(
     }
 }
 
-pub fn gamma_correction_composition_core_transform<P>(
-    core: shader::Core<P, (), screen_quad::Vertex>,
+pub fn gamma_correction_composition_core_transform<P, I, V>(
+    core: shader::Core<P, I, V>,
     gamma: f32,
-) -> shader::Core<P, (), screen_quad::Vertex> {
+) -> shader::Core<P, I, V> {
     assert!(
         core.fragment.has_out_def(shader::defs::F_COLOR),
         "FragmentCore needs F_COLOR output for gamma correction composition pass"
@@ -84,7 +138,7 @@ pub fn gamma_correction_composition_core_transform<P>(
 
     let fragment = core.fragment.with_out_expr(
         "f_color",
-        &format!("vec4(pow(vec3(f_color), vec3(1.0 / {})), 1.0)", gamma),
+        &format!("vec4(pow(vec3(f_color), vec3(1.0 / {})), f_color.a)", gamma),
     );
 
     shader::Core {
@@ -92,3 +146,31 @@ pub fn gamma_correction_composition_core_transform<P>(
         fragment,
     }
 }
+
+/// Shader core transform for mapping the colors of a core into the output
+/// range of the composition pass, as configured by `hdr`, `color_grading`
+/// and `gamma_correction` in `Config`.
+///
+/// With `color_grading`, the core expects the uniforms of
+/// `color_grading::Params`.
+pub fn color_correction_core_transform<P, I, V>(
+    hdr: Option<f32>,
+    color_grading: bool,
+    gamma_correction: Option<f32>,
+    mut core: shader::Core<P, I, V>,
+) -> shader::Core<P, I, V> {
+    if hdr.is_some() {
+        // TODO: Use factor
+        core = hdr_composition_core_transform(core);
+    }
+
+    if color_grading {
+        core = color_grading::shaders::core_transform(core);
+    }
+
+    if let Some(gamma) = gamma_correction {
+        core = gamma_correction_composition_core_transform(core, gamma);
+    }
+
+    core
+}
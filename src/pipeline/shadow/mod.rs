@@ -1,7 +1,20 @@
-//! Shadow mapping.
+//! Shadow mapping with cascades.
 //!
 //! Heavily inspired by:
 //! https://github.com/glium/glium/blob/master/examples/shadow_mapping.rs
+//!
+//! Cascade fitting follows:
+//! https://docs.microsoft.com/en-us/windows/win32/dxtecharticles/common-techniques-to-improve-shadow-depth-maps
+//!
+//! The camera frustum is split into `num_cascades` slices along the view
+//! direction. Each slice is enclosed by a bounding sphere, which gives us an
+//! orthographic light projection whose size does not change when the camera
+//! rotates. Snapping the projection to shadow map texels then keeps the
+//! shadows from shimmering when the camera moves.
+//!
+//! Additionally, a bounded number of point lights can cast shadows. For each
+//! of them, we render the scene into the six faces of a depth cube map, using
+//! the same programs as for the main light.
 
 mod shaders;
 
@@ -9,61 +22,175 @@ use log::info;
 
 use nalgebra as na;
 
-use glium::texture::DepthTexture2d;
-use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler};
+use glium::texture::depth_texture2d_array::DepthTexture2dArrayLayerMipmap;
+use glium::texture::{CubeLayer, DepthCubemap, DepthTexture2dArray};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, UniformValue, Uniforms};
 use glium::Surface;
 
 use crate::pipeline::render_pass::{HasScenePassParams, RenderPassComponent, ScenePassComponent};
-use crate::shader::{self, ToUniforms};
-use crate::{Camera, Context, DrawError, Drawable};
+use crate::shader::{self, HasUniforms, ToUniforms};
+use crate::{Camera, Context, DrawError, Drawable, Light, LightKind};
 
 pub use crate::CreationError;
 
-#[derive(Debug, Clone)]
+/// Maximal number of cascades that can be used.
+///
+/// The far distances of the cascades are passed to shaders in a single
+/// `vec4`, hence the limit.
+pub const MAX_CASCADES: usize = 4;
+
+/// Distance of the near plane used for rendering point light shadows.
+pub const POINT_LIGHT_SHADOW_NEAR: f32 = 0.05;
+
+/// Direction and up vector of the camera for each face of a cube map, as
+/// expected by OpenGL.
+const CUBE_FACES: [(CubeLayer, [f32; 3], [f32; 3]); 6] = [
+    (CubeLayer::PositiveX, [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    (CubeLayer::NegativeX, [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    (CubeLayer::PositiveY, [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    (CubeLayer::NegativeY, [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    (CubeLayer::PositiveZ, [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    (CubeLayer::NegativeZ, [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Config {
+    /// Size of the shadow map of each cascade.
     pub shadow_map_size: na::Vector2<u32>,
     pub shadow_value: f32,
     pub pcf_distance: usize,
+
+    /// Number of slices that the camera frustum is split into. Must be
+    /// between 1 and `MAX_CASCADES`.
+    pub num_cascades: usize,
+
+    /// Blends between uniform (0.0) and logarithmic (1.0) cascade splits.
+    pub cascade_split_lambda: f32,
+
+    /// View distance up to which shadows are rendered.
+    pub max_distance: f32,
+
+    /// Maximal number of point lights that cast shadows. Lights opt in with
+    /// `Light::casts_shadow`; if there are more of them, only the first ones
+    /// are shadowed. Point light shadows are only rendered with deferred
+    /// shading.
+    pub max_point_light_shadows: usize,
+
+    /// Size of each face of the point light shadow cube maps.
+    pub point_light_shadow_map_size: u32,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
-            shadow_map_size: na::Vector2::new(4096, 4096),
+            shadow_map_size: na::Vector2::new(2048, 2048),
             shadow_value: 0.5,
             pcf_distance: 1,
+            num_cascades: 3,
+            cascade_split_lambda: 0.75,
+            max_distance: 60.0,
+            max_point_light_shadows: 4,
+            point_light_shadow_map_size: 512,
         }
     }
 }
 
+/// One slice of the camera frustum, shadowed with its own shadow map layer.
+#[derive(Debug, Clone, Copy)]
+pub struct Cascade {
+    /// View distance at which this cascade ends.
+    pub split_distance: f32,
+
+    pub light_view: na::Matrix4<f32>,
+    pub light_projection: na::Matrix4<f32>,
+}
+
+/// A point light whose shadow cube map is rendered in the current frame.
+#[derive(Debug, Clone, Copy)]
+struct PointLight {
+    position: na::Point3<f32>,
+    far: f32,
+}
+
 pub struct ShadowMapping {
     config: Config,
-    shadow_texture: DepthTexture2d,
+    shadow_texture: DepthTexture2dArray,
+
+    point_light_shadow_textures: Vec<DepthCubemap>,
+    point_lights: Vec<PointLight>,
 }
 
 impl RenderPassComponent for ShadowMapping {
     fn clear_buffers<F: glium::backend::Facade>(&self, facade: &F) -> Result<(), DrawError> {
-        let mut shadow_target =
-            glium::framebuffer::SimpleFrameBuffer::depth_only(facade, &self.shadow_texture)?;
+        for i in 0..self.config.num_cascades {
+            let mut shadow_target =
+                glium::framebuffer::SimpleFrameBuffer::depth_only(facade, self.cascade_layer(i))?;
+
+            shadow_target.clear_depth(1.0);
+        }
 
-        shadow_target.clear_depth(1.0);
+        // Point light shadow maps are cleared in `set_point_lights`, once we
+        // know which of them are used.
 
         Ok(())
     }
 }
 
+/// Names of the elements of the light matrix array in the shader. Glium
+/// flattens uniform arrays into separate uniforms with these names.
+const LIGHT_PROJECTION_VIEW_NAMES: [&str; MAX_CASCADES] = [
+    "shadow_light_projection_views[0]",
+    "shadow_light_projection_views[1]",
+    "shadow_light_projection_views[2]",
+    "shadow_light_projection_views[3]",
+];
+
+#[derive(Clone, Copy)]
 pub struct ScenePassParams<'a> {
-    light_projection_view: na::Matrix4<f32>,
-    shadow_map: Sampler<'a, DepthTexture2d>,
+    num_cascades: usize,
+    light_projection_views: [[[f32; 4]; 4]; MAX_CASCADES],
+    cascade_splits: [f32; MAX_CASCADES],
+    shadow_map: Sampler<'a, DepthTexture2dArray>,
 }
 
-impl_uniform_input!(
-    ScenePassParams<'a>,
-    self => {
-        shadow_light_projection_view: [[f32; 4]; 4] = self.light_projection_view.into(),
-        shadow_map: Sampler<'a, DepthTexture2d> = self.shadow_map,
-    },
-);
+impl<'a> Uniforms for ScenePassParams<'a> {
+    fn visit_values<'b, F>(&'b self, mut output: F)
+    where
+        F: FnMut(&str, UniformValue<'b>),
+    {
+        use glium::uniforms::AsUniformValue;
+
+        let light_projection_views = LIGHT_PROJECTION_VIEW_NAMES
+            .iter()
+            .zip(self.light_projection_views.iter())
+            .take(self.num_cascades);
+
+        for (name, light_projection_view) in light_projection_views {
+            output(name, UniformValue::Mat4(*light_projection_view));
+        }
+
+        output(
+            "shadow_cascade_splits",
+            UniformValue::Vec4(self.cascade_splits),
+        );
+        output("shadow_map", self.shadow_map.as_uniform_value());
+    }
+}
+
+impl<'a, 'u> HasUniforms<'u> for ScenePassParams<'a> {
+    type Uniforms = Self;
+}
+
+impl<'a> ToUniforms for ScenePassParams<'a> {
+    fn to_uniforms(&self) -> Self {
+        *self
+    }
+}
 
 impl<'u> HasScenePassParams<'u> for ShadowMapping {
     type Params = ScenePassParams<'u>;
@@ -81,13 +208,24 @@ impl ScenePassComponent for ShadowMapping {
         shaders::render_shadowed_core_transform(
             self.config.shadow_value,
             self.config.pcf_distance,
+            self.config.num_cascades,
             core,
         )
     }
 
-    fn params(&self, context: &Context) -> ScenePassParams {
+    fn params(&self, context: &Context) -> ScenePassParams<'_> {
+        let mut light_projection_views = [[[0.0; 4]; 4]; MAX_CASCADES];
+        let mut cascade_splits = [f32::MAX; MAX_CASCADES];
+
+        for (i, cascade) in self.cascades(context).iter().enumerate() {
+            light_projection_views[i] = (cascade.light_projection * cascade.light_view).into();
+            cascade_splits[i] = cascade.split_distance;
+        }
+
         ScenePassParams {
-            light_projection_view: self.light_projection() * self.light_view(context),
+            num_cascades: self.config.num_cascades,
+            light_projection_views,
+            cascade_splits,
             shadow_map: Sampler::new(&self.shadow_texture)
                 .magnify_filter(MagnifySamplerFilter::Nearest)
                 .minify_filter(MinifySamplerFilter::Nearest),
@@ -100,29 +238,210 @@ impl ShadowMapping {
         facade: &F,
         config: &Config,
     ) -> Result<ShadowMapping, CreationError> {
-        info!("Creating shadow texture");
-        let shadow_texture =
-            DepthTexture2d::empty(facade, config.shadow_map_size.x, config.shadow_map_size.y)?;
+        if config.num_cascades == 0 || config.num_cascades > MAX_CASCADES {
+            return Err(CreationError::InvalidConfig(format!(
+                "Number of cascades must be between 1 and {}, got {}",
+                MAX_CASCADES, config.num_cascades
+            )));
+        }
+
+        info!(
+            "Creating shadow texture with {} cascades",
+            config.num_cascades
+        );
+        let shadow_texture = DepthTexture2dArray::empty(
+            facade,
+            config.shadow_map_size.x,
+            config.shadow_map_size.y,
+            config.num_cascades as u32,
+        )?;
+
+        info!(
+            "Creating {} point light shadow textures",
+            config.max_point_light_shadows
+        );
+        let point_light_shadow_textures = (0..config.max_point_light_shadows)
+            .map(|_| DepthCubemap::empty(facade, config.point_light_shadow_map_size))
+            .collect::<Result<Vec<_>, _>>()?;
 
         info!("Shadow mapping initialized");
 
         Ok(ShadowMapping {
             config: config.clone(),
             shadow_texture,
+            point_light_shadow_textures,
+            point_lights: Vec::new(),
         })
     }
 
-    fn light_projection(&self) -> na::Matrix4<f32> {
-        let w = 20.0;
-        na::Matrix4::new_orthographic(-w, w, -w, w, 0.01, 50.0)
+    pub fn config(&self) -> &Config {
+        &self.config
     }
 
-    fn light_view(&self, context: &Context) -> na::Matrix4<f32> {
-        na::Matrix4::look_at_rh(
-            &context.main_light_pos,
-            &context.main_light_center,
-            &na::Vector3::new(0.0, 0.0, 1.0),
-        )
+    /// Selects the point lights that cast shadows. The order determines which
+    /// shadow cube map belongs to which light.
+    ///
+    /// Spot lights are not shadowed, since they would need a single shadow
+    /// map in the direction of their cone rather than a cube map.
+    pub fn shadow_casting_point_lights<'a>(
+        &self,
+        lights: &'a [Light],
+    ) -> impl Iterator<Item = &'a Light> + 'a {
+        lights
+            .iter()
+            .filter(|light| light.casts_shadow && !light.is_main && light.kind == LightKind::Point)
+            .take(self.config.max_point_light_shadows)
+    }
+
+    /// Sets the position and range of the point lights whose shadows are to
+    /// be rendered in the following shadow passes, and clears their shadow
+    /// cube maps.
+    pub fn set_point_lights<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        lights: &[(na::Point3<f32>, f32)],
+    ) -> Result<(), DrawError> {
+        self.point_lights = lights
+            .iter()
+            .take(self.point_light_shadow_textures.len())
+            .map(|&(position, far)| PointLight { position, far })
+            .collect();
+
+        let shadow_textures = self
+            .point_light_shadow_textures
+            .iter()
+            .take(self.point_lights.len());
+
+        for shadow_texture in shadow_textures {
+            for &(layer, _, _) in CUBE_FACES.iter() {
+                let mut shadow_target = glium::framebuffer::SimpleFrameBuffer::depth_only(
+                    facade,
+                    shadow_texture.main_level().image(layer),
+                )?;
+
+                shadow_target.clear_depth(1.0);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Shadow map of the main light, with one layer per cascade.
+    pub fn shadow_texture(&self) -> &DepthTexture2dArray {
+        &self.shadow_texture
+    }
+
+    /// Shadow cube maps, in the order given by `shadow_casting_point_lights`.
+    pub fn point_light_shadow_maps(&self) -> &[DepthCubemap] {
+        &self.point_light_shadow_textures
+    }
+
+    fn cascade_layer(&self, i: usize) -> DepthTexture2dArrayLayerMipmap<'_> {
+        // Safe to unwrap, since we have created one layer per cascade.
+        self.shadow_texture.layer(i as u32).unwrap().main_level()
+    }
+
+    /// Split the camera frustum into cascades and fit a light projection to
+    /// each of them.
+    pub fn cascades(&self, context: &Context) -> Vec<Cascade> {
+        let camera = &context.camera;
+        let num_cascades = self.config.num_cascades;
+
+        let (near, far) = camera.depth_range();
+        let shadow_far = far.min(self.config.max_distance).max(near);
+
+        // Corners of the view frustum in world space, with the near plane
+        // corners first.
+        let inv_projection_view = (camera.projection * camera.view)
+            .try_inverse()
+            .unwrap_or_else(na::Matrix4::identity);
+        let mut frustum_corners = Vec::with_capacity(8);
+        for &z in &[-1.0, 1.0] {
+            for &(x, y) in &[(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                frustum_corners
+                    .push(inv_projection_view.transform_point(&na::Point3::new(x, y, z)));
+            }
+        }
+
+        // All cascades share the rotation of the light; we use this space
+        // for snapping to texels.
+        let light_dir = (context.main_light_center - context.main_light_pos).normalize();
+        let up = if light_dir.z.abs() > 0.99 {
+            na::Vector3::y()
+        } else {
+            na::Vector3::z()
+        };
+        let light_view =
+            na::Matrix4::look_at_rh(&na::Point3::origin(), &na::Point3::from(light_dir), &up);
+        let light_pos_depth = -light_view.transform_point(&context.main_light_pos).z;
+
+        let mut cascades = Vec::with_capacity(num_cascades);
+        let mut split_near = near;
+
+        for i in 1..=num_cascades {
+            // Practical split scheme: blend logarithmic and uniform splits.
+            let fraction = i as f32 / num_cascades as f32;
+            let log_split = near * (shadow_far / near).powf(fraction);
+            let uniform_split = near + (shadow_far - near) * fraction;
+            let split_far = self.config.cascade_split_lambda * log_split
+                + (1.0 - self.config.cascade_split_lambda) * uniform_split;
+
+            // Frustum edges are straight lines, along which view depth is
+            // linear, so we can interpolate the corners of the slice.
+            let mut slice_corners = Vec::with_capacity(8);
+            for &depth in &[split_near, split_far] {
+                let t = (depth - near) / (far - near);
+
+                for j in 0..4 {
+                    slice_corners.push(
+                        frustum_corners[j] + (frustum_corners[j + 4] - frustum_corners[j]) * t,
+                    );
+                }
+            }
+
+            // Enclose the slice by a sphere, so that the projection size
+            // stays constant under camera rotation.
+            let center = slice_corners
+                .iter()
+                .fold(na::Vector3::zeros(), |sum, p| sum + p.coords)
+                / slice_corners.len() as f32;
+            let radius = slice_corners
+                .iter()
+                .map(|p| (p.coords - center).norm())
+                .fold(0.0, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            // Snap the center to texels, so that shadow edges do not shimmer
+            // when the camera moves.
+            let texel_size = 2.0 * radius / self.config.shadow_map_size.x as f32;
+            let center = light_view.transform_point(&na::Point3::from(center));
+            let center_x = (center.x / texel_size).floor() * texel_size;
+            let center_y = (center.y / texel_size).floor() * texel_size;
+
+            // Extend the depth range towards the light, so that we catch
+            // shadow casters in front of the slice.
+            let z_near = (-center.z - radius).min(light_pos_depth);
+            let z_far = -center.z + radius;
+
+            let light_projection = na::Matrix4::new_orthographic(
+                center_x - radius,
+                center_x + radius,
+                center_y - radius,
+                center_y + radius,
+                z_near,
+                z_far,
+            );
+
+            cascades.push(Cascade {
+                split_distance: split_far,
+                light_view,
+                light_projection,
+            });
+
+            split_near = split_far;
+        }
+
+        cascades
     }
 
     pub fn shadow_pass_core_transform<P, I, V>(
@@ -132,7 +451,8 @@ impl ShadowMapping {
         shaders::depth_map_core_transform(core)
     }
 
-    /// Render scene from the light's point of view into depth buffer.
+    /// Render scene from the light's point of view into the depth buffer of
+    /// each cascade.
     pub fn shadow_pass<F, I, V, P>(
         &self,
         facade: &F,
@@ -146,23 +466,6 @@ impl ShadowMapping {
         V: glium::vertex::Vertex,
         P: ToUniforms,
     {
-        let mut shadow_target =
-            glium::framebuffer::SimpleFrameBuffer::depth_only(facade, &self.shadow_texture)?;
-
-        let light_projection = self.light_projection();
-        let light_view = self.light_view(params.0);
-
-        let camera = Camera {
-            viewport_size: params.0.camera.viewport_size,
-            projection: light_projection,
-            view: light_view,
-        };
-
-        let light_context = Context {
-            camera,
-            ..*params.0
-        };
-
         let draw_params = glium::DrawParameters {
             depth: glium::Depth {
                 test: glium::DepthTest::IfLessOrEqual,
@@ -172,11 +475,75 @@ impl ShadowMapping {
             ..draw_params.clone()
         };
 
-        drawable.draw(
-            program,
-            &(light_context, params.1),
-            &draw_params,
-            &mut shadow_target,
-        )
+        for (i, cascade) in self.cascades(params.0).into_iter().enumerate() {
+            let mut shadow_target =
+                glium::framebuffer::SimpleFrameBuffer::depth_only(facade, self.cascade_layer(i))?;
+
+            let camera = Camera {
+                viewport_size: params.0.camera.viewport_size,
+                projection: cascade.light_projection,
+                view: cascade.light_view,
+            };
+
+            let light_context = Context {
+                camera,
+                ..params.0.clone()
+            };
+
+            drawable.draw(
+                program,
+                &(light_context, &params.1),
+                &draw_params,
+                &mut shadow_target,
+            )?;
+        }
+
+        let point_lights = self
+            .point_lights
+            .iter()
+            .zip(self.point_light_shadow_textures.iter());
+
+        for (point_light, shadow_texture) in point_lights {
+            let projection = na::Perspective3::new(
+                1.0,
+                std::f32::consts::FRAC_PI_2,
+                POINT_LIGHT_SHADOW_NEAR,
+                point_light.far.max(2.0 * POINT_LIGHT_SHADOW_NEAR),
+            )
+            .to_homogeneous();
+
+            for &(layer, direction, up) in CUBE_FACES.iter() {
+                let mut shadow_target = glium::framebuffer::SimpleFrameBuffer::depth_only(
+                    facade,
+                    shadow_texture.main_level().image(layer),
+                )?;
+
+                let view = na::Matrix4::look_at_rh(
+                    &point_light.position,
+                    &(point_light.position + na::Vector3::from(direction)),
+                    &na::Vector3::from(up),
+                );
+
+                let camera = Camera {
+                    viewport_size: params.0.camera.viewport_size,
+                    projection,
+                    view,
+                };
+
+                let light_context = Context {
+                    camera,
+                    ..params.0.clone()
+                };
+
+                drawable.draw(
+                    program,
+                    &(light_context, &params.1),
+                    &draw_params,
+                    &mut shadow_target,
+                )?;
+            }
+        }
+
+        Ok(())
     }
 }
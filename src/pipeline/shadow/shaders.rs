@@ -24,9 +24,13 @@ pub fn depth_map_core_transform<P, I, V>(mut core: shader::Core<P, I, V>) -> sha
 }
 
 /// Shader core for rendering the shadowed scene.
+///
+/// Each fragment picks the cascade that covers its view distance, and then
+/// looks up its shadow value in the corresponding layer of the shadow map.
 pub fn render_shadowed_core_transform<P, I, V>(
     shadow_value: f32,
     pcf_distance: usize,
+    num_cascades: usize,
     core: shader::Core<(Context, P), I, V>,
 ) -> shader::Core<(Context, P), I, V> {
     assert!(
@@ -38,32 +42,51 @@ pub fn render_shadowed_core_transform<P, I, V>(
         "VertexCore needs V_WORLD_NORMAL output for shadow mapping"
     );
 
-    // Position of current vertex in light space
-    let v_light_space_pos = (
-        "v_light_space_pos",
-        shader::VertexOutDef(shader::Type::FloatVec4, shader::VertexOutQualifier::Smooth),
-    );
-
-    let vertex = core
-        .vertex
-        .with_extra_uniform("shadow_light_projection_view", UniformType::FloatMat4)
-        .with_out(
-            v_light_space_pos.clone(),
-            // Bias shadow coord a bit in the direction of the normal --
-            // this is a simple fix for a lot of self-shadowing artifacts
-            "shadow_light_projection_view * (v_world_pos + 0.02 * vec4(v_world_normal, 0.0))",
-        );
-
+    // Uniform arrays are not supported by `extra_uniforms`, so we declare
+    // the light matrices directly.
     let shadow_calculation = "
-        float shadow_calculation(vec4 light_space_pos) {
-            vec3 light_dir = normalize(vec3(context_main_light_pos - v_world_pos.xyz));
+        uniform mat4 shadow_light_projection_views[NUM_CASCADES];
 
-            vec3 proj_coords = light_space_pos.xyz / light_space_pos.w;
-            proj_coords = proj_coords * 0.5 + 0.5;
+        float shadow_calculation(vec4 world_pos) {
+            vec3 light_dir = normalize(vec3(context_main_light_pos - world_pos.xyz));
 
             if (dot(light_dir, v_world_normal) < 0.0)
                 return SHADOW_VALUE;
 
+            float view_depth = -(context_camera_view * world_pos).z;
+
+            if (view_depth > shadow_cascade_splits[NUM_CASCADES - 1])
+                return 1.0;
+
+            int cascade = NUM_CASCADES - 1;
+            for (int i = 0; i < NUM_CASCADES - 1; ++i) {
+                if (view_depth < shadow_cascade_splits[i]) {
+                    cascade = i;
+                    break;
+                }
+            }
+
+            // Bias shadow coord a bit in the direction of the normal --
+            // this is a simple fix for a lot of self-shadowing artifacts.
+            // Cascades further away cover more world space per texel, so the
+            // bias is scaled with the texel size. Note that the length of the
+            // first row of the light matrix is the inverse cascade radius.
+            mat4 light_projection_view = shadow_light_projection_views[cascade];
+            vec2 texel_size = 1.0 / textureSize(shadow_map, 0).xy;
+            float inv_radius = length(vec3(
+                light_projection_view[0][0],
+                light_projection_view[1][0],
+                light_projection_view[2][0]
+            ));
+            float world_texel_size = 2.0 * texel_size.x / inv_radius;
+            float normal_bias = max(0.02, 1.5 * world_texel_size);
+
+            vec4 light_space_pos = light_projection_view
+                * (world_pos + normal_bias * vec4(v_world_normal, 0.0));
+
+            vec3 proj_coords = light_space_pos.xyz / light_space_pos.w;
+            proj_coords = proj_coords * 0.5 + 0.5;
+
             // Q: Is there a way to do this on texture-level?
             // Answer: yes, for x/y, but it's not supported in glium.
             // (GL_CLAMP_TO_BORDER + GL_TEXTURE_BORDER_COLOR)
@@ -76,16 +99,14 @@ pub fn render_shadowed_core_transform<P, I, V>(
                 return 1.0;
             }
 
-            vec2 texel_size = 1.0 / textureSize(shadow_map, 0);
-
             float shadow = 0.0;
             for (int x = -PCF_DISTANCE; x <= PCF_DISTANCE; ++x) {
                 for (int y = -PCF_DISTANCE; y <= PCF_DISTANCE; ++y) {
                     float closest_depth = texture(
-                        shadow_map, 
-                        proj_coords.xy + vec2(x, y) * texel_size
+                        shadow_map,
+                        vec3(proj_coords.xy + vec2(x, y) * texel_size, cascade)
                     ).r;
-                    
+
                     shadow += proj_coords.z > closest_depth ? SHADOW_VALUE : 1.0;
                 }
             }
@@ -95,21 +116,22 @@ pub fn render_shadowed_core_transform<P, I, V>(
         }
     "
     .to_string()
+    .replace("NUM_CASCADES", &num_cascades.to_string())
     .replace("SHADOW_VALUE", &shadow_value.to_string())
     .replace("PCF_DISTANCE", &pcf_distance.to_string())
     .replace("PCF_SAMPLES", &(2 * pcf_distance + 1).pow(2).to_string());
 
     let fragment = core
         .fragment
-        .with_extra_uniform("shadow_map", UniformType::Sampler2d)
+        .with_extra_uniform("shadow_cascade_splits", UniformType::FloatVec4)
+        .with_extra_uniform("shadow_map", UniformType::Sampler2dArray)
         .with_in_def(shader::defs::V_WORLD_POS)
         .with_in_def(shader::defs::V_WORLD_NORMAL)
-        .with_in_def(v_light_space_pos)
         .with_defs(&shadow_calculation)
-        .with_out(
-            shader::defs::F_SHADOW,
-            "shadow_calculation(v_light_space_pos)",
-        );
+        .with_out(shader::defs::F_SHADOW, "shadow_calculation(v_world_pos)");
 
-    shader::Core { vertex, fragment }
+    shader::Core {
+        vertex: core.vertex,
+        fragment,
+    }
 }
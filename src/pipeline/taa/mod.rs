@@ -0,0 +1,237 @@
+//! Temporal anti-aliasing.
+//!
+//! For reference:
+//! http://advances.realtimerendering.com/s2014/index.html#_HIGH-QUALITY_TEMPORAL_SUPERSAMPLING
+//!
+//! Each frame, the projection is jittered by a sub-pixel offset, so that
+//! consecutive frames sample different positions within each pixel. The
+//! jittered frames are accumulated in a history buffer. To follow camera and
+//! object motion, the history is reprojected with the velocity buffer of the
+//! shaded scene pass. History colors are clamped to the neighbourhood of the
+//! current pixel, which hides most ghosting from disocclusions.
+
+pub mod shaders;
+
+use log::info;
+use nalgebra as na;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{DepthTexture2d, MipmapsOption, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+use glium::{uniform, Program, Surface, Texture2d};
+
+use crate::{shader, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    /// Weight of the current frame when blending with the history. Smaller
+    /// values give smoother edges, but slower response to changes.
+    pub blend_factor: f32,
+
+    /// Number of distinct jitter offsets that are cycled through.
+    pub num_jitter_samples: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            blend_factor: 0.1,
+            num_jitter_samples: 8,
+        }
+    }
+}
+
+pub struct TAA {
+    config: Config,
+
+    /// We alternate between the two textures, reading the history from one
+    /// and writing the new frame into the other.
+    history_textures: [Texture2d; 2],
+
+    /// Index of the texture that was written last.
+    current_index: usize,
+
+    /// Whether the history textures hold a previous frame.
+    history_valid: bool,
+
+    frame_index: usize,
+
+    /// Jitter of the current frame, in normalized device coordinates.
+    jitter: na::Vector2<f32>,
+
+    program: Program,
+    screen_quad: ScreenQuad,
+}
+
+impl TAA {
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        let history_textures = [
+            Self::create_texture(facade, target_size)?,
+            Self::create_texture(facade, target_size)?,
+        ];
+
+        info!("Creating TAA program");
+        let program =
+            shaders::resolve_core().build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        info!("Creating screen quad");
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(TAA {
+            config: config.clone(),
+            history_textures,
+            current_index: 0,
+            history_valid: false,
+            frame_index: 0,
+            jitter: na::Vector2::zeros(),
+            program,
+            screen_quad,
+        })
+    }
+
+    /// Advances to the next jitter offset.
+    pub fn start_frame(&mut self, target_size: (u32, u32)) {
+        let num_samples = self.config.num_jitter_samples.max(1);
+        self.frame_index = (self.frame_index + 1) % num_samples;
+
+        // Halton sequence offsets in [-0.5, 0.5] pixels. We skip index zero,
+        // which would be the same for both dimensions.
+        let offset = na::Vector2::new(
+            halton(self.frame_index + 1, 2) - 0.5,
+            halton(self.frame_index + 1, 3) - 0.5,
+        );
+
+        self.jitter = na::Vector2::new(
+            2.0 * offset.x / target_size.0 as f32,
+            2.0 * offset.y / target_size.1 as f32,
+        );
+    }
+
+    /// Applies the jitter of the current frame to `projection`. This works
+    /// for both perspective and orthographic projections.
+    pub fn jitter_projection(&self, projection: &na::Matrix4<f32>) -> na::Matrix4<f32> {
+        Self::translation(&self.jitter) * projection
+    }
+
+    /// Removes the jitter of the current frame from `projection`.
+    pub fn unjitter_projection(&self, projection: &na::Matrix4<f32>) -> na::Matrix4<f32> {
+        Self::translation(&-self.jitter) * projection
+    }
+
+    /// Forgets the history, e.g. after a camera cut.
+    pub fn reset_history(&mut self) {
+        self.history_valid = false;
+    }
+
+    /// Texture holding the result of the last `draw` call.
+    pub fn output_texture(&self) -> &Texture2d {
+        &self.history_textures[self.current_index]
+    }
+
+    /// Blends `color_texture` with the reprojected history, writing the
+    /// result into `output_texture`. This result becomes the history of
+    /// the next frame.
+    pub fn draw<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        color_texture: &Texture2d,
+        depth_texture: &DepthTexture2d,
+        velocity_texture: &Texture2d,
+    ) -> Result<(), DrawError> {
+        let (history_texture, output_texture) = if self.current_index == 0 {
+            (&self.history_textures[0], &self.history_textures[1])
+        } else {
+            (&self.history_textures[1], &self.history_textures[0])
+        };
+
+        let mut framebuffer = SimpleFrameBuffer::new(facade, output_texture)?;
+
+        framebuffer.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.program,
+            &uniform! {
+                color_texture: Self::sampler(color_texture),
+                history_texture: Self::sampler(history_texture),
+                velocity_texture: velocity_texture,
+                scene_depth_texture: depth_texture,
+                taa_blend_factor: self.config.blend_factor,
+                taa_history_valid: self.history_valid,
+            },
+            &Default::default(),
+        )?;
+
+        self.current_index = 1 - self.current_index;
+        self.history_valid = true;
+
+        Ok(())
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        self.history_textures = [
+            Self::create_texture(facade, target_size)?,
+            Self::create_texture(facade, target_size)?,
+        ];
+        self.history_valid = false;
+
+        Ok(())
+    }
+
+    fn translation(offset: &na::Vector2<f32>) -> na::Matrix4<f32> {
+        // Offsets clip space by `offset * w`, so that the offset in
+        // normalized device coordinates is independent of depth.
+        let mut matrix = na::Matrix4::identity();
+        matrix[(0, 3)] = offset.x;
+        matrix[(1, 3)] = offset.y;
+        matrix
+    }
+
+    fn sampler(texture: &Texture2d) -> Sampler<'_, Texture2d> {
+        Sampler::new(texture)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .wrap_function(SamplerWrapFunction::Clamp)
+    }
+
+    fn create_texture<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<Texture2d, CreationError> {
+        Ok(Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+}
+
+/// Returns the element of the Halton sequence with the given index and base.
+fn halton(mut index: usize, base: usize) -> f32 {
+    let mut factor = 1.0;
+    let mut result = 0.0;
+
+    while index > 0 {
+        factor /= base as f32;
+        result += factor * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
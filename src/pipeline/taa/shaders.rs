@@ -0,0 +1,69 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader};
+
+/// Shader core for blending the current frame with the reprojected history.
+///
+/// Motion vectors are taken from the closest pixel in the 3x3 neighbourhood,
+/// so that the edges of moving objects are reprojected along with them.
+pub fn resolve_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty()
+        .with_out(shader::defs::V_TEX_COORD, "tex_coord")
+        .with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("color_texture", UniformType::Sampler2d)
+        .with_extra_uniform("history_texture", UniformType::Sampler2d)
+        .with_extra_uniform("velocity_texture", UniformType::Sampler2d)
+        .with_extra_uniform("scene_depth_texture", UniformType::Sampler2d)
+        .with_extra_uniform("taa_blend_factor", UniformType::Float)
+        .with_extra_uniform("taa_history_valid", UniformType::Bool)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_body(
+            "
+            vec2 texel_size = 1.0 / vec2(textureSize(color_texture, 0));
+
+            vec3 current = texture(color_texture, v_tex_coord).rgb;
+            vec3 color_min = current;
+            vec3 color_max = current;
+
+            float closest_depth = 1.0;
+            vec2 closest_offset = vec2(0.0);
+
+            for (int x = -1; x <= 1; x++) {
+                for (int y = -1; y <= 1; y++) {
+                    vec2 offset = vec2(x, y) * texel_size;
+
+                    vec3 neighbour = texture(color_texture, v_tex_coord + offset).rgb;
+                    color_min = min(color_min, neighbour);
+                    color_max = max(color_max, neighbour);
+
+                    float depth = texture(scene_depth_texture, v_tex_coord + offset).r;
+                    if (depth < closest_depth) {
+                        closest_depth = depth;
+                        closest_offset = offset;
+                    }
+                }
+            }
+
+            vec2 velocity = texture(velocity_texture, v_tex_coord + closest_offset).xy;
+            vec2 history_coord = v_tex_coord - velocity;
+
+            vec3 color = current;
+
+            if (taa_history_valid
+                && all(greaterThanEqual(history_coord, vec2(0.0)))
+                && all(lessThanEqual(history_coord, vec2(1.0)))) {
+                vec3 history = texture(history_texture, history_coord).rgb;
+
+                // Neighbourhood clamping
+                history = clamp(history, color_min, color_max);
+
+                color = mix(history, current, taa_blend_factor);
+            }
+            ",
+        )
+        .with_out(shader::defs::F_COLOR, "vec4(color, 1.0)");
+
+    shader::Core { vertex, fragment }
+}
@@ -0,0 +1,208 @@
+//! Rendering at a lower internal resolution, which is upscaled to the
+//! target.
+//!
+//! The scene passes, composition and postprocessing run at the render size,
+//! which is the target size multiplied by `Config::render_scale`. At the end
+//! of postprocessing, the image and the depth buffer are upscaled to the
+//! target size. Outlines and plain scene passes after postprocessing, e.g.
+//! for UI, are then drawn at the full resolution.
+//!
+//! While the scene is drawn, the camera's viewport size is set to the render
+//! size, so sizes that are given in pixels, e.g. the thickness of lines,
+//! refer to pixels at the render size.
+
+pub mod shaders;
+
+use log::info;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{
+    DepthFormat, DepthTexture2d, MipmapsOption, Texture2d, UncompressedFloatFormat,
+};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+use glium::{uniform, Program, Surface};
+
+use crate::{shader, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Filter {
+    /// Bilinear interpolation, which is cheap but blurry.
+    Bilinear,
+
+    /// Bilinear interpolation followed by sharpening, which restores some
+    /// of the contrast that is lost when upscaling. A `sharpness` of zero
+    /// is the same as `Bilinear`, typical values are between zero and one.
+    Sharpened { sharpness: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    /// Scale of the render size relative to the target size, usually
+    /// between zero and one.
+    pub render_scale: f32,
+
+    pub filter: Filter,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            render_scale: 0.75,
+            filter: Filter::Sharpened { sharpness: 0.5 },
+        }
+    }
+}
+
+impl Config {
+    /// Returns the size at which the scene is drawn for the given target
+    /// size.
+    pub fn render_size(&self, target_size: (u32, u32)) -> (u32, u32) {
+        let scale = |size: u32| ((size as f32 * self.render_scale).round() as u32).max(1);
+
+        (scale(target_size.0), scale(target_size.1))
+    }
+}
+
+pub struct Upscale {
+    config: Config,
+
+    color_texture: Texture2d,
+    depth_texture: DepthTexture2d,
+
+    program: Program,
+    screen_quad: ScreenQuad,
+}
+
+impl Upscale {
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        info!(
+            "Creating upscale textures for render size {:?}",
+            config.render_size(target_size)
+        );
+        let color_texture = Self::create_color_texture(facade, config.render_size(target_size))?;
+        let depth_texture = Self::create_depth_texture(facade, target_size)?;
+
+        info!("Creating upscale program");
+        let program = shaders::upscale_core(config.filter)
+            .build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        info!("Creating screen quad");
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(Upscale {
+            config: config.clone(),
+            color_texture,
+            depth_texture,
+            program,
+            screen_quad,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Texture at the render size into which postprocessing draws the
+    /// scene before it is upscaled.
+    pub fn color_texture(&self) -> &Texture2d {
+        &self.color_texture
+    }
+
+    /// Depth buffer at the target size, holding the result of the last
+    /// `draw` call.
+    pub fn depth_texture(&self) -> &DepthTexture2d {
+        &self.depth_texture
+    }
+
+    /// Upscales `color_texture` into `target_texture`, and
+    /// `scene_depth_texture` into `depth_texture`.
+    pub fn draw<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        scene_depth_texture: &DepthTexture2d,
+        target_texture: &Texture2d,
+    ) -> Result<(), DrawError> {
+        let mut framebuffer =
+            SimpleFrameBuffer::with_depth_buffer(facade, target_texture, &self.depth_texture)?;
+
+        let color_texture = Sampler::new(&self.color_texture)
+            .magnify_filter(MagnifySamplerFilter::Linear)
+            .minify_filter(MinifySamplerFilter::Linear)
+            .wrap_function(SamplerWrapFunction::Clamp);
+        let sharpness = match self.config.filter {
+            Filter::Bilinear => 0.0,
+            Filter::Sharpened { sharpness } => sharpness,
+        };
+
+        framebuffer.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.program,
+            &uniform! {
+                color_texture: color_texture,
+                depth_texture: scene_depth_texture,
+                upscale_sharpness: sharpness,
+            },
+            &glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::DepthTest::Overwrite,
+                    write: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )?;
+
+        Ok(())
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        self.color_texture =
+            Self::create_color_texture(facade, self.config.render_size(target_size))?;
+        self.depth_texture = Self::create_depth_texture(facade, target_size)?;
+
+        Ok(())
+    }
+
+    fn create_color_texture<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<Texture2d, CreationError> {
+        Ok(Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32F32F32F32,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+
+    fn create_depth_texture<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<DepthTexture2d, CreationError> {
+        Ok(DepthTexture2d::empty_with_format(
+            facade,
+            DepthFormat::F32,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+}
@@ -0,0 +1,57 @@
+use glium::uniforms::UniformType;
+
+use crate::pipeline::upscale::Filter;
+use crate::{screen_quad, shader};
+
+/// Shader core for upscaling the postprocessed scene to the target size.
+/// The depth buffer is upscaled as well, taking the nearest texel, so that
+/// plain scene passes after postprocessing can still be depth tested
+/// against the scene.
+pub fn upscale_core(filter: Filter) -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty()
+        .with_out(shader::defs::V_TEX_COORD, "tex_coord")
+        .with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("color_texture", UniformType::Sampler2d)
+        .with_extra_uniform("depth_texture", UniformType::Sampler2d)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_body(
+            "
+            vec4 color = texture(color_texture, v_tex_coord);
+
+            ivec2 depth_size = textureSize(depth_texture, 0);
+            ivec2 depth_texel = min(ivec2(v_tex_coord * vec2(depth_size)), depth_size - 1);
+            gl_FragDepth = texelFetch(depth_texture, depth_texel, 0).r;
+            ",
+        );
+
+    let fragment = match filter {
+        Filter::Bilinear => fragment,
+        Filter::Sharpened { .. } => fragment
+            .with_extra_uniform("upscale_sharpness", UniformType::Float)
+            .with_body(
+                "
+                // Sharpen by subtracting the neighbors at a distance of one
+                // source texel. The result is clamped to the neighborhood
+                // to avoid halos around edges.
+                vec2 texel_size = 1.0 / vec2(textureSize(color_texture, 0));
+                vec3 n = texture(color_texture, v_tex_coord + vec2(0.0, texel_size.y)).rgb;
+                vec3 s = texture(color_texture, v_tex_coord - vec2(0.0, texel_size.y)).rgb;
+                vec3 e = texture(color_texture, v_tex_coord + vec2(texel_size.x, 0.0)).rgb;
+                vec3 w = texture(color_texture, v_tex_coord - vec2(texel_size.x, 0.0)).rgb;
+
+                vec3 neighborhood_min = min(min(min(n, s), min(e, w)), color.rgb);
+                vec3 neighborhood_max = max(max(max(n, s), max(e, w)), color.rgb);
+
+                vec3 sharpened = color.rgb
+                    + upscale_sharpness * (color.rgb - 0.25 * (n + s + e + w));
+                color.rgb = clamp(sharpened, neighborhood_min, neighborhood_max);
+                ",
+            ),
+    };
+
+    let fragment = fragment.with_out(shader::defs::F_COLOR, "color");
+
+    shader::Core { vertex, fragment }
+}
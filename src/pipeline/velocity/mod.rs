@@ -0,0 +1,189 @@
+//! Screen-space motion vectors.
+//!
+//! The shaded scene pass writes the motion of each fragment since the
+//! previous frame into a velocity texture. Camera motion is given by
+//! `Context::previous_camera`, while object motion needs to be provided by
+//! scene cores through `V_PREVIOUS_WORLD_POS`. For pixels that are not
+//! covered by the scene, the motion of the camera is reconstructed from the
+//! scene depth.
+
+pub mod shaders;
+
+use nalgebra as na;
+
+use log::info;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{DepthTexture2d, MipmapsOption, UncompressedFloatFormat};
+use glium::{uniform, Program, Surface, Texture2d};
+
+use crate::pipeline::render_pass::{HasScenePassParams, RenderPassComponent, ScenePassComponent};
+use crate::{shader, Context, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+pub struct Velocity {
+    velocity_texture: Texture2d,
+    background_program: Program,
+    screen_quad: ScreenQuad,
+
+    /// Projection and view of the current and of the previous frame, if a
+    /// frame has been started.
+    projection_views: Option<(na::Matrix4<f32>, na::Matrix4<f32>)>,
+}
+
+impl RenderPassComponent for Velocity {
+    fn clear_buffers<F: glium::backend::Facade>(&self, facade: &F) -> Result<(), DrawError> {
+        let mut framebuffer = SimpleFrameBuffer::new(facade, &self.velocity_texture)?;
+        framebuffer.clear_color(0.0, 0.0, 0.0, 0.0);
+
+        Ok(())
+    }
+}
+
+pub struct ScenePassParams {
+    projection_view: na::Matrix4<f32>,
+    previous_projection_view: na::Matrix4<f32>,
+}
+
+impl_uniform_input!(
+    ScenePassParams,
+    self => {
+        velocity_projection_view: [[f32; 4]; 4] = self.projection_view,
+        velocity_previous_projection_view: [[f32; 4]; 4] = self.previous_projection_view,
+    },
+);
+
+impl<'u> HasScenePassParams<'u> for Velocity {
+    type Params = ScenePassParams;
+}
+
+impl ScenePassComponent for Velocity {
+    fn core_transform<P, I, V>(
+        &self,
+        core: shader::Core<(Context, P), I, V>,
+    ) -> shader::Core<(Context, P), I, V> {
+        shaders::velocity_core_transform(core)
+    }
+
+    fn output_textures(&self) -> Vec<(&'static str, &Texture2d)> {
+        vec![(shaders::F_VELOCITY.0, &self.velocity_texture)]
+    }
+
+    fn params(&self, context: &Context) -> ScenePassParams {
+        let (projection_view, previous_projection_view) = self
+            .projection_views
+            .unwrap_or_else(|| Self::projection_views(context));
+
+        ScenePassParams {
+            projection_view,
+            previous_projection_view,
+        }
+    }
+}
+
+impl Velocity {
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        let velocity_texture = Self::create_texture(facade, target_size)?;
+
+        info!("Creating velocity background program");
+        let background_program =
+            shaders::background_core().build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        info!("Creating screen quad");
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(Velocity {
+            velocity_texture,
+            background_program,
+            screen_quad,
+            projection_views: None,
+        })
+    }
+
+    /// Remembers the cameras of the new frame.
+    ///
+    /// Motion vectors are computed with these cameras, rather than with the
+    /// camera of the scene pass context. This allows excluding the
+    /// projection jitter of temporal anti-aliasing.
+    pub fn start_frame(&mut self, context: &Context) {
+        self.projection_views = Some(Self::projection_views(context));
+    }
+
+    /// Writes the motion of the camera into the pixels that are not covered
+    /// by the scene, i.e. whose depth is at the far plane.
+    pub fn background_pass<F: glium::backend::Facade>(
+        &self,
+        facade: &F,
+        depth_texture: &DepthTexture2d,
+    ) -> Result<(), DrawError> {
+        let (projection_view, previous_projection_view) = match self.projection_views {
+            Some(projection_views) => projection_views,
+            None => return Ok(()),
+        };
+
+        let inverse_projection_view: [[f32; 4]; 4] = projection_view
+            .try_inverse()
+            .unwrap_or_else(na::Matrix4::identity)
+            .into();
+        let previous_projection_view: [[f32; 4]; 4] = previous_projection_view.into();
+
+        let mut framebuffer = SimpleFrameBuffer::new(facade, &self.velocity_texture)?;
+
+        framebuffer.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.background_program,
+            &uniform! {
+                scene_depth_texture: depth_texture,
+                velocity_inverse_projection_view: inverse_projection_view,
+                velocity_previous_projection_view: previous_projection_view,
+            },
+            &Default::default(),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn velocity_texture(&self) -> &Texture2d {
+        &self.velocity_texture
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        self.velocity_texture = Self::create_texture(facade, target_size)?;
+
+        Ok(())
+    }
+
+    /// Projection and view of the camera of `context` and of its previous
+    /// camera. Without a previous camera, the camera is considered static.
+    fn projection_views(context: &Context) -> (na::Matrix4<f32>, na::Matrix4<f32>) {
+        let projection_view = context.camera.projection * context.camera.view;
+        let previous_projection_view = context
+            .previous_camera
+            .as_ref()
+            .map_or(projection_view, |camera| camera.projection * camera.view);
+
+        (projection_view, previous_projection_view)
+    }
+
+    fn create_texture<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<Texture2d, CreationError> {
+        Ok(Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::F32F32,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?)
+    }
+}
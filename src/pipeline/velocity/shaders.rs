@@ -0,0 +1,93 @@
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader, Context};
+
+pub const F_VELOCITY: (&str, shader::FragmentOutDef) = (
+    "f_velocity",
+    shader::FragmentOutDef(shader::Type::FloatVec2, shader::FragmentOutQualifier::Yield),
+);
+
+const V_VELOCITY_CLIP_POS: (&str, shader::VertexOutDef) = (
+    "v_velocity_clip_pos",
+    shader::VertexOutDef(shader::Type::FloatVec4, shader::VertexOutQualifier::Smooth),
+);
+
+const V_VELOCITY_PREVIOUS_CLIP_POS: (&str, shader::VertexOutDef) = (
+    "v_velocity_previous_clip_pos",
+    shader::VertexOutDef(shader::Type::FloatVec4, shader::VertexOutQualifier::Smooth),
+);
+
+/// Shader core transform for writing screen-space motion vectors of
+/// fragments into `F_VELOCITY`, given in texture coordinates per frame.
+///
+/// If the core provides `V_PREVIOUS_WORLD_POS`, motion of objects is taken
+/// into account. Otherwise, only the motion of the camera is considered.
+pub fn velocity_core_transform<P, I, V>(
+    core: shader::Core<(Context, P), I, V>,
+) -> shader::Core<(Context, P), I, V> {
+    assert!(
+        core.vertex.has_out_def(shader::defs::V_WORLD_POS),
+        "VertexCore needs V_WORLD_POS output for velocity buffer"
+    );
+
+    let previous_world_pos = if core.vertex.has_out_def(shader::defs::V_PREVIOUS_WORLD_POS) {
+        "v_previous_world_pos"
+    } else {
+        "v_world_pos"
+    };
+
+    let vertex = core
+        .vertex
+        .with_extra_uniform("velocity_projection_view", UniformType::FloatMat4)
+        .with_extra_uniform("velocity_previous_projection_view", UniformType::FloatMat4)
+        .with_out(
+            V_VELOCITY_CLIP_POS,
+            "velocity_projection_view * v_world_pos",
+        )
+        .with_out(
+            V_VELOCITY_PREVIOUS_CLIP_POS,
+            &format!("velocity_previous_projection_view * {}", previous_world_pos),
+        );
+
+    let fragment = core
+        .fragment
+        .with_in_def(V_VELOCITY_CLIP_POS)
+        .with_in_def(V_VELOCITY_PREVIOUS_CLIP_POS)
+        .with_out(
+            F_VELOCITY,
+            "0.5 * (v_velocity_clip_pos.xy / v_velocity_clip_pos.w
+                - v_velocity_previous_clip_pos.xy / v_velocity_previous_clip_pos.w)",
+        );
+
+    shader::Core { vertex, fragment }
+}
+
+/// Shader core for writing the motion of the camera into `F_COLOR` for pixels
+/// that are not covered by the scene, i.e. pixels at the far plane.
+pub fn background_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let vertex = shader::VertexCore::empty()
+        .with_out(shader::defs::V_TEX_COORD, "tex_coord")
+        .with_out(shader::defs::V_POS, "position");
+
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("scene_depth_texture", UniformType::Sampler2d)
+        .with_extra_uniform("velocity_inverse_projection_view", UniformType::FloatMat4)
+        .with_extra_uniform("velocity_previous_projection_view", UniformType::FloatMat4)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_body(
+            "
+            if (texture(scene_depth_texture, v_tex_coord).r < 1.0)
+                discard;
+
+            vec4 clip_pos = vec4(v_tex_coord * 2.0 - 1.0, 1.0, 1.0);
+            vec4 previous_clip_pos = velocity_previous_projection_view
+                * (velocity_inverse_projection_view * clip_pos);
+            ",
+        )
+        .with_out(
+            shader::defs::F_COLOR,
+            "vec4(0.5 * (clip_pos.xy - previous_clip_pos.xy / previous_clip_pos.w), 0.0, 0.0)",
+        );
+
+    shader::Core { vertex, fragment }
+}
@@ -15,6 +15,26 @@ pub const V_WORLD_POS: (&str, VertexOutDef) = (
     VertexOutDef(Type::FloatVec4, VertexOutQualifier::Smooth),
 );
 
+/// World space position in the previous frame. Scene cores can provide this
+/// in order to get per-object motion vectors.
+pub const V_PREVIOUS_WORLD_POS: (&str, VertexOutDef) = (
+    "v_previous_world_pos",
+    VertexOutDef(Type::FloatVec4, VertexOutQualifier::Smooth),
+);
+
+/// ID of the object that a vertex belongs to, where 0 means no object.
+/// Scene cores can provide this in order to make their instances pickable.
+pub const V_OBJECT_ID: (&str, VertexOutDef) = (
+    "v_object_id",
+    VertexOutDef(Type::UnsignedInt, VertexOutQualifier::Flat),
+);
+
+/// World space tangent, with the handedness of the tangent space in W.
+pub const V_WORLD_TANGENT: (&str, VertexOutDef) = (
+    "v_world_tangent",
+    VertexOutDef(Type::FloatVec4, VertexOutQualifier::Smooth),
+);
+
 pub const V_COLOR: (&str, VertexOutDef) = (
     "v_color",
     VertexOutDef(Type::FloatVec4, VertexOutQualifier::Smooth),
@@ -35,7 +55,35 @@ pub const F_FRAGMENT_DEPTH: (&str, FragmentOutDef) = (
     FragmentOutDef(Type::Float, FragmentOutQualifier::Yield),
 );
 
+/// World space normal of a fragment, for cores that modify the interpolated
+/// `v_world_normal`, e.g. with normal mapping.
+pub const F_NORMAL: (&str, FragmentOutDef) = (
+    "f_normal",
+    FragmentOutDef(Type::FloatVec3, FragmentOutQualifier::Local),
+);
+
 pub const F_SHADOW: (&str, FragmentOutDef) = (
     "f_shadow",
     FragmentOutDef(Type::Float, FragmentOutQualifier::Local),
 );
+
+/// Specular intensity and shininess (the Blinn-Phong exponent) of a fragment.
+/// Scene cores can provide this in order to get specular highlights.
+pub const F_SPECULAR: (&str, FragmentOutDef) = (
+    "f_specular",
+    FragmentOutDef(Type::FloatVec2, FragmentOutQualifier::Local),
+);
+
+/// Metallic, roughness and ambient occlusion of a fragment, used for
+/// physically based shading.
+pub const F_MATERIAL: (&str, FragmentOutDef) = (
+    "f_material",
+    FragmentOutDef(Type::FloatVec3, FragmentOutQualifier::Local),
+);
+
+/// Light emitted by a fragment. This is written into the glow texture, so
+/// emissive surfaces glow even in passes that do not draw glowing objects.
+pub const F_EMISSIVE: (&str, FragmentOutDef) = (
+    "f_emissive",
+    FragmentOutDef(Type::FloatVec3, FragmentOutQualifier::Local),
+);
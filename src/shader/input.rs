@@ -293,6 +293,10 @@ impl StaticUniformType for f32 {
     const TYPE: UniformType = UniformType::Float;
 }
 
+impl StaticUniformType for u32 {
+    const TYPE: UniformType = UniformType::UnsignedInt;
+}
+
 impl StaticUniformType for [f32; 2] {
     const TYPE: UniformType = UniformType::FloatVec2;
 }
@@ -341,6 +345,38 @@ impl<'a> StaticUniformType for Sampler<'a, glium::texture::CompressedSrgbTexture
     const TYPE: UniformType = UniformType::Sampler2d;
 }
 
+impl<'a> StaticUniformType for &'a glium::texture::SrgbTexture2d {
+    const TYPE: UniformType = UniformType::Sampler2d;
+}
+
+impl<'a> StaticUniformType for Sampler<'a, glium::texture::SrgbTexture2d> {
+    const TYPE: UniformType = UniformType::Sampler2d;
+}
+
+impl<'a> StaticUniformType for &'a glium::texture::Texture2dArray {
+    const TYPE: UniformType = UniformType::Sampler2dArray;
+}
+
+impl<'a> StaticUniformType for Sampler<'a, glium::texture::Texture2dArray> {
+    const TYPE: UniformType = UniformType::Sampler2dArray;
+}
+
+impl<'a> StaticUniformType for &'a glium::texture::SrgbTexture2dArray {
+    const TYPE: UniformType = UniformType::Sampler2dArray;
+}
+
+impl<'a> StaticUniformType for Sampler<'a, glium::texture::SrgbTexture2dArray> {
+    const TYPE: UniformType = UniformType::Sampler2dArray;
+}
+
+impl<'a> StaticUniformType for &'a glium::texture::Texture3d {
+    const TYPE: UniformType = UniformType::Sampler3d;
+}
+
+impl<'a> StaticUniformType for Sampler<'a, glium::texture::Texture3d> {
+    const TYPE: UniformType = UniformType::Sampler3d;
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct MyEmptyUniforms;
 
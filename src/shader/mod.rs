@@ -452,10 +452,16 @@ fn compile_type(t: Type) -> &'static str {
         Type::FloatMat3 => "mat3",
         Type::FloatMat4 => "mat4",
         Type::Int => "int",
+        Type::UnsignedInt => "uint",
         Type::IntVec2 => "ivec2",
         Type::IntVec3 => "ivec3",
         Type::IntVec4 => "ivec4",
         Type::Sampler2d => "sampler2D",
+        Type::Sampler2dArray => "sampler2DArray",
+        Type::Sampler2dMultisample => "sampler2DMS",
+        Type::USampler2dMultisample => "usampler2DMS",
+        Type::Sampler3d => "sampler3D",
+        Type::SamplerCube => "samplerCube",
         Type::Bool => "bool",
         _ => unimplemented!("Given Type not yet supported: {:?}", t),
     }
@@ -0,0 +1,250 @@
+//! Subpixel morphological anti-aliasing (SMAA 1x).
+//!
+//! For reference:
+//! http://www.iryoku.com/smaa/
+//!
+//! SMAA runs in three passes. First, edges are detected by comparing the
+//! luma of neighboring pixels. Then, for each edge, the line that it belongs
+//! to is followed to both ends, and the shape at the ends is used to look up
+//! how much of the pixel the revectorized, i.e. smooth, line would cover.
+//! This gives blending weights, which are finally used to blend each pixel
+//! with its neighbors.
+
+mod shaders;
+mod textures;
+
+use std::borrow::Cow;
+
+use log::info;
+
+use glium::framebuffer::SimpleFrameBuffer;
+use glium::texture::{ClientFormat, MipmapsOption, RawImage2d, UncompressedFloatFormat};
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter, Sampler, SamplerWrapFunction};
+use glium::{uniform, Program, Surface, Texture2d};
+
+use crate::{shader, DrawError, ScreenQuad};
+
+pub use crate::CreationError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Quality {
+    Low,
+    Medium,
+    High,
+}
+
+impl Quality {
+    /// Minimal difference in luma for detecting an edge.
+    pub fn threshold(&self) -> f32 {
+        match self {
+            Quality::Low => 0.15,
+            Quality::Medium => 0.1,
+            Quality::High => 0.1,
+        }
+    }
+
+    /// Maximal number of steps when searching for the end of a line. Each
+    /// step covers two pixels.
+    pub fn max_search_steps(&self) -> usize {
+        match self {
+            Quality::Low => 4,
+            Quality::Medium => 8,
+            Quality::High => 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Config {
+    pub quality: Quality,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            quality: Quality::High,
+        }
+    }
+}
+
+pub struct SMAA {
+    config: Config,
+
+    edges_texture: Texture2d,
+    blend_texture: Texture2d,
+    area_texture: Texture2d,
+    search_texture: Texture2d,
+
+    edge_detection_program: Program,
+    blend_weight_program: Program,
+    neighborhood_blending_program: Program,
+
+    screen_quad: ScreenQuad,
+}
+
+impl SMAA {
+    pub fn create<F: glium::backend::Facade>(
+        facade: &F,
+        config: &Config,
+        target_size: (u32, u32),
+    ) -> Result<Self, CreationError> {
+        let (edges_texture, blend_texture) = Self::create_textures(facade, target_size)?;
+
+        info!("Creating SMAA lookup textures");
+        let area_texture = Texture2d::with_format(
+            facade,
+            RawImage2d {
+                data: Cow::Owned(textures::area_texture_data()),
+                width: textures::AREA_TEXTURE_SIZE as u32,
+                height: textures::AREA_TEXTURE_SIZE as u32,
+                format: ClientFormat::F32F32,
+            },
+            UncompressedFloatFormat::F32F32,
+            MipmapsOption::NoMipmap,
+        )?;
+        let search_texture = Texture2d::with_format(
+            facade,
+            RawImage2d {
+                data: Cow::Owned(textures::search_texture_data()),
+                width: textures::SEARCH_TEXTURE_SIZE.0 as u32,
+                height: textures::SEARCH_TEXTURE_SIZE.1 as u32,
+                format: ClientFormat::F32,
+            },
+            UncompressedFloatFormat::F32,
+            MipmapsOption::NoMipmap,
+        )?;
+
+        info!("Creating SMAA programs");
+        let edge_detection_program = shaders::edge_detection_core()
+            .build_program(facade, shader::InstancingMode::Uniforms)?;
+        let blend_weight_program = shaders::blend_weight_core(config.quality.max_search_steps())
+            .build_program(facade, shader::InstancingMode::Uniforms)?;
+        let neighborhood_blending_program = shaders::neighborhood_blending_core()
+            .build_program(facade, shader::InstancingMode::Uniforms)?;
+
+        info!("Creating screen quad");
+        let screen_quad = ScreenQuad::create(facade)?;
+
+        Ok(SMAA {
+            config: config.clone(),
+            edges_texture,
+            blend_texture,
+            area_texture,
+            search_texture,
+            edge_detection_program,
+            blend_weight_program,
+            neighborhood_blending_program,
+            screen_quad,
+        })
+    }
+
+    pub fn draw<F: glium::backend::Facade, S: Surface>(
+        &self,
+        facade: &F,
+        texture: &Texture2d,
+        target: &mut S,
+    ) -> Result<(), DrawError> {
+        {
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &self.edges_texture)?;
+            framebuffer.clear_color(0.0, 0.0, 0.0, 0.0);
+
+            framebuffer.draw(
+                &self.screen_quad.vertex_buffer,
+                &self.screen_quad.index_buffer,
+                &self.edge_detection_program,
+                &uniform! {
+                    color_texture: Self::sampler(texture, MagnifySamplerFilter::Nearest),
+                    smaa_threshold: self.config.quality.threshold(),
+                },
+                &Default::default(),
+            )?;
+        }
+
+        {
+            let mut framebuffer = SimpleFrameBuffer::new(facade, &self.blend_texture)?;
+
+            // The search relies on bilinear filtering of the edges texture
+            framebuffer.draw(
+                &self.screen_quad.vertex_buffer,
+                &self.screen_quad.index_buffer,
+                &self.blend_weight_program,
+                &uniform! {
+                    smaa_edges_texture:
+                        Self::sampler(&self.edges_texture, MagnifySamplerFilter::Linear),
+                    smaa_area_texture:
+                        Self::sampler(&self.area_texture, MagnifySamplerFilter::Linear),
+                    smaa_search_texture:
+                        Self::sampler(&self.search_texture, MagnifySamplerFilter::Nearest),
+                },
+                &Default::default(),
+            )?;
+        }
+
+        target.draw(
+            &self.screen_quad.vertex_buffer,
+            &self.screen_quad.index_buffer,
+            &self.neighborhood_blending_program,
+            &uniform! {
+                color_texture: Self::sampler(texture, MagnifySamplerFilter::Linear),
+                smaa_blend_texture: Self::sampler(&self.blend_texture, MagnifySamplerFilter::Nearest),
+            },
+            &Default::default(),
+        )?;
+
+        Ok(())
+    }
+
+    pub fn on_target_resize<F: glium::backend::Facade>(
+        &mut self,
+        facade: &F,
+        target_size: (u32, u32),
+    ) -> Result<(), CreationError> {
+        let (edges_texture, blend_texture) = Self::create_textures(facade, target_size)?;
+
+        self.edges_texture = edges_texture;
+        self.blend_texture = blend_texture;
+
+        Ok(())
+    }
+
+    fn sampler(texture: &Texture2d, filter: MagnifySamplerFilter) -> Sampler<'_, Texture2d> {
+        let minify_filter = match filter {
+            MagnifySamplerFilter::Nearest => MinifySamplerFilter::Nearest,
+            MagnifySamplerFilter::Linear => MinifySamplerFilter::Linear,
+        };
+
+        Sampler::new(texture)
+            .magnify_filter(filter)
+            .minify_filter(minify_filter)
+            .wrap_function(SamplerWrapFunction::Clamp)
+    }
+
+    /// Creates the edges texture and the blending weights texture.
+    fn create_textures<F: glium::backend::Facade>(
+        facade: &F,
+        size: (u32, u32),
+    ) -> Result<(Texture2d, Texture2d), CreationError> {
+        let edges_texture = Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::U8U8,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?;
+        let blend_texture = Texture2d::empty_with_format(
+            facade,
+            UncompressedFloatFormat::U8U8U8U8,
+            MipmapsOption::NoMipmap,
+            size.0,
+            size.1,
+        )?;
+
+        Ok((edges_texture, blend_texture))
+    }
+}
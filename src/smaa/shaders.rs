@@ -0,0 +1,325 @@
+//! Shaders for the three passes of SMAA 1x.
+//!
+//! These closely follow the reference implementation, using the same
+//! conventions: edges are stored at the left (red) and top (green) side of
+//! each pixel, where "top" refers to the neighbor at `y - 1`.
+
+use glium::uniforms::UniformType;
+
+use crate::{screen_quad, shader};
+
+use super::textures::{AREA_TEXTURE_SIZE, AREA_TILE_SIZE};
+
+/// Factor by which a neighboring edge may be stronger without suppressing
+/// an edge.
+const LOCAL_CONTRAST_ADAPTATION_FACTOR: f32 = 2.0;
+
+/// How much the corners of shapes are rounded, between 0 and 1.
+const CORNER_ROUNDING: f32 = 0.25;
+
+fn vertex() -> shader::VertexCore<(), (), screen_quad::Vertex> {
+    shader::VertexCore::empty()
+        .with_out(shader::defs::V_TEX_COORD, "tex_coord")
+        .with_out(shader::defs::V_POS, "position")
+}
+
+/// Shader core for detecting edges based on luma.
+pub fn edge_detection_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("color_texture", UniformType::Sampler2d)
+        .with_extra_uniform("smaa_threshold", UniformType::Float)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_defs(
+            "
+            float smaa_luma(vec2 tex_coord) {
+                return dot(
+                    textureLod(color_texture, tex_coord, 0.0).rgb,
+                    vec3(0.2126, 0.7152, 0.0722)
+                );
+            }
+            ",
+        )
+        .with_body(&format!(
+            "
+            vec2 pixel_size = 1.0 / vec2(textureSize(color_texture, 0));
+
+            float luma = smaa_luma(v_tex_coord);
+            float luma_left = smaa_luma(v_tex_coord - vec2(pixel_size.x, 0.0));
+            float luma_top = smaa_luma(v_tex_coord - vec2(0.0, pixel_size.y));
+
+            vec4 delta;
+            delta.xy = abs(luma - vec2(luma_left, luma_top));
+            vec2 edges = step(vec2(smaa_threshold), delta.xy);
+
+            if (dot(edges, vec2(1.0)) == 0.0)
+                discard;
+
+            // Local contrast adaptation: suppress edges that are much weaker
+            // than neighboring ones.
+            float luma_right = smaa_luma(v_tex_coord + vec2(pixel_size.x, 0.0));
+            float luma_bottom = smaa_luma(v_tex_coord + vec2(0.0, pixel_size.y));
+            delta.zw = abs(luma - vec2(luma_right, luma_bottom));
+            vec2 max_delta = max(delta.xy, delta.zw);
+
+            float luma_left_left = smaa_luma(v_tex_coord - vec2(2.0 * pixel_size.x, 0.0));
+            float luma_top_top = smaa_luma(v_tex_coord - vec2(0.0, 2.0 * pixel_size.y));
+            delta.zw = abs(vec2(luma_left, luma_top) - vec2(luma_left_left, luma_top_top));
+            max_delta = max(max_delta.xy, delta.zw);
+
+            float final_delta = max(max_delta.x, max_delta.y);
+            edges *= step(final_delta, {factor:.1} * delta.xy);
+            ",
+            factor = LOCAL_CONTRAST_ADAPTATION_FACTOR,
+        ))
+        .with_out(shader::defs::F_COLOR, "vec4(edges, 0.0, 0.0)");
+
+    shader::Core {
+        vertex: vertex(),
+        fragment,
+    }
+}
+
+/// Definitions for searching the ends of lines, looking up the area texture
+/// and detecting corners.
+fn blend_weight_defs() -> String {
+    format!(
+        "
+        float smaa_search_length(vec2 e, int side) {{
+            ivec2 coord = ivec2(round(e * 32.0)) + ivec2(33 * side, 0);
+            return texelFetch(smaa_search_texture, coord, 0).r;
+        }}
+
+        float smaa_search_x_left(vec2 tex_coord, float end, vec2 pixel_size) {{
+            vec2 e = vec2(0.0, 1.0);
+            while (tex_coord.x > end && e.g > 0.8281 && e.r == 0.0) {{
+                e = textureLod(smaa_edges_texture, tex_coord, 0.0).rg;
+                tex_coord -= vec2(2.0 * pixel_size.x, 0.0);
+            }}
+
+            float offset = 3.25 - smaa_search_length(e, 0);
+            return tex_coord.x + offset * pixel_size.x;
+        }}
+
+        float smaa_search_x_right(vec2 tex_coord, float end, vec2 pixel_size) {{
+            vec2 e = vec2(0.0, 1.0);
+            while (tex_coord.x < end && e.g > 0.8281 && e.r == 0.0) {{
+                e = textureLod(smaa_edges_texture, tex_coord, 0.0).rg;
+                tex_coord += vec2(2.0 * pixel_size.x, 0.0);
+            }}
+
+            float offset = 3.25 - smaa_search_length(e, 1);
+            return tex_coord.x - offset * pixel_size.x;
+        }}
+
+        float smaa_search_y_up(vec2 tex_coord, float end, vec2 pixel_size) {{
+            vec2 e = vec2(1.0, 0.0);
+            while (tex_coord.y > end && e.r > 0.8281 && e.g == 0.0) {{
+                e = textureLod(smaa_edges_texture, tex_coord, 0.0).rg;
+                tex_coord -= vec2(0.0, 2.0 * pixel_size.y);
+            }}
+
+            float offset = 3.25 - smaa_search_length(e.gr, 0);
+            return tex_coord.y + offset * pixel_size.y;
+        }}
+
+        float smaa_search_y_down(vec2 tex_coord, float end, vec2 pixel_size) {{
+            vec2 e = vec2(1.0, 0.0);
+            while (tex_coord.y < end && e.r > 0.8281 && e.g == 0.0) {{
+                e = textureLod(smaa_edges_texture, tex_coord, 0.0).rg;
+                tex_coord += vec2(0.0, 2.0 * pixel_size.y);
+            }}
+
+            float offset = 3.25 - smaa_search_length(e.gr, 1);
+            return tex_coord.y - offset * pixel_size.y;
+        }}
+
+        // Looks up the area below and above the edge, given the square
+        // roots of the distances to both ends of the line, and the crossing
+        // edges at both ends.
+        vec2 smaa_area(vec2 sqrt_d, float e1, float e2) {{
+            vec2 tex_coord = {tile_size:.1} * round(4.0 * vec2(e1, e2)) + sqrt_d;
+            tex_coord = (tex_coord + 0.5) / {texture_size:.1};
+            return textureLod(smaa_area_texture, tex_coord, 0.0).rg;
+        }}
+
+        vec2 smaa_horizontal_corner(vec2 weights, vec4 tex_coord, vec2 d) {{
+            vec2 left_right = step(d.xy, d.yx);
+            vec2 rounding = (1.0 - {corner_rounding:.2}) * left_right;
+
+            // Reduce blending for pixels in the center of a line
+            rounding /= left_right.x + left_right.y;
+
+            vec2 factor = vec2(1.0);
+            factor.x -= rounding.x
+                * textureLodOffset(smaa_edges_texture, tex_coord.xy, 0.0, ivec2(0, 1)).r;
+            factor.x -= rounding.y
+                * textureLodOffset(smaa_edges_texture, tex_coord.zw, 0.0, ivec2(1, 1)).r;
+            factor.y -= rounding.x
+                * textureLodOffset(smaa_edges_texture, tex_coord.xy, 0.0, ivec2(0, -2)).r;
+            factor.y -= rounding.y
+                * textureLodOffset(smaa_edges_texture, tex_coord.zw, 0.0, ivec2(1, -2)).r;
+
+            return weights * clamp(factor, 0.0, 1.0);
+        }}
+
+        vec2 smaa_vertical_corner(vec2 weights, vec4 tex_coord, vec2 d) {{
+            vec2 left_right = step(d.xy, d.yx);
+            vec2 rounding = (1.0 - {corner_rounding:.2}) * left_right;
+
+            rounding /= left_right.x + left_right.y;
+
+            vec2 factor = vec2(1.0);
+            factor.x -= rounding.x
+                * textureLodOffset(smaa_edges_texture, tex_coord.xy, 0.0, ivec2(1, 0)).g;
+            factor.x -= rounding.y
+                * textureLodOffset(smaa_edges_texture, tex_coord.zw, 0.0, ivec2(1, 1)).g;
+            factor.y -= rounding.x
+                * textureLodOffset(smaa_edges_texture, tex_coord.xy, 0.0, ivec2(-2, 0)).g;
+            factor.y -= rounding.y
+                * textureLodOffset(smaa_edges_texture, tex_coord.zw, 0.0, ivec2(-2, 1)).g;
+
+            return weights * clamp(factor, 0.0, 1.0);
+        }}
+        ",
+        tile_size = AREA_TILE_SIZE as f32,
+        texture_size = AREA_TEXTURE_SIZE as f32,
+        corner_rounding = CORNER_ROUNDING,
+    )
+}
+
+/// Shader core for calculating blending weights of pixels along edges.
+///
+/// For each edge, we search for both ends of the line that it belongs to.
+/// The crossing edges at the ends determine the pattern of the line, which,
+/// together with the distances to the ends, is used to look up the area
+/// that the revectorized line covers in the current pixel.
+pub fn blend_weight_core(max_search_steps: usize) -> shader::Core<(), (), screen_quad::Vertex> {
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("smaa_edges_texture", UniformType::Sampler2d)
+        .with_extra_uniform("smaa_area_texture", UniformType::Sampler2d)
+        .with_extra_uniform("smaa_search_texture", UniformType::Sampler2d)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_defs(&blend_weight_defs())
+        .with_body(&format!(
+            "
+            const float MAX_SEARCH_STEPS = {max_search_steps:.1};
+
+            vec2 pixel_size = 1.0 / vec2(textureSize(smaa_edges_texture, 0));
+            vec2 pixel_coord = v_tex_coord / pixel_size;
+
+            vec4 offset0 = v_tex_coord.xyxy
+                + pixel_size.xyxy * vec4(-0.25, -0.125, 1.25, -0.125);
+            vec4 offset1 = v_tex_coord.xyxy
+                + pixel_size.xyxy * vec4(-0.125, -0.25, -0.125, 1.25);
+            vec4 offset2 = vec4(offset0.xz, offset1.yw)
+                + pixel_size.xxyy * vec4(-2.0, 2.0, -2.0, 2.0) * MAX_SEARCH_STEPS;
+
+            vec4 weights = vec4(0.0);
+            vec2 e = textureLod(smaa_edges_texture, v_tex_coord, 0.0).rg;
+
+            if (e.g > 0.0) {{
+                // Edge at the top
+                vec2 d;
+                vec3 coords;
+
+                coords.x = smaa_search_x_left(offset0.xy, offset2.x, pixel_size);
+                coords.y = offset1.y;
+                d.x = coords.x;
+
+                float e1 = textureLod(smaa_edges_texture, coords.xy, 0.0).r;
+
+                coords.z = smaa_search_x_right(offset0.zw, offset2.y, pixel_size);
+                d.y = coords.z;
+
+                d = abs(round(d / pixel_size.x - pixel_coord.xx));
+
+                float e2 = textureLodOffset(smaa_edges_texture, coords.zy, 0.0, ivec2(1, 0)).r;
+
+                weights.rg = smaa_area(sqrt(d), e1, e2);
+
+                coords.y = v_tex_coord.y;
+                weights.rg = smaa_horizontal_corner(weights.rg, coords.xyzy, d);
+            }}
+
+            if (e.r > 0.0) {{
+                // Edge at the left
+                vec2 d;
+                vec3 coords;
+
+                coords.y = smaa_search_y_up(offset1.xy, offset2.z, pixel_size);
+                coords.x = offset0.x;
+                d.x = coords.y;
+
+                float e1 = textureLod(smaa_edges_texture, coords.xy, 0.0).g;
+
+                coords.z = smaa_search_y_down(offset1.zw, offset2.w, pixel_size);
+                d.y = coords.z;
+
+                d = abs(round(d / pixel_size.y - pixel_coord.yy));
+
+                float e2 = textureLodOffset(smaa_edges_texture, coords.xz, 0.0, ivec2(0, 1)).g;
+
+                weights.ba = smaa_area(sqrt(d), e1, e2);
+
+                coords.x = v_tex_coord.x;
+                weights.ba = smaa_vertical_corner(weights.ba, coords.xyxz, d);
+            }}
+            ",
+            max_search_steps = max_search_steps as f32,
+        ))
+        .with_out(shader::defs::F_COLOR, "weights");
+
+    shader::Core {
+        vertex: vertex(),
+        fragment,
+    }
+}
+
+/// Shader core for blending each pixel with its neighbors, according to the
+/// weights calculated in the previous pass.
+pub fn neighborhood_blending_core() -> shader::Core<(), (), screen_quad::Vertex> {
+    let fragment = shader::FragmentCore::empty()
+        .with_extra_uniform("color_texture", UniformType::Sampler2d)
+        .with_extra_uniform("smaa_blend_texture", UniformType::Sampler2d)
+        .with_in_def(shader::defs::V_TEX_COORD)
+        .with_body(
+            "
+            vec2 pixel_size = 1.0 / vec2(textureSize(color_texture, 0));
+
+            vec4 a;
+            a.x = textureLodOffset(smaa_blend_texture, v_tex_coord, 0.0, ivec2(1, 0)).a;
+            a.y = textureLodOffset(smaa_blend_texture, v_tex_coord, 0.0, ivec2(0, 1)).g;
+            a.wz = textureLod(smaa_blend_texture, v_tex_coord, 0.0).xz;
+
+            vec3 color;
+
+            if (dot(a, vec4(1.0)) < 1e-5) {
+                color = textureLod(color_texture, v_tex_coord, 0.0).rgb;
+            } else {
+                // Blend only horizontally or vertically, whichever has the
+                // larger weight.
+                bool horizontal = max(a.x, a.z) > max(a.y, a.w);
+
+                vec4 blending_offset = horizontal
+                    ? vec4(a.x, 0.0, a.z, 0.0)
+                    : vec4(0.0, a.y, 0.0, a.w);
+                vec2 blending_weight = horizontal ? a.xz : a.yw;
+                blending_weight /= dot(blending_weight, vec2(1.0));
+
+                vec4 blending_coord = v_tex_coord.xyxy
+                    + blending_offset * vec4(pixel_size, -pixel_size);
+
+                color = blending_weight.x
+                    * textureLod(color_texture, blending_coord.xy, 0.0).rgb;
+                color += blending_weight.y
+                    * textureLod(color_texture, blending_coord.zw, 0.0).rgb;
+            }
+            ",
+        )
+        .with_out(shader::defs::F_COLOR, "vec4(color, 1.0)");
+
+    shader::Core {
+        vertex: vertex(),
+        fragment,
+    }
+}
@@ -0,0 +1,402 @@
+//! Lookup textures for SMAA.
+//!
+//! These follow the `AreaTex.py` and `SearchTex.py` scripts of the reference
+//! implementation, restricted to what is needed for SMAA 1x, i.e. without
+//! diagonal patterns and subsample offsets. The data is generated when
+//! creating `SMAA`, so that we do not need to ship binary files.
+//!
+//! The area texture corresponds to the top left 80x80 texels of the
+//! reference `AreaTex`, i.e. to the orthogonal patterns without subsample
+//! offset. The search texture corresponds to the reference `SearchTex`
+//! before it is cropped to 64x16, with the deltas stored directly instead
+//! of being scaled by 127.
+
+/// Size of one pattern tile in the area texture. Distances are stored
+/// quadratically compressed, so the texture covers distances up to
+/// `(AREA_TILE_SIZE - 1)^2` pixels.
+pub const AREA_TILE_SIZE: usize = 16;
+
+/// The area texture consists of 5x5 tiles, indexed by the edge values at
+/// both ends of a line.
+pub const AREA_TEXTURE_SIZE: usize = 5 * AREA_TILE_SIZE;
+
+/// The edge fetches of the search have 33 possible values in each channel.
+pub const SEARCH_TEXTURE_SIZE: (usize, usize) = (2 * 33, 33);
+
+/// Distance beyond which U patterns are no longer smoothed.
+const SMOOTH_MAX_DISTANCE: f32 = 32.0;
+
+/// Tile of each orthogonal pattern, given as rounded edge values at the left
+/// and right end of the line. A value of 1 means a crossing edge going up,
+/// 3 means a crossing edge going down, and 4 means both.
+const PATTERN_TILES: [(usize, usize); 16] = [
+    (0, 0),
+    (3, 0),
+    (0, 3),
+    (3, 3),
+    (1, 0),
+    (4, 0),
+    (1, 3),
+    (4, 3),
+    (0, 1),
+    (3, 1),
+    (0, 4),
+    (3, 4),
+    (1, 1),
+    (4, 1),
+    (1, 4),
+    (4, 4),
+];
+
+/// Returns the area texture as interleaved RG values, row by row.
+///
+/// The red channel holds the area below the edge, i.e. on the side of the
+/// pixel at which the edge was detected, while the green channel holds the
+/// area above the edge.
+pub fn area_texture_data() -> Vec<(f32, f32)> {
+    let mut data = vec![(0.0, 0.0); AREA_TEXTURE_SIZE * AREA_TEXTURE_SIZE];
+
+    for (pattern, &(tile_x, tile_y)) in PATTERN_TILES.iter().enumerate() {
+        for y in 0..AREA_TILE_SIZE {
+            for x in 0..AREA_TILE_SIZE {
+                let left = (x * x) as f32;
+                let right = (y * y) as f32;
+
+                let texel_x = tile_x * AREA_TILE_SIZE + x;
+                let texel_y = tile_y * AREA_TILE_SIZE + y;
+
+                data[texel_y * AREA_TEXTURE_SIZE + texel_x] = area_ortho(pattern, left, right);
+            }
+        }
+    }
+
+    data
+}
+
+/// Returns the search texture, row by row.
+///
+/// The left half holds the distance that needs to be added in the last step
+/// of a search to the left, the right half for searches to the right. Both
+/// are indexed by the bilinearly fetched crossing edges (x) and followed
+/// edges (y), scaled by 32.
+pub fn search_texture_data() -> Vec<f32> {
+    let (width, height) = SEARCH_TEXTURE_SIZE;
+    let mut data = vec![0.0; width * height];
+
+    for crossing in 0..16 {
+        for followed in 0..16 {
+            let crossing_edges = edge_bits(crossing);
+            let followed_edges = edge_bits(followed);
+
+            let x = bilinear_index(crossing_edges);
+            let y = bilinear_index(followed_edges);
+
+            data[y * width + x] = delta_left(crossing_edges, followed_edges);
+            data[y * width + 33 + x] = delta_right(crossing_edges, followed_edges);
+        }
+    }
+
+    data
+}
+
+fn edge_bits(bits: usize) -> [bool; 4] {
+    [bits & 1 != 0, bits & 2 != 0, bits & 4 != 0, bits & 8 != 0]
+}
+
+/// Returns the value, scaled by 32, of a bilinear fetch at offset
+/// `(-0.25, -0.125)` for the given edges:
+///
+/// ```text
+///   e[0]       e[1]
+///
+///            x <-------- Sample position:    (-0.25, -0.125)
+///   e[2]       e[3] <--- Current pixel [3]:  (  0.0,    0.0)
+/// ```
+fn bilinear_index(e: [bool; 4]) -> usize {
+    [1, 3, 7, 21]
+        .iter()
+        .zip(e.iter())
+        .filter(|(_, &edge)| edge)
+        .map(|(weight, _)| weight)
+        .sum()
+}
+
+/// Delta distance to add in the last step of searches to the left.
+fn delta_left(crossing: [bool; 4], followed: [bool; 4]) -> f32 {
+    let mut d = 0.0;
+
+    // If there is an edge, continue
+    if followed[3] {
+        d += 1.0;
+    }
+
+    // If an additional edge is found, continue
+    if d == 1.0 && followed[2] && !crossing[1] && !crossing[3] {
+        d += 1.0;
+    }
+
+    d
+}
+
+/// Delta distance to add in the last step of searches to the right.
+fn delta_right(crossing: [bool; 4], followed: [bool; 4]) -> f32 {
+    let mut d = 0.0;
+
+    // If there is an edge, and no crossing edges, continue
+    if followed[3] && !crossing[1] && !crossing[3] {
+        d += 1.0;
+    }
+
+    // If an additional edge is found, and no crossing edges, continue
+    if d == 1.0 && followed[2] && !crossing[0] && !crossing[2] {
+        d += 1.0;
+    }
+
+    d
+}
+
+/// Returns the area covered in the pixel at distance `left` from the start of
+/// the line from `p1` to `p2`, split into the area below and above the edge.
+fn area(p1: (f32, f32), p2: (f32, f32), left: f32) -> (f32, f32) {
+    let d = (p2.0 - p1.0, p2.1 - p1.1);
+
+    let x1 = left;
+    let x2 = left + 1.0;
+
+    let inside = (x1 >= p1.0 && x1 < p2.0) || (x2 > p1.0 && x2 <= p2.0);
+    if !inside {
+        return (0.0, 0.0);
+    }
+
+    let y1 = p1.1 + d.1 * (x1 - p1.0) / d.0;
+    let y2 = p1.1 + d.1 * (x2 - p1.0) / d.0;
+
+    let split = |a: f32| if a < 0.0 { (-a, 0.0) } else { (0.0, a) };
+
+    if y1.signum() == y2.signum() || y1.abs() < 1e-4 || y2.abs() < 1e-4 {
+        // Trapezoid
+        split((y1 + y2) / 2.0)
+    } else {
+        // The line crosses the edge within the pixel, giving two triangles.
+        // Only the parts of the triangles that lie on the line are covered.
+        let x = p1.0 - p1.1 * d.0 / d.1;
+
+        let a1 = if x > p1.0 { y1 * (x - x1) / 2.0 } else { 0.0 };
+        let a2 = if x < p2.0 { y2 * (x2 - x) / 2.0 } else { 0.0 };
+
+        // As in the reference, the side of the second triangle is derived
+        // from the first one, even if the first one is empty.
+        if a1 < 0.0 {
+            (a1.abs(), a2.abs())
+        } else {
+            (a2.abs(), a1.abs())
+        }
+    }
+}
+
+/// Reduces blending of short U patterns, which otherwise look too round.
+fn smooth_area(d: f32, a1: (f32, f32), a2: (f32, f32)) -> (f32, f32) {
+    let p = (d / SMOOTH_MAX_DISTANCE).clamp(0.0, 1.0);
+    let smooth = |a: f32| {
+        let b = (a * 2.0).sqrt() * 0.5;
+        b + (a - b) * p
+    };
+
+    (smooth(a1.0) + smooth(a2.0), smooth(a1.1) + smooth(a2.1))
+}
+
+/// Returns the area for one of the orthogonal patterns. The drawings show
+/// the crossing edges at both ends of a line of length `d`.
+fn area_ortho(pattern: usize, left: f32, right: f32) -> (f32, f32) {
+    let d = left + right + 1.0;
+
+    let o1 = 0.5;
+    let o2 = -0.5;
+
+    match pattern {
+        //
+        //    ------
+        //
+        0 => (0.0, 0.0),
+
+        //
+        //   .------
+        //   |
+        //
+        // We only revectorize L patterns on the side of the crossing edge,
+        // so that they converge with pattern 0.
+        1 if left <= right => area((0.0, o2), (d / 2.0, 0.0), left),
+
+        //
+        //    ------.
+        //          |
+        2 if left >= right => area((d / 2.0, 0.0), (d, o2), left),
+
+        //
+        //   .------.
+        //   |      |
+        3 => smooth_area(
+            d,
+            area((0.0, o2), (d / 2.0, 0.0), left),
+            area((d / 2.0, 0.0), (d, o2), left),
+        ),
+
+        //   |
+        //   `------
+        //
+        4 if left <= right => area((0.0, o1), (d / 2.0, 0.0), left),
+
+        //   |
+        //   +------
+        //   |
+        5 => (0.0, 0.0),
+
+        //   |
+        //   `------.
+        //          |
+        6 => area((0.0, o1), (d, o2), left),
+
+        //   |
+        //   +------.
+        //   |      |
+        7 => area((0.0, o1), (d, o2), left),
+
+        //          |
+        //    ------´
+        //
+        8 if left >= right => area((d / 2.0, 0.0), (d, o1), left),
+
+        //          |
+        //   .------´
+        //   |
+        9 => area((0.0, o2), (d, o1), left),
+
+        //          |
+        //    ------+
+        //          |
+        10 => (0.0, 0.0),
+
+        //          |
+        //   .------+
+        //   |      |
+        11 => area((0.0, o2), (d, o1), left),
+
+        //   |      |
+        //   `------´
+        //
+        12 => smooth_area(
+            d,
+            area((0.0, o1), (d / 2.0, 0.0), left),
+            area((d / 2.0, 0.0), (d, o1), left),
+        ),
+
+        //   |      |
+        //   +------´
+        //   |
+        13 => area((0.0, o2), (d, o1), left),
+
+        //   |      |
+        //   `------+
+        //          |
+        14 => area((0.0, o1), (d, o2), left),
+
+        //   |      |
+        //   +------+
+        //   |      |
+        15 => (0.0, 0.0),
+
+        // L patterns that are not on the side of the crossing edge
+        _ => (0.0, 0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The expected values are taken from the output of the reference
+    // scripts, before quantization to 8 bits.
+
+    fn area_texel(x: usize, y: usize) -> (f32, f32) {
+        area_texture_data()[y * AREA_TEXTURE_SIZE + x]
+    }
+
+    fn search_texel(x: usize, y: usize) -> f32 {
+        search_texture_data()[y * SEARCH_TEXTURE_SIZE.0 + x]
+    }
+
+    fn assert_area_eq(x: usize, y: usize, expected: (f32, f32)) {
+        let actual = area_texel(x, y);
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-5 && (actual.1 - expected.1).abs() < 1e-5,
+            "area texel ({}, {}): {:?} != {:?}",
+            x,
+            y,
+            actual,
+            expected,
+        );
+    }
+
+    #[test]
+    fn area_of_lines_without_crossing_edges_is_zero() {
+        for y in 0..AREA_TILE_SIZE {
+            for x in 0..AREA_TILE_SIZE {
+                assert_area_eq(x, y, (0.0, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn area_of_l_patterns() {
+        // Pattern 1 at distances 25 and 49
+        assert_area_eq(53, 7, (0.16, 0.0));
+        // Pattern 1 at distances 4 and 9
+        assert_area_eq(50, 3, (0.178_571_43, 0.0));
+        // Pattern 2 at distances 9 and 4
+        assert_area_eq(3, 50, (0.178_571_43, 0.0));
+    }
+
+    #[test]
+    fn area_of_z_patterns() {
+        // Patterns 6 and 7 at distances 25 and 49
+        assert_area_eq(21, 55, (0.0, 0.16));
+        assert_area_eq(69, 55, (0.0, 0.16));
+        // Pattern 13 at distance 0
+        assert_area_eq(64, 16, (0.125, 0.125));
+    }
+
+    #[test]
+    fn area_of_u_patterns() {
+        // Pattern 3 at distance 0
+        assert_area_eq(48, 48, (0.492_187_5, 0.0));
+        // Pattern 12 at distance 0, where the reference assigns the second
+        // triangle to the other side of the edge
+        assert_area_eq(16, 16, (0.246_093_75, 0.246_093_75));
+        // Pattern 12 at distances 1 and 25
+        assert_area_eq(17, 21, (0.0, 0.448_656_96));
+    }
+
+    #[test]
+    fn search_deltas() {
+        // Crossing edges at x, followed edges at y, as bilinear fetches
+        // scaled by 32. To the left:
+        assert_eq!(search_texel(21, 21), 1.0);
+        assert_eq!(search_texel(0, 28), 2.0);
+        assert_eq!(search_texel(7, 28), 2.0);
+        assert_eq!(search_texel(24, 28), 1.0);
+        // To the right:
+        assert_eq!(search_texel(33, 28), 2.0);
+        assert_eq!(search_texel(33 + 7, 28), 1.0);
+        assert_eq!(search_texel(33 + 21, 28), 0.0);
+        // No bilinear fetch gives these values
+        assert_eq!(search_texel(2, 0), 0.0);
+    }
+
+    #[test]
+    fn search_deltas_count() {
+        let data = search_texture_data();
+
+        assert_eq!(data.iter().filter(|&&d| d == 1.0).count(), 140);
+        assert_eq!(data.iter().filter(|&&d| d == 2.0).count(), 20);
+    }
+}
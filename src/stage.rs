@@ -8,6 +8,11 @@ pub struct Context {
     pub main_light_pos: na::Point3<f32>,
     pub main_light_center: na::Point3<f32>,
     pub ambient_light: na::Vector3<f32>,
+
+    /// Camera of the previous frame of this view, which is used for motion
+    /// vectors. If it is not given, e.g. after a camera cut, the camera is
+    /// considered to be static.
+    pub previous_camera: Option<Camera>,
 }
 
 impl_uniform_input!(
@@ -17,10 +22,72 @@ impl_uniform_input!(
         context_camera_projection: [[f32; 4]; 4] = self.camera.projection,
         context_camera_view: [[f32; 4]; 4] = self.camera.view,
         context_main_light_pos: [f32; 3] = self.main_light_pos.coords,
+        context_main_light_center: [f32; 3] = self.main_light_center.coords,
         context_ambient_light: [f32; 3] = self.ambient_light,
     },
 );
 
+/// Determines how light is emitted by a `Light`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    /// Light is emitted uniformly in all directions from the position.
+    Point,
+
+    /// Light is emitted from the position in a cone around `direction`.
+    /// Intensity falls off smoothly between `inner_angle` and `outer_angle`,
+    /// which are given in radians, measured from the cone axis.
+    Spot {
+        direction: na::Vector3<f32>,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+
+    /// Light arrives from infinitely far away in `direction`. Position and
+    /// attenuation are ignored.
+    Directional { direction: na::Vector3<f32> },
+}
+
+impl LightKind {
+    /// Maximal outer angle of spot lights. Above that, the cone shaped light
+    /// volume degenerates.
+    pub const MAX_SPOT_ANGLE: f32 = 80.0 * std::f32::consts::PI / 180.0;
+
+    pub fn is_directional(&self) -> bool {
+        matches!(self, LightKind::Directional { .. })
+    }
+
+    /// Normalized direction of the light, or zero for point lights.
+    pub fn direction(&self) -> na::Vector3<f32> {
+        match self {
+            LightKind::Point => na::Vector3::zeros(),
+            LightKind::Spot { direction, .. } | LightKind::Directional { direction } => {
+                direction.normalize()
+            }
+        }
+    }
+
+    /// Parameters of the light cone, as passed to shaders: cosine of the
+    /// inner and outer angle, and 1.0 if the light is directional.
+    ///
+    /// Non-spot lights are given cosines that disable the cone falloff.
+    pub(crate) fn cone(&self) -> na::Vector3<f32> {
+        match self {
+            LightKind::Point => na::Vector3::new(-2.0, -3.0, 0.0),
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+                ..
+            } => {
+                let outer_angle = outer_angle.min(Self::MAX_SPOT_ANGLE);
+                let inner_angle = inner_angle.min(outer_angle * 0.999);
+
+                na::Vector3::new(inner_angle.cos(), outer_angle.cos(), 0.0)
+            }
+            LightKind::Directional { .. } => na::Vector3::new(-2.0, -3.0, 1.0),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Light {
     pub position: na::Point3<f32>,
@@ -28,6 +95,13 @@ pub struct Light {
     pub color: na::Vector3<f32>,
     pub is_main: bool,
     pub radius: f32,
+    pub kind: LightKind,
+
+    /// Whether this light should be rendered with a shadow cube map. This
+    /// has no effect for the main light, which is always shadowed, and for
+    /// spot lights and directional lights. It is also ignored without
+    /// deferred shading.
+    pub casts_shadow: bool,
 }
 
 impl_instance_input!(
@@ -37,6 +111,8 @@ impl_instance_input!(
         light_attenuation: [f32; 4] = self.attenuation,
         light_color: [f32; 3] = self.color,
         light_radius: f32 = self.radius,
+        light_direction: [f32; 3] = self.kind.direction(),
+        light_cone: [f32; 3] = self.kind.cone(),
     },
 );
 
@@ -48,6 +124,8 @@ impl Default for Light {
             color: na::Vector3::zeros(),
             is_main: false,
             radius: 0.0,
+            kind: LightKind::Point,
+            casts_shadow: false,
         }
     }
 }
@@ -0,0 +1,643 @@
+//! Golden-image regression tests.
+//!
+//! Reference scenes are rendered headlessly with Mesa's software rasterizer
+//! and compared against the PNGs in `tests/golden`. Pixels are compared with
+//! a perceptual color difference in YIQ space, so that small rounding
+//! differences do not cause failures. On failure, the actual image and an
+//! image highlighting the differing pixels are written to
+//! `target/tmp/golden`.
+//!
+//! Run with `cargo test --features headless,png --test golden`. To update the
+//! reference images after an intended change, set `GOLDEN_UPDATE=1`.
+
+#![cfg(all(feature = "headless", feature = "png"))]
+
+use std::path::{Path, PathBuf};
+
+use nalgebra as na;
+
+use rendology::headless::Headless;
+use rendology::particle::{self, Particle};
+use rendology::pipeline::capture::Image;
+use rendology::pipeline::upscale;
+use rendology::{
+    basic_obj, line, BasicObj, Config, InstancingMode, Light, LightKind, RenderList,
+    ShadedScenePassSetup,
+};
+
+const IMAGE_SIZE: (u32, u32) = (320, 180);
+
+/// Maximal perceptual difference of two pixels that are considered equal,
+/// in `[0, 1]`.
+const PIXEL_THRESHOLD: f32 = 0.1;
+
+/// Fraction of pixels that may differ before the comparison fails.
+const MAX_DIFFERENT_PIXELS: f32 = 0.001;
+
+#[derive(Default)]
+struct Scene {
+    objects: basic_obj::RenderList<basic_obj::Instance>,
+    glowing_objects: basic_obj::RenderList<basic_obj::Instance>,
+    transparent_objects: basic_obj::RenderList<basic_obj::Instance>,
+    lines: RenderList<line::Instance>,
+    particles: RenderList<Particle>,
+    lights: Vec<Light>,
+}
+
+#[test]
+fn golden_images() {
+    // The reference images are rendered with the software rasterizer, so
+    // that they do not depend on the GPU of the machine.
+    std::env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
+
+    let headless = Headless::create().expect("Failed to create headless context");
+
+    // All scenes share one context, so they are rendered one after another
+    // instead of in separate tests.
+    let cases: Vec<(&str, Config, Scene)> = vec![
+        ("basic_objs", Default::default(), basic_objs_scene()),
+        ("lines", Default::default(), lines_scene()),
+        ("particles", Default::default(), particles_scene()),
+        ("shadows", Default::default(), shadows_scene()),
+        (
+            "shadows_forward",
+            Config {
+                deferred_shading: None,
+                ..Default::default()
+            },
+            shadows_scene(),
+        ),
+        ("deferred_lights", Default::default(), lights_scene()),
+        ("glow", Default::default(), glow_scene()),
+        (
+            "no_glow",
+            Config {
+                glow: None,
+                ..Default::default()
+            },
+            glow_scene(),
+        ),
+        (
+            "hdr",
+            Config {
+                hdr: Some(1.0),
+                ..Default::default()
+            },
+            lights_scene(),
+        ),
+        (
+            "no_fxaa",
+            Config {
+                fxaa: None,
+                ..Default::default()
+            },
+            basic_objs_scene(),
+        ),
+        (
+            "smaa",
+            Config {
+                fxaa: None,
+                smaa: Some(Default::default()),
+                ..Default::default()
+            },
+            basic_objs_scene(),
+        ),
+        (
+            "upscale",
+            Config {
+                upscale: Some(upscale::Config {
+                    render_scale: 0.5,
+                    filter: upscale::Filter::Sharpened { sharpness: 0.5 },
+                }),
+                ..Default::default()
+            },
+            basic_objs_scene(),
+        ),
+        (
+            "oit",
+            Config {
+                oit: Some(Default::default()),
+                ..Default::default()
+            },
+            transparent_scene(),
+        ),
+    ];
+
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|(name, config, scene)| {
+            let image = render(&headless, config, scene);
+            check(name, &image).err()
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "Golden image mismatches:\n{}",
+        failures.join("\n")
+    );
+}
+
+fn render(headless: &Headless, config: &Config, scene: &Scene) -> Image<[u8; 4]> {
+    let mut pipeline = rendology::Pipeline::create(headless, config, IMAGE_SIZE).unwrap();
+
+    let shadow_pass = pipeline
+        .create_shadow_pass(headless, basic_obj::Core, InstancingMode::Uniforms)
+        .unwrap();
+    let scene_pass = pipeline
+        .create_shaded_scene_pass(
+            headless,
+            basic_obj::Core,
+            InstancingMode::Uniforms,
+            ShadedScenePassSetup {
+                draw_shadowed: true,
+                draw_glowing: false,
+            },
+        )
+        .unwrap();
+    let glow_pass = pipeline
+        .create_shaded_scene_pass(
+            headless,
+            basic_obj::Core,
+            InstancingMode::Uniforms,
+            ShadedScenePassSetup {
+                draw_shadowed: true,
+                draw_glowing: true,
+            },
+        )
+        .unwrap();
+    let transparent_pass = pipeline
+        .create_transparent_scene_pass(headless, basic_obj::Core, InstancingMode::Uniforms)
+        .unwrap();
+    let line_pass = pipeline
+        .create_plain_scene_pass(headless, line::Core, InstancingMode::Uniforms)
+        .unwrap();
+
+    let mut particle_system = particle::System::create(headless, &Default::default()).unwrap();
+    let particle_pass = pipeline
+        .create_plain_scene_pass(headless, particle_system.shader(), InstancingMode::Uniforms)
+        .unwrap();
+
+    let resources = basic_obj::Resources::create(headless).unwrap();
+    let line_mesh = line::create_mesh(headless).unwrap();
+
+    let particle_time = 1.0;
+    particle_system.spawn(scene.particles.as_slice());
+    particle_system.set_current_time(particle_time);
+
+    let draw_params = glium::DrawParameters {
+        backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
+        ..Default::default()
+    };
+    let line_draw_params = glium::DrawParameters {
+        backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
+        blend: glium::draw_parameters::Blend::alpha_blending(),
+        ..Default::default()
+    };
+    let particle_draw_params = glium::DrawParameters {
+        depth: glium::Depth {
+            test: glium::DepthTest::IfLessOrEqual,
+            write: false,
+            ..Default::default()
+        },
+        blend: glium::Blend {
+            color: glium::BlendingFunction::Addition {
+                source: glium::LinearBlendingFactor::SourceAlpha,
+                destination: glium::LinearBlendingFactor::One,
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let image = headless
+        .render(IMAGE_SIZE, |target| {
+            pipeline
+                .start_frame(
+                    headless,
+                    (0.1, 0.1, 0.2),
+                    render_context(),
+                    &scene.lights,
+                    target,
+                )?
+                .shadow_pass()
+                .draw(
+                    &shadow_pass,
+                    &scene.objects.as_drawable(&resources),
+                    &(),
+                    &draw_params,
+                )?
+                .draw(
+                    &shadow_pass,
+                    &scene.glowing_objects.as_drawable(&resources),
+                    &(),
+                    &draw_params,
+                )?
+                .shaded_scene_pass()
+                .draw(
+                    &scene_pass,
+                    &scene.objects.as_drawable(&resources),
+                    &(),
+                    &draw_params,
+                )?
+                .draw(
+                    &glow_pass,
+                    &scene.glowing_objects.as_drawable(&resources),
+                    &(),
+                    &draw_params,
+                )?
+                .compose()?
+                .transparent_scene_pass()
+                .draw(
+                    &transparent_pass,
+                    &scene.transparent_objects.as_drawable(&resources),
+                    &(),
+                    &draw_params,
+                )?
+                .plain_scene_pass()?
+                .draw(
+                    &line_pass,
+                    &scene.lines.as_drawable(&line_mesh),
+                    &line::Params { feather: 0.1 },
+                    &line_draw_params,
+                )?
+                .draw(
+                    &particle_pass,
+                    &particle_system,
+                    &particle::Params {
+                        time: particle_time,
+                    },
+                    &particle_draw_params,
+                )?
+                .postprocess()?
+                .present()
+        })
+        .unwrap();
+
+    // Alpha is not meaningful in the final image.
+    let mut image = image.to_rgba8();
+    for pixel in image.pixels.iter_mut() {
+        pixel[3] = 255;
+    }
+
+    image
+}
+
+fn render_context() -> rendology::Context {
+    let camera = rendology::Camera {
+        view: na::Matrix4::look_at_rh(
+            &na::Point3::new(9.0, -5.0, 7.0),
+            &na::Point3::new(0.0, 0.0, 0.0),
+            &na::Vector3::new(0.0, 0.0, 1.0),
+        ),
+        projection: na::Perspective3::new(
+            IMAGE_SIZE.0 as f32 / IMAGE_SIZE.1 as f32,
+            60.0f32.to_radians(),
+            0.1,
+            1000.0,
+        )
+        .to_homogeneous(),
+        viewport_size: na::Vector2::new(IMAGE_SIZE.0 as f32, IMAGE_SIZE.1 as f32),
+    };
+
+    rendology::Context {
+        camera,
+        main_light_pos: na::Point3::new(10.0, 10.0, 10.0),
+        main_light_center: na::Point3::new(0.0, 0.0, 0.0),
+        ambient_light: na::Vector3::new(0.3, 0.3, 0.3),
+        previous_camera: None,
+    }
+}
+
+fn main_light() -> Light {
+    Light {
+        position: na::Point3::new(10.0, 10.0, 10.0),
+        attenuation: na::Vector4::new(1.0, 0.0, 0.0, 0.0),
+        color: na::Vector3::new(1.0, 1.0, 1.0),
+        is_main: true,
+        ..Default::default()
+    }
+}
+
+fn floor() -> basic_obj::Instance {
+    basic_obj::Instance {
+        transform: na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(12.0, 12.0, 0.1)),
+        color: na::Vector4::new(0.8, 0.8, 0.8, 1.0),
+        ..Default::default()
+    }
+}
+
+fn basic_objs_scene() -> Scene {
+    let mut scene = Scene::default();
+
+    scene.objects[BasicObj::Cube].add(floor());
+
+    let objs = [
+        BasicObj::Triangle,
+        BasicObj::Quad,
+        BasicObj::Cube,
+        BasicObj::Sphere,
+        BasicObj::LineX,
+        BasicObj::LineY,
+        BasicObj::LineZ,
+        BasicObj::TessellatedCube,
+        BasicObj::TessellatedCylinder,
+    ];
+
+    for (i, &obj) in objs.iter().enumerate() {
+        let x = (i % 3) as f32 * 2.5 - 2.5;
+        let y = (i / 3) as f32 * 2.5 - 2.5;
+
+        scene.objects[obj].add(basic_obj::Instance {
+            transform: na::Matrix4::new_translation(&na::Vector3::new(x, y, 1.0))
+                * na::Matrix4::from_euler_angles(0.3, 0.2, 0.1),
+            color: na::Vector4::new(0.9, 0.2 + 0.08 * i as f32, 0.2, 1.0),
+            ..Default::default()
+        });
+    }
+
+    scene.lights.push(main_light());
+
+    scene
+}
+
+fn lines_scene() -> Scene {
+    let mut scene = Scene::default();
+
+    scene.objects[BasicObj::Cube].add(floor());
+
+    for i in 0..5 {
+        let offset = i as f32 - 2.0;
+
+        scene.lines.add(line::Instance::from_start_end(
+            &na::Point3::new(-4.0, offset, 0.5),
+            &na::Point3::new(4.0, offset, 0.5 + i as f32),
+            &na::Vector4::new(0.2 * i as f32, 0.0, 1.0 - 0.2 * i as f32, 1.0),
+            1.0 + 2.0 * i as f32,
+        ));
+    }
+
+    scene.lights.push(main_light());
+
+    scene
+}
+
+fn particles_scene() -> Scene {
+    let mut scene = Scene::default();
+
+    scene.objects[BasicObj::Cube].add(floor());
+
+    for i in 0..200 {
+        let angle = i as f32 * 0.1;
+        let radius = 0.5 + i as f32 * 0.02;
+        let velocity = na::Vector3::new(angle.cos(), angle.sin(), 1.0) * radius;
+
+        scene.particles.add(Particle {
+            spawn_time: 0.0,
+            life_duration: 3.0,
+            start_pos: na::Point3::new(0.0, 0.0, 1.0),
+            velocity,
+            color: na::Vector3::new(1.0, radius / 4.0, 0.1),
+            size: 0.05,
+            friction: 0.5,
+        });
+    }
+
+    scene.lights.push(main_light());
+
+    scene
+}
+
+fn shadows_scene() -> Scene {
+    let mut scene = Scene::default();
+
+    scene.objects[BasicObj::Cube].add(floor());
+
+    for i in 0..4 {
+        let x = i as f32 * 2.0 - 3.0;
+
+        scene.objects[BasicObj::Cube].add(basic_obj::Instance {
+            transform: na::Matrix4::new_translation(&na::Vector3::new(x, -x, 1.0))
+                * na::Matrix4::new_nonuniform_scaling(&na::Vector3::new(0.5, 0.5, 2.0)),
+            color: na::Vector4::new(0.3, 0.4, 0.9, 1.0),
+            ..Default::default()
+        });
+    }
+
+    scene.lights.push(main_light());
+    scene.lights.push(Light {
+        position: na::Point3::new(0.0, 0.0, 1.5),
+        attenuation: na::Vector4::new(1.0, 0.0, 0.3, 0.0),
+        color: na::Vector3::new(2.0, 1.5, 0.5),
+        casts_shadow: true,
+        ..Default::default()
+    });
+
+    scene
+}
+
+fn lights_scene() -> Scene {
+    let mut scene = Scene::default();
+
+    scene.objects[BasicObj::Cube].add(floor());
+    scene.objects[BasicObj::Sphere].add(basic_obj::Instance {
+        transform: na::Matrix4::new_translation(&na::Vector3::new(0.0, 0.0, 1.0))
+            * na::Matrix4::new_scaling(2.0),
+        color: na::Vector4::new(0.9, 0.9, 0.9, 1.0),
+        ..Default::default()
+    });
+
+    let mut main_light = main_light();
+    main_light.color = na::Vector3::new(0.2, 0.2, 0.2);
+    scene.lights.push(main_light);
+
+    let colors = [
+        na::Vector3::new(4.0, 0.5, 0.5),
+        na::Vector3::new(0.5, 4.0, 0.5),
+        na::Vector3::new(0.5, 0.5, 4.0),
+    ];
+
+    for i in 0..12 {
+        let angle = i as f32 / 12.0 * std::f32::consts::PI * 2.0;
+
+        scene.lights.push(Light {
+            position: na::Point3::new(4.0 * angle.cos(), 4.0 * angle.sin(), 0.5),
+            attenuation: na::Vector4::new(1.0, 0.0, 1.0, 0.0),
+            color: colors[i % colors.len()],
+            ..Default::default()
+        });
+    }
+
+    scene.lights.push(Light {
+        position: na::Point3::new(-5.0, 0.0, 6.0),
+        attenuation: na::Vector4::new(1.0, 0.0, 0.02, 0.0),
+        color: na::Vector3::new(3.0, 3.0, 1.0),
+        kind: LightKind::Spot {
+            direction: na::Vector3::new(0.5, 0.0, -1.0),
+            inner_angle: 0.3,
+            outer_angle: 0.4,
+        },
+        ..Default::default()
+    });
+
+    scene
+}
+
+fn glow_scene() -> Scene {
+    let mut scene = Scene::default();
+
+    scene.objects[BasicObj::Cube].add(floor());
+
+    for i in 0..3 {
+        scene.glowing_objects[BasicObj::Cube].add(basic_obj::Instance {
+            transform: na::Matrix4::new_translation(&na::Vector3::new(
+                i as f32 * 2.5 - 2.5,
+                0.0,
+                1.0,
+            )),
+            color: na::Vector4::new(
+                if i == 0 { 1.0 } else { 0.1 },
+                if i == 1 { 1.0 } else { 0.1 },
+                if i == 2 { 1.0 } else { 0.1 },
+                1.0,
+            ),
+            ..Default::default()
+        });
+    }
+
+    scene.lights.push(main_light());
+
+    scene
+}
+
+fn transparent_scene() -> Scene {
+    let mut scene = Scene::default();
+
+    scene.objects[BasicObj::Cube].add(floor());
+    scene.objects[BasicObj::Cube].add(basic_obj::Instance {
+        transform: na::Matrix4::new_translation(&na::Vector3::new(-1.0, 1.0, 1.0)),
+        color: na::Vector4::new(0.9, 0.9, 0.9, 1.0),
+        ..Default::default()
+    });
+
+    let colors = [
+        na::Vector4::new(1.0, 0.1, 0.1, 0.5),
+        na::Vector4::new(0.1, 1.0, 0.1, 0.5),
+        na::Vector4::new(0.1, 0.1, 1.0, 0.8),
+    ];
+
+    for (i, color) in colors.iter().enumerate() {
+        scene.transparent_objects[BasicObj::Sphere].add(basic_obj::Instance {
+            transform: na::Matrix4::new_translation(&na::Vector3::new(
+                i as f32 * 0.8 - 0.8,
+                i as f32 * 1.4 - 1.4,
+                1.5,
+            )) * na::Matrix4::new_scaling(3.0),
+            color: *color,
+            ..Default::default()
+        });
+    }
+
+    scene.lights.push(main_light());
+
+    scene
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+/// Compares the image against the reference image of the given name.
+fn check(name: &str, image: &Image<[u8; 4]>) -> Result<(), String> {
+    let reference_path = golden_dir().join(format!("{}.png", name));
+
+    if std::env::var("GOLDEN_UPDATE").is_ok() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        image.write_png(&reference_path).unwrap();
+        return Ok(());
+    }
+
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.to_rgba(),
+        Err(err) => {
+            return Err(format!(
+                "{}: failed to load {:?} ({}), run with GOLDEN_UPDATE=1 to create it",
+                name, reference_path, err,
+            ));
+        }
+    };
+
+    if reference.dimensions() != (image.width, image.height) {
+        return Err(format!(
+            "{}: size {:?} differs from reference size {:?}",
+            name,
+            (image.width, image.height),
+            reference.dimensions(),
+        ));
+    }
+
+    let mut diff = Image {
+        width: image.width,
+        height: image.height,
+        pixels: Vec::with_capacity(image.pixels.len()),
+    };
+    let mut num_different = 0;
+
+    for (actual, expected) in image.pixels.iter().zip(reference.pixels()) {
+        if color_delta(actual, &expected.0) > PIXEL_THRESHOLD {
+            num_different += 1;
+            diff.pixels.push([255, 0, 0, 255]);
+        } else {
+            // Show the matching pixels faded, for orientation.
+            let gray = (255.0 - 0.1 * (255.0 - luma(&expected.0))) as u8;
+            diff.pixels.push([gray, gray, gray, 255]);
+        }
+    }
+
+    let fraction = num_different as f32 / image.pixels.len() as f32;
+    if fraction <= MAX_DIFFERENT_PIXELS {
+        return Ok(());
+    }
+
+    std::fs::create_dir_all(output_dir()).unwrap();
+    let actual_path = output_dir().join(format!("{}-actual.png", name));
+    let diff_path = output_dir().join(format!("{}-diff.png", name));
+    image.write_png(&actual_path).unwrap();
+    diff.write_png(&diff_path).unwrap();
+
+    Err(format!(
+        "{}: {} pixels ({:.2}%) differ, see {:?} and {:?}",
+        name,
+        num_different,
+        fraction * 100.0,
+        actual_path,
+        diff_path,
+    ))
+}
+
+fn luma(color: &[u8; 4]) -> f32 {
+    color[0] as f32 * 0.298_895_3 + color[1] as f32 * 0.586_622_5 + color[2] as f32 * 0.114_482_2
+}
+
+/// Perceptual difference between two colors, scaled to `[0, 1]`. This is the
+/// YIQ difference that is used by `pixelmatch`. For reference:
+/// http://www.progmat.uaem.mx:8080/artVol2Num2/Articulo3Vol2Num2.pdf
+fn color_delta(a: &[u8; 4], b: &[u8; 4]) -> f32 {
+    let i = |c: &[u8; 4]| {
+        c[0] as f32 * 0.595_978 - c[1] as f32 * 0.274_176_1 - c[2] as f32 * 0.321_801_9
+    };
+    let q = |c: &[u8; 4]| {
+        c[0] as f32 * 0.211_470_2 - c[1] as f32 * 0.522_617_1 + c[2] as f32 * 0.311_146_9
+    };
+
+    let dy = luma(a) - luma(b);
+    let di = i(a) - i(b);
+    let dq = q(a) - q(b);
+
+    // 35215 is the largest possible difference.
+    ((0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / 35215.0).sqrt()
+}